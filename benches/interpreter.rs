@@ -0,0 +1,81 @@
+// baselines for the interpreter's hot paths: cargo bench
+use criterion::{criterion_group, criterion_main, Criterion};
+use ps_interpreter::interpreter::file::BufferSink;
+use ps_interpreter::interpreter::interpreter::Interpreter;
+use ps_interpreter::interpreter::scanner::tokenize;
+use std::hint::black_box;
+
+// a fresh interpreter that keeps its output to itself
+fn quiet() -> Interpreter {
+    let mut interp = Interpreter::new();
+    interp.set_output(Box::new(BufferSink::new()));
+    interp
+}
+
+fn run(c: &mut Criterion, name: &str, program: &str) {
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            let mut interp = quiet();
+            interp.run(black_box(program)).unwrap();
+        })
+    });
+}
+
+// a prolog-sized file: procedure definitions, strings, arrays and numbers
+fn large_file() -> String {
+    (0..2000)
+        .map(|i| {
+            format!("/proc{i} {{ {i} 2 mul (label {i}) [1 2.5 /x] exch pop }} def % comment {i}\n")
+        })
+        .collect()
+}
+
+fn tokenization(c: &mut Criterion) {
+    let src = large_file();
+    c.bench_function("tokenize large file", |b| {
+        b.iter(|| tokenize(black_box(&src)).unwrap())
+    });
+}
+
+fn arithmetic_loop(c: &mut Criterion) {
+    run(
+        c,
+        "for loop with arithmetic",
+        "0 1 1 10000 { add 3 mul 7 mod } for pop",
+    );
+}
+
+fn recursion(c: &mut Criterion) {
+    // not a tail call, so each level nests
+    run(
+        c,
+        "deep recursion",
+        "/down { dup 0 gt { 1 sub down 1 add } if } def 500 down pop",
+    );
+}
+
+fn lookup_under_frames(c: &mut Criterion) {
+    let mut program = String::from("/x 1 def ");
+    program.push_str(&"1 dict begin ".repeat(200));
+    program.push_str("1 1 5000 { pop x pop } for");
+    run(c, "lookup under 200 dictionaries", &program);
+}
+
+fn string_building(c: &mut Criterion) {
+    // there's no string operator yet, so start from a literal of spaces
+    let program = format!(
+        "/s ({}) def 0 1 999 {{ /i exch def s i 4 mul (abcd) putinterval /s exch def }} for",
+        " ".repeat(4000)
+    );
+    run(c, "string building", &program);
+}
+
+criterion_group!(
+    benches,
+    tokenization,
+    arithmetic_loop,
+    recursion,
+    lookup_under_frames,
+    string_building
+);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+/* drives the interpreter through the C API and checks what comes back:
+ *
+ *     cargo rustc --release --lib --features capi --crate-type cdylib
+ *     cc examples/capi/roundtrip.c -Iinclude -Ltarget/release -lps_interpreter -o roundtrip
+ *     LD_LIBRARY_PATH=target/release ./roundtrip
+ */
+
+#include <stdio.h>
+#include <string.h>
+#include "ps_interpreter.h"
+
+static int failures = 0;
+
+static void expect(int ok, const char *what) {
+    if (!ok) {
+        fprintf(stderr, "FAIL: %s\n", what);
+        failures++;
+    }
+}
+
+/* pop the top of the stack and compare it with want */
+static void expect_pop(PsInterpreter *ps, const char *want) {
+    char *got = ps_interpreter_pop_string(ps);
+    expect(got != NULL && strcmp(got, want) == 0, want);
+    ps_string_free(got);
+}
+
+int main(void) {
+    PsInterpreter *ps = ps_interpreter_new();
+
+    expect(ps_interpreter_define(ps, "width", "612") == 0, "define a number");
+    expect(ps_interpreter_define(ps, "title", "(Quarterly report)") == 0, "define a string");
+    expect(ps_interpreter_define(ps, "sq", "{ dup mul }") == 0, "define a procedure");
+    expect(ps_interpreter_define(ps, "bad", "1 2") == -1, "refuse two objects");
+
+    expect(ps_interpreter_run(ps, "title width 2 idiv sq") == 0, "run");
+    expect(ps_interpreter_last_error(ps) == NULL, "no error");
+    expect_pop(ps, "93636");
+    expect_pop(ps, "Quarterly report");
+    expect(ps_interpreter_pop_string(ps) == NULL, "empty stack");
+
+    expect(ps_interpreter_run(ps, "1 (x) add") == 1, "an error");
+    const char *err = ps_interpreter_last_error(ps);
+    expect(err != NULL && strcmp(err, "typecheck") == 0, "typecheck");
+
+    ps_interpreter_free(ps);
+    if (failures == 0) {
+        printf("ok\n");
+    }
+    return failures != 0;
+}
@@ -0,0 +1,6 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ps_interpreter::interpreter::fuzz;
+
+fuzz_target!(|data: &[u8]| fuzz::run(data));
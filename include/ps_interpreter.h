@@ -0,0 +1,43 @@
+/* the C API of ps-interpreter. build the library with
+ *
+ *     cargo rustc --release --lib --features capi --crate-type cdylib
+ *
+ * (or --crate-type staticlib) and link against target/release/libps_interpreter.
+ * strings are NUL-terminated; an interpreter is not thread safe, use one per thread. */
+
+#ifndef PS_INTERPRETER_H
+#define PS_INTERPRETER_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct PsInterpreter PsInterpreter;
+
+/* a fresh interpreter, free it with ps_interpreter_free */
+PsInterpreter *ps_interpreter_new(void);
+void ps_interpreter_free(PsInterpreter *ps);
+
+/* run a program. 0 when it ran to the end, 1 when it stopped with an error (see
+ * ps_interpreter_last_error), -1 when ps or src is NULL. output goes to stdout */
+int ps_interpreter_run(PsInterpreter *ps, const char *src);
+
+/* the name of the error the last run stopped with ("typecheck", "undefined", ...), NULL if
+ * it didn't. owned by the interpreter, valid until the next run */
+const char *ps_interpreter_last_error(const PsInterpreter *ps);
+
+/* bind name to the object value reads as ("42", "(text)", "/name", "{dup mul}") in the
+ * current dictionary. 0 when it did, -1 for NULL arguments or a value that isn't exactly
+ * one object */
+int ps_interpreter_define(PsInterpreter *ps, const char *name, const char *value);
+
+/* pop the top of the operand stack as text: a string's contents, anything else the way =
+ * prints it. NULL when the stack is empty. free it with ps_string_free */
+char *ps_interpreter_pop_string(PsInterpreter *ps);
+void ps_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,357 @@
+// a jupyter kernel, so postscript can be used in notebooks: every cell runs in the same
+// interpreter, which lives as long as the kernel, and what a cell prints comes back as its
+// output. build it with `cargo build --features jupyter` and install jupyter/postscript with
+// `jupyter kernelspec install`. there is no renderer, so cells that paint show nothing
+
+use hmac::{Hmac, KeyInit, Mac};
+use ps_interpreter::interpreter::clock::{Clock, DateTime, SystemClock};
+use ps_interpreter::interpreter::config::InterpreterConfig;
+use ps_interpreter::interpreter::editor;
+use ps_interpreter::interpreter::file::BufferSink;
+use ps_interpreter::interpreter::interpreter::{Interpreter, LANGUAGE_LEVEL};
+use ps_interpreter::interpreter::lexer::TokenKind;
+use ps_interpreter::interpreter::scanner;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::error::Error;
+use zeromq::{PubSocket, RepSocket, RouterSocket, Socket, SocketRecv, SocketSend, ZmqMessage};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+// what separates the routing identities from the message proper
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or("usage: postscript-kernel CONNECTION_FILE")?;
+    let info: Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+    let endpoint = |port: &str| {
+        format!(
+            "{}://{}:{}",
+            info["transport"].as_str().unwrap_or("tcp"),
+            info["ip"].as_str().unwrap_or("127.0.0.1"),
+            info[port]
+        )
+    };
+    let mut shell = RouterSocket::new();
+    shell.bind(&endpoint("shell_port")).await?;
+    let mut control = RouterSocket::new();
+    control.bind(&endpoint("control_port")).await?;
+    // bound because clients connect to it, but programs never ask for input this way
+    let mut stdin = RouterSocket::new();
+    stdin.bind(&endpoint("stdin_port")).await?;
+    let mut iopub = PubSocket::new();
+    iopub.bind(&endpoint("iopub_port")).await?;
+    let mut heartbeat = RepSocket::new();
+    heartbeat.bind(&endpoint("hb_port")).await?;
+    // the heartbeat is echoed whatever a cell is doing
+    tokio::spawn(async move {
+        while let Ok(ping) = heartbeat.recv().await {
+            if heartbeat.send(ping).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut kernel = Kernel::new(info["key"].as_str().unwrap_or("").as_bytes(), iopub);
+    loop {
+        let (socket, frames) = tokio::select! {
+            frames = shell.recv() => (&mut shell, frames?),
+            frames = control.recv() => (&mut control, frames?),
+        };
+        let Some(request) = kernel.parse(frames) else {
+            eprintln!("postscript-kernel: dropped a message with a bad signature");
+            continue;
+        };
+        if kernel.handle(socket, &request).await? {
+            return Ok(());
+        }
+    }
+}
+
+// a message from the frontend
+struct Request {
+    ids: Vec<Vec<u8>>,
+    header: Value,
+    content: Value,
+}
+
+impl Request {
+    fn msg_type(&self) -> &str {
+        self.header["msg_type"].as_str().unwrap_or("")
+    }
+}
+
+struct Kernel {
+    // signs messages with HMAC-SHA256, no signatures when it's empty
+    key: Vec<u8>,
+    session: String,
+    iopub: PubSocket,
+    interp: Interpreter,
+    output: BufferSink,
+    // cells run so far, shown as In [n]
+    execution_count: u64,
+    sent: u64,
+}
+
+impl Kernel {
+    fn new(key: &[u8], iopub: PubSocket) -> Kernel {
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            extensions: true,
+            ..InterpreterConfig::default()
+        });
+        let output = BufferSink::new();
+        interp.set_output(Box::new(output.clone()));
+        Kernel {
+            key: key.to_vec(),
+            session: format!("postscript-{}", std::process::id()),
+            iopub,
+            interp,
+            output,
+            execution_count: 0,
+            sent: 0,
+        }
+    }
+
+    fn signature(&self, parts: &[&[u8]]) -> String {
+        if self.key.is_empty() {
+            return String::new();
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("any key length works");
+        for part in parts {
+            mac.update(part);
+        }
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    // None when the signature doesn't match
+    fn parse(&self, frames: ZmqMessage) -> Option<Request> {
+        let frames: Vec<Vec<u8>> = frames.into_vec().iter().map(|f| f.to_vec()).collect();
+        let split = frames.iter().position(|f| f == DELIMITER)?;
+        let [signature, header, parent, metadata, content] = frames.get(split + 1..split + 6)?
+        else {
+            return None;
+        };
+        if self
+            .signature(&[header, parent, metadata, content])
+            .as_bytes()
+            != signature
+        {
+            return None;
+        }
+        Some(Request {
+            ids: frames[..split].to_vec(),
+            header: serde_json::from_slice(header).ok()?,
+            content: serde_json::from_slice(content).ok()?,
+        })
+    }
+
+    // the frames of a message in answer to (or about) a request
+    fn message(
+        &mut self,
+        ids: &[Vec<u8>],
+        parent: &Request,
+        msg_type: &str,
+        content: Value,
+    ) -> ZmqMessage {
+        self.sent += 1;
+        let now = DateTime::from_unix(SystemClock.now());
+        let header = json!({
+            "msg_id": format!("{}-{}", self.session, self.sent),
+            "session": self.session,
+            "username": "kernel",
+            "date": format!("{}T{}Z", now.date(), now.time()),
+            "msg_type": msg_type,
+            "version": "5.3",
+        });
+        let parts: Vec<Vec<u8>> = [header, parent.header.clone(), json!({}), content]
+            .iter()
+            .map(|v| v.to_string().into_bytes())
+            .collect();
+        let signature = self.signature(&parts.iter().map(Vec::as_slice).collect::<Vec<_>>());
+        let mut frames = ids.to_vec();
+        frames.push(DELIMITER.to_vec());
+        frames.push(signature.into_bytes());
+        frames.extend(parts);
+        let mut frames = frames.into_iter();
+        let mut message = ZmqMessage::from(frames.next().unwrap());
+        for frame in frames {
+            message.push_back(frame.into());
+        }
+        message
+    }
+
+    async fn publish(&mut self, parent: &Request, msg_type: &str, content: Value) -> Result<()> {
+        let frames = self.message(&[msg_type.as_bytes().to_vec()], parent, msg_type, content);
+        self.iopub.send(frames).await?;
+        Ok(())
+    }
+
+    // answer a request on the socket it came in on, with busy and idle around it. true once
+    // the kernel should shut down
+    async fn handle(&mut self, socket: &mut RouterSocket, request: &Request) -> Result<bool> {
+        self.publish(request, "status", json!({ "execution_state": "busy" }))
+            .await?;
+        let (reply, content) = match request.msg_type() {
+            "kernel_info_request" => ("kernel_info_reply", kernel_info()),
+            "execute_request" => ("execute_reply", self.execute(request).await?),
+            "is_complete_request" => {
+                let code = request.content["code"].as_str().unwrap_or("");
+                let status = if scanner::needs_more_input(code) {
+                    json!({ "status": "incomplete", "indent": "" })
+                } else {
+                    json!({ "status": "complete" })
+                };
+                ("is_complete_reply", status)
+            }
+            "complete_request" => ("complete_reply", self.complete(&request.content)),
+            "inspect_request" => ("inspect_reply", inspect(&request.content)),
+            "comm_info_request" => ("comm_info_reply", json!({ "status": "ok", "comms": {} })),
+            "history_request" => ("history_reply", json!({ "status": "ok", "history": [] })),
+            // cells run to the end, there's nothing to interrupt them with
+            "interrupt_request" => ("interrupt_reply", json!({ "status": "ok" })),
+            "shutdown_request" => {
+                let restart = request.content["restart"].as_bool().unwrap_or(false);
+                let content = json!({ "status": "ok", "restart": restart });
+                let frames = self.message(&request.ids, request, "shutdown_reply", content);
+                socket.send(frames).await?;
+                return Ok(true);
+            }
+            other => {
+                eprintln!("postscript-kernel: ignoring {}", other);
+                self.publish(request, "status", json!({ "execution_state": "idle" }))
+                    .await?;
+                return Ok(false);
+            }
+        };
+        let frames = self.message(&request.ids, request, reply, content);
+        socket.send(frames).await?;
+        self.publish(request, "status", json!({ "execution_state": "idle" }))
+            .await?;
+        Ok(false)
+    }
+
+    // run a cell, publishing what it prints and any error it stops with
+    async fn execute(&mut self, request: &Request) -> Result<Value> {
+        let code = request.content["code"].as_str().unwrap_or("").to_string();
+        if !request.content["silent"].as_bool().unwrap_or(false) {
+            self.execution_count += 1;
+        }
+        let count = self.execution_count;
+        self.publish(
+            request,
+            "execute_input",
+            json!({ "code": code, "execution_count": count }),
+        )
+        .await?;
+        let result = self.interp.run(&code);
+        for (name, text) in [
+            ("stdout", self.output.take_stdout()),
+            ("stderr", self.output.take_stderr()),
+        ] {
+            if !text.is_empty() {
+                self.publish(request, "stream", json!({ "name": name, "text": text }))
+                    .await?;
+            }
+        }
+        let err = match result {
+            Ok(()) => {
+                return Ok(json!({
+                    "status": "ok",
+                    "execution_count": count,
+                    "user_expressions": {},
+                    "payload": [],
+                }))
+            }
+            Err(err) => err,
+        };
+        let error = json!({
+            "ename": err.name,
+            "evalue": err.summary(),
+            "traceback": [err.summary(), err.stacks()],
+        });
+        // a handleerror in the program has printed it already
+        if !err.handled {
+            self.publish(request, "error", error.clone()).await?;
+        }
+        let mut reply = json!({ "status": "error", "execution_count": count });
+        reply
+            .as_object_mut()
+            .unwrap()
+            .extend(error.as_object().unwrap().clone());
+        Ok(reply)
+    }
+
+    // names for the one being typed: what the cell defines, and what earlier cells did
+    fn complete(&self, content: &Value) -> Value {
+        let code = content["code"].as_str().unwrap_or("");
+        let at = byte_offset(code, &content["cursor_pos"]);
+        // what's been typed of the name gets replaced, the slash of a literal name stays
+        let start = editor::token_at(code, at)
+            .filter(|t| matches!(t.kind, TokenKind::Name | TokenKind::LiteralName))
+            .map_or(at, |t| t.span.start + t.text.starts_with('/') as usize);
+        let typed = &code[start..at];
+        let mut names: Vec<String> = editor::completions(code, at)
+            .into_iter()
+            .map(|known| known.name)
+            .collect();
+        for known in self.interp.known_names() {
+            if known.name.starts_with(typed) && !names.contains(&known.name) {
+                names.push(known.name);
+            }
+        }
+        json!({
+            "status": "ok",
+            "matches": names,
+            "cursor_start": code[..start].chars().count(),
+            "cursor_end": code[..at].chars().count(),
+            "metadata": {},
+        })
+    }
+}
+
+fn kernel_info() -> Value {
+    json!({
+        "status": "ok",
+        "protocol_version": "5.3",
+        "implementation": "ps-interpreter",
+        "implementation_version": env!("CARGO_PKG_VERSION"),
+        "language_info": {
+            "name": "postscript",
+            "version": LANGUAGE_LEVEL.to_string(),
+            "mimetype": "application/postscript",
+            "file_extension": ".ps",
+            "pygments_lexer": "postscript",
+        },
+        "banner": "PostScript (ps-interpreter)",
+        "help_links": [],
+    })
+}
+
+// byte offset of a cursor the protocol gives in characters
+fn byte_offset(code: &str, cursor: &Value) -> usize {
+    let chars = cursor.as_u64().unwrap_or(0) as usize;
+    code.char_indices()
+        .nth(chars)
+        .map_or(code.len(), |(i, _)| i)
+}
+
+fn inspect(content: &Value) -> Value {
+    let code = content["code"].as_str().unwrap_or("");
+    let at = byte_offset(code, &content["cursor_pos"]);
+    match editor::hover(code, at) {
+        Some(doc) => json!({
+            "status": "ok",
+            "found": true,
+            "data": { "text/plain": doc },
+            "metadata": {},
+        }),
+        None => json!({ "status": "ok", "found": false, "data": {}, "metadata": {} }),
+    }
+}
@@ -0,0 +1,211 @@
+// a language server for postscript over stdio: diagnostics from check and lint, hover docs
+// from the operator table and completion of operator and defined names. build it with
+// `cargo build --features lsp`
+
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{Completion, HoverRequest, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, Diagnostic,
+    DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, Hover, HoverContents, HoverParams, HoverProviderCapability,
+    MarkupContent, MarkupKind, Position, PublishDiagnosticsParams, Range, ServerCapabilities,
+    TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+use ps_interpreter::interpreter::{check, editor, lint};
+use std::collections::HashMap;
+use std::error::Error;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+fn main() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        // the whole text on every change, programs are small
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["/".into()]),
+            ..CompletionOptions::default()
+        }),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    // the writer thread finishes once the connection is gone
+    serve(connection)?;
+    io_threads.join()?;
+    Ok(())
+}
+
+fn serve(connection: Connection) -> Result<()> {
+    // open documents by uri
+    let mut documents: HashMap<String, String> = HashMap::new();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = answer(&documents, request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(note) => {
+                if let Some((uri, text)) = update(&mut documents, note)? {
+                    let params = PublishDiagnosticsParams {
+                        diagnostics: text.map_or_else(Vec::new, |text| diagnostics(&text)),
+                        uri,
+                        version: None,
+                    };
+                    let note = Notification::new(PublishDiagnostics::METHOD.into(), params);
+                    connection.sender.send(Message::Notification(note))?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+// keep track of the documents the editor has open. gives back the document that changed and
+// its text now, None once it's closed
+fn update(
+    documents: &mut HashMap<String, String>,
+    note: Notification,
+) -> Result<Option<(Uri, Option<String>)>> {
+    let changed = match note.method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let params: DidOpenTextDocumentParams = serde_json::from_value(note.params)?;
+            let doc = params.text_document;
+            (doc.uri, Some(doc.text))
+        }
+        DidChangeTextDocument::METHOD => {
+            let params: DidChangeTextDocumentParams = serde_json::from_value(note.params)?;
+            let Some(change) = params.content_changes.into_iter().last() else {
+                return Ok(None);
+            };
+            (params.text_document.uri, Some(change.text))
+        }
+        DidCloseTextDocument::METHOD => {
+            let params: DidCloseTextDocumentParams = serde_json::from_value(note.params)?;
+            (params.text_document.uri, None)
+        }
+        _ => return Ok(None),
+    };
+    match &changed.1 {
+        Some(text) => documents.insert(changed.0.to_string(), text.clone()),
+        None => documents.remove(changed.0.as_str()),
+    };
+    Ok(Some(changed))
+}
+
+fn answer(documents: &HashMap<String, String>, request: Request) -> Response {
+    let result = match request.method.as_str() {
+        HoverRequest::METHOD => serde_json::from_value::<HoverParams>(request.params)
+            .map(|p| hover(documents, p.text_document_position_params))
+            .and_then(serde_json::to_value),
+        Completion::METHOD => serde_json::from_value::<CompletionParams>(request.params)
+            .map(|p| completion(documents, p.text_document_position))
+            .and_then(serde_json::to_value),
+        _ => {
+            return Response::new_err(
+                request.id,
+                lsp_server::ErrorCode::MethodNotFound as i32,
+                format!("no {}", request.method),
+            )
+        }
+    };
+    match result {
+        Ok(value) => Response::new_ok(request.id, value),
+        Err(e) => Response::new_err(
+            request.id,
+            lsp_server::ErrorCode::InvalidParams as i32,
+            e.to_string(),
+        ),
+    }
+}
+
+// the document and byte offset a request is about
+fn locate<'a>(
+    documents: &'a HashMap<String, String>,
+    at: &TextDocumentPositionParams,
+) -> Option<(&'a str, usize)> {
+    let text = documents.get(at.text_document.uri.as_str())?;
+    let pos = at.position;
+    Some((
+        text,
+        editor::offset(text, pos.line as usize, pos.character as usize),
+    ))
+}
+
+fn hover(documents: &HashMap<String, String>, at: TextDocumentPositionParams) -> Option<Hover> {
+    let (text, offset) = locate(documents, &at)?;
+    let doc = editor::hover(text, offset)?;
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::PlainText,
+            value: doc,
+        }),
+        range: None,
+    })
+}
+
+fn completion(
+    documents: &HashMap<String, String>,
+    at: TextDocumentPositionParams,
+) -> Option<Vec<CompletionItem>> {
+    let (text, offset) = locate(documents, &at)?;
+    let items = editor::completions(text, offset)
+        .into_iter()
+        .map(|known| CompletionItem {
+            kind: Some(match known.operator {
+                Some(_) => CompletionItemKind::FUNCTION,
+                None => CompletionItemKind::VARIABLE,
+            }),
+            detail: known.operator.map(|op| op.usage.to_string()),
+            label: known.name,
+            ..CompletionItem::default()
+        })
+        .collect();
+    Some(items)
+}
+
+// check's problems are errors, the program won't scan. lint's are only warnings. columns
+// are counted in characters, which is what editors mean for anything but astral text
+fn diagnostics(text: &str) -> Vec<Diagnostic> {
+    let errors = check::check(text);
+    let severity = if errors.is_empty() {
+        DiagnosticSeverity::WARNING
+    } else {
+        DiagnosticSeverity::ERROR
+    };
+    let found = if errors.is_empty() {
+        lint::lint(text)
+    } else {
+        errors
+    };
+    found
+        .into_iter()
+        .map(|problem| {
+            let line = problem.line as u32 - 1;
+            let start = problem.column as u32 - 1;
+            // the whole token it's about, when there is one
+            let at = editor::offset(text, line as usize, start as usize);
+            let width = editor::token_at(text, at)
+                .filter(|t| t.span.start == at)
+                .map_or(1, |t| t.text.chars().count() as u32);
+            Diagnostic {
+                range: Range::new(
+                    Position::new(line, start),
+                    Position::new(line, start + width),
+                ),
+                severity: Some(severity),
+                source: Some("ps-interpreter".into()),
+                message: problem.message,
+                ..Diagnostic::default()
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,150 @@
+// an http service that runs postscript for whoever asks: POST a program to /run and get
+// back what it printed, the error it stopped with and the operand stack, as JSON. every
+// request gets an interpreter of its own with no filesystem or environment, so programs
+// can't see each other, and limits on steps and VM, so a program that loops or holds on
+// to too much gets timeout or VMerror back. VM is counted roughly, by what composites and
+// closures' frames hold, not by what the process takes. there is no renderer, so there
+// are no pages to send back. build it with `cargo build --features serve`
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::{Json, Router};
+use ps_interpreter::interpreter::config::{InterpreterConfig, SecurityPolicy};
+use ps_interpreter::interpreter::file::BufferSink;
+use ps_interpreter::interpreter::interpreter::Interpreter;
+use serde_json::{json, Value};
+use std::error::Error;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+const USAGE: &str = "usage: postscript-serve [--listen ADDR] [--max-steps N] [--max-vm BYTES]";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (listen, config) = match parse(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let app = Router::new().route("/run", post(run)).with_state(config);
+    let listener = tokio::net::TcpListener::bind(&listen).await?;
+    eprintln!("postscript-serve: listening on {}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+// where to listen and the config every request runs with
+fn parse(args: &[String]) -> std::result::Result<(String, InterpreterConfig), String> {
+    let mut listen = "127.0.0.1:8080".to_string();
+    let mut config = InterpreterConfig {
+        security: SecurityPolicy::NoFilesystem,
+        max_steps: Some(10_000_000),
+        max_vm: Some(64 << 20),
+        extensions: true,
+        ..InterpreterConfig::default()
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        let mut number = || {
+            let n = value()?;
+            n.parse()
+                .map_err(|_| format!("{} needs a number, not {}", arg, n))
+        };
+        match arg.as_str() {
+            "--listen" => listen = value()?.clone(),
+            "--max-steps" => config.max_steps = Some(number()? as u64),
+            "--max-vm" => config.max_vm = Some(number()?),
+            other => return Err(format!("unknown option {}", other)),
+        }
+    }
+    Ok((listen, config))
+}
+
+// the body is the program. its errors are part of the answer, only a panic in the
+// interpreter makes the request itself fail
+async fn run(
+    State(config): State<InterpreterConfig>,
+    program: Bytes,
+) -> std::result::Result<Json<Value>, StatusCode> {
+    tokio::task::spawn_blocking(move || execute(config, &program))
+        .await
+        .map(Json)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn execute(config: InterpreterConfig, program: &[u8]) -> Value {
+    let mut interp = Interpreter::with_config(config);
+    let output = BufferSink::new();
+    interp.set_output(Box::new(output.clone()));
+    let error = match interp.run_bytes(program) {
+        Ok(()) => Value::Null,
+        Err(e) => json!({
+            "name": e.name,
+            "command": e.command,
+            "position": e.position,
+            "message": e.summary(),
+            // the operands when it happened, the stack below is what's left after
+            "stack": e.stack,
+        }),
+    };
+    json!({
+        "output": output.take_stdout(),
+        "errors": output.take_stderr(),
+        "error": error,
+        "stack": interp.stack(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn error(program: &str) -> Value {
+        let (_, config) = parse(&[]).unwrap();
+        execute(config, program.as_bytes())["error"]["name"].clone()
+    }
+
+    // each of these used to abort the whole process, and the server with it
+    #[test]
+    fn limits_hold_under_the_default_config() {
+        assert_eq!(error("2000000000 dict"), "VMerror");
+        assert_eq!(error("1 20000 {1 packedarray} repeat"), "limitcheck");
+        assert_eq!(error("1 2000000000 ndup"), "VMerror");
+        assert_eq!(error("1 30000000 ndup"), "VMerror");
+        // dup's copies come apart when they're popped into a packed array
+        let dups = format!(
+            "({}) 99999 {{dup}} repeat 100000 packedarray",
+            "x".repeat(1000)
+        );
+        assert_eq!(error(&dups), "VMerror");
+        assert_eq!(error("1 2 add"), Value::Null);
+    }
+
+    // closures defined into the frame that made them used to leak one frame per call, under
+    // every limit but the step count
+    #[test]
+    fn lexical_closure_loops_stay_within_max_vm() {
+        let (_, config) = parse(&[]).unwrap();
+        let program = "lexical /g { /h { 1 } def } def 200000 { g } repeat \
+                       0 vmreclaim vmstatus pop exch pop =";
+        let answer = execute(config.clone(), program.as_bytes());
+        assert_eq!(answer["error"], Value::Null);
+        let used: usize = answer["output"].as_str().unwrap().trim().parse().unwrap();
+        assert!(used < 1024, "{} bytes still in use", used);
+        // and the frames closures that are still around keep count against max_vm
+        let config = InterpreterConfig {
+            max_vm: Some(1 << 20),
+            ..config
+        };
+        let program =
+            "lexical /g { /h (a string in the frame) def { h } } def 1000000 { g } repeat";
+        let answer = execute(config, program.as_bytes());
+        assert_eq!(answer["error"]["name"], "VMerror");
+    }
+}
@@ -0,0 +1,154 @@
+// the functions declared in include/ps_interpreter.h, so hosts that aren't rust (C and C++
+// print pipelines, python through ctypes) can embed the interpreter. every pointer comes
+// from C, the header says what each one has to point at
+#![allow(clippy::missing_safety_doc)]
+
+use crate::interpreter::interpreter::Interpreter;
+use crate::interpreter::object::PSObject;
+use crate::interpreter::scanner::Scanner;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+
+// opaque to C
+pub struct PsInterpreter {
+    interp: Interpreter,
+    // the name of the error the last run stopped with
+    error: Option<CString>,
+}
+
+#[no_mangle]
+pub extern "C" fn ps_interpreter_new() -> *mut PsInterpreter {
+    Box::into_raw(Box::new(PsInterpreter {
+        interp: Interpreter::new(),
+        error: None,
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ps_interpreter_free(ps: *mut PsInterpreter) {
+    if !ps.is_null() {
+        drop(Box::from_raw(ps));
+    }
+}
+
+// 0 when the program ran to the end, 1 when it stopped with an error, -1 for a null pointer
+#[no_mangle]
+pub unsafe extern "C" fn ps_interpreter_run(ps: *mut PsInterpreter, src: *const c_char) -> c_int {
+    let Some(ps) = ps.as_mut().filter(|_| !src.is_null()) else {
+        return -1;
+    };
+    match ps.interp.run_bytes(CStr::from_ptr(src).to_bytes()) {
+        Ok(()) => {
+            ps.error = None;
+            0
+        }
+        Err(e) => {
+            ps.error = CString::new(e.name).ok();
+            1
+        }
+    }
+}
+
+// the error name (typecheck, undefined, ...) the last run stopped with, NULL if it didn't.
+// it belongs to the interpreter and lasts until the next run
+#[no_mangle]
+pub unsafe extern "C" fn ps_interpreter_last_error(ps: *const PsInterpreter) -> *const c_char {
+    ps.as_ref()
+        .and_then(|ps| ps.error.as_ref())
+        .map_or(ptr::null(), |e| e.as_ptr())
+}
+
+// bind name to the object value reads as (42, (text), /name, {dup mul}) in the current
+// dictionary. 0 when it did, -1 for a null pointer or a value that isn't one object
+#[no_mangle]
+pub unsafe extern "C" fn ps_interpreter_define(
+    ps: *mut PsInterpreter,
+    name: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    let Some(ps) = ps.as_mut().filter(|_| !name.is_null() && !value.is_null()) else {
+        return -1;
+    };
+    let Ok(name) = CStr::from_ptr(name).to_str() else {
+        return -1;
+    };
+    let mut objects = Scanner::from_bytes(CStr::from_ptr(value).to_bytes());
+    match (objects.next(), objects.next()) {
+        (Some(Ok(obj)), None) => {
+            ps.interp.define(name, obj);
+            0
+        }
+        _ => -1,
+    }
+}
+
+// pop the top of the operand stack as text: a string's contents, anything else the way =
+// prints it. NULL when the stack is empty. free it with ps_string_free
+#[no_mangle]
+pub unsafe extern "C" fn ps_interpreter_pop_string(ps: *mut PsInterpreter) -> *mut c_char {
+    let Some(obj) = ps.as_mut().and_then(|ps| ps.interp.pop()) else {
+        return ptr::null_mut();
+    };
+    let mut bytes = match obj {
+        PSObject::String(s, _) => s,
+        PSObject::Name(n, _) => n.into_bytes(),
+        other => other.to_string().into_bytes(),
+    };
+    // a NUL inside would cut it short in C anyway
+    bytes.truncate(bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len()));
+    CString::new(bytes).unwrap().into_raw()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ps_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    unsafe fn pop(ps: *mut PsInterpreter) -> Option<String> {
+        let s = ps_interpreter_pop_string(ps);
+        if s.is_null() {
+            return None;
+        }
+        let text = CStr::from_ptr(s).to_str().unwrap().to_string();
+        ps_string_free(s);
+        Some(text)
+    }
+
+    #[test]
+    fn round_trip() {
+        unsafe {
+            let ps = ps_interpreter_new();
+            assert_eq!(
+                ps_interpreter_define(ps, c"width".as_ptr(), c"612".as_ptr()),
+                0
+            );
+            assert_eq!(
+                ps_interpreter_define(ps, c"sq".as_ptr(), c"{dup mul}".as_ptr()),
+                0
+            );
+            assert_eq!(
+                ps_interpreter_define(ps, c"x".as_ptr(), c"1 2".as_ptr()),
+                -1
+            );
+            let src = c"(job) width 2 idiv sq /name";
+            assert_eq!(ps_interpreter_run(ps, src.as_ptr()), 0);
+            assert!(ps_interpreter_last_error(ps).is_null());
+            assert_eq!(pop(ps).as_deref(), Some("name"));
+            assert_eq!(pop(ps).as_deref(), Some("93636"));
+            assert_eq!(pop(ps).as_deref(), Some("job"));
+            assert_eq!(pop(ps), None);
+
+            assert_eq!(ps_interpreter_run(ps, c"1 (x) add".as_ptr()), 1);
+            let err = CStr::from_ptr(ps_interpreter_last_error(ps));
+            assert_eq!(err.to_str(), Ok("typecheck"));
+            assert_eq!(ps_interpreter_run(ptr::null_mut(), c"1".as_ptr()), -1);
+            ps_interpreter_free(ps);
+        }
+    }
+}
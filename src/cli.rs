@@ -0,0 +1,244 @@
+use crate::style::ColorChoice;
+use ps_interpreter::interpreter::dsc::PageRanges;
+
+pub const USAGE: &str =
+    "usage: ps-interpreter [-i] [--explain] [--deterministic] [--undo N] [--real-digits N] [--color WHEN] [--error-format text|json] [--pages RANGE] [--skip-prolog-errors] [--jobs N] [-e CODE | FILE | -]... [-- ARG...]
+       ps-interpreter --check [--error-format text|json] | --dump-tokens | --highlight ansi|html [-e CODE | FILE | -]...
+       ps-interpreter fmt | lint [FILE | -]...
+       ps-interpreter --eps-info FILE";
+
+// one piece of postscript to run, in the order given on the command line
+#[derive(Debug, PartialEq)]
+pub enum Source {
+    File(String),
+    // -e "3 4 add ="
+    Expr(String),
+    // - reads the whole of stdin
+    Stdin,
+}
+
+// what to do with the sources
+#[derive(Debug, Default, PartialEq)]
+pub enum Mode {
+    #[default]
+    Run,
+    // --check: report syntax problems
+    Check,
+    // --dump-tokens: print what the scanner makes of them
+    DumpTokens,
+    // fmt: print them reformatted
+    Fmt,
+    // lint: warn about likely mistakes
+    Lint,
+    // --highlight ansi|html: print them syntax highlighted
+    Highlight(Highlight),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Highlight {
+    Ansi,
+    Html,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub mode: Mode,
+    pub sources: Vec<Source>,
+    // only run these pages of each file (plus prolog and trailer)
+    pub pages: Option<PageRanges>,
+    // --skip-prolog-errors: an error in a DSC file's prolog skips what failed instead of
+    // stopping the file
+    pub skip_prolog_errors: bool,
+    // --jobs N: run the pages of DSC files on up to N threads, each with its own interpreter.
+    // a failing page stops new ones starting, but up to N - 1 after it may already have run
+    pub jobs: usize,
+    pub eps_info: Option<String>,
+    // -i: go to the repl after the sources, even when stdin isn't a terminal
+    pub interactive: bool,
+    // --explain: teaching mode, every step explained on stderr
+    pub explain: bool,
+    // --deterministic: the same output for the same input on every run
+    pub deterministic: bool,
+    // --undo N: how many lines the repl's :undo can take back, 0 to keep no snapshots
+    pub undo_limit: Option<usize>,
+    // --real-digits N: significant digits reals print with, like Ghostscript's 6
+    pub real_digits: Option<usize>,
+    pub color: ColorChoice,
+    // --error-format json: errors as lines of JSON rather than text, for tools
+    pub json_errors: bool,
+    // everything after --, for the program to read from ARGUMENTS
+    pub arguments: Vec<String>,
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Options, String> {
+        let mut opts = Options::default();
+        // subcommands come first
+        let args = match args.first().map(String::as_str) {
+            Some("fmt") => {
+                opts.mode = Mode::Fmt;
+                &args[1..]
+            }
+            Some("lint") => {
+                opts.mode = Mode::Lint;
+                &args[1..]
+            }
+            _ => args,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "-e" => opts.sources.push(Source::Expr(value()?.clone())),
+                "--pages" => opts.pages = Some(PageRanges::parse(value()?)?),
+                "--skip-prolog-errors" => opts.skip_prolog_errors = true,
+                "--eps-info" => opts.eps_info = Some(value()?.clone()),
+                "-i" => opts.interactive = true,
+                "--explain" => opts.explain = true,
+                "--deterministic" => opts.deterministic = true,
+                "--jobs" => {
+                    let n = value()?;
+                    opts.jobs = n
+                        .parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or(format!("--jobs needs a number of threads, not {}", n))?;
+                }
+                "--undo" => {
+                    let n = value()?;
+                    opts.undo_limit = Some(
+                        n.parse()
+                            .map_err(|_| format!("--undo needs a number, not {}", n))?,
+                    );
+                }
+                "--real-digits" => {
+                    let n = value()?;
+                    opts.real_digits = Some(
+                        n.parse()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .ok_or(format!("--real-digits needs a number of digits, not {}", n))?,
+                    );
+                }
+                "--check" => opts.mode = Mode::Check,
+                "--dump-tokens" => opts.mode = Mode::DumpTokens,
+                "--highlight" => {
+                    opts.mode = Mode::Highlight(match value()?.as_str() {
+                        "ansi" => Highlight::Ansi,
+                        "html" => Highlight::Html,
+                        other => {
+                            return Err(format!("--highlight takes ansi or html, not {}", other))
+                        }
+                    })
+                }
+                "--color" => opts.color = ColorChoice::parse(value()?)?,
+                "--error-format" => {
+                    opts.json_errors = match value()?.as_str() {
+                        "text" => false,
+                        "json" => true,
+                        other => {
+                            return Err(format!("--error-format takes text or json, not {}", other))
+                        }
+                    }
+                }
+                "-" => opts.sources.push(Source::Stdin),
+                "-h" | "--help" => return Err(USAGE.into()),
+                "--" => {
+                    opts.arguments = args.cloned().collect();
+                    break;
+                }
+                flag if flag.starts_with('-') => {
+                    return Err(format!("unknown option {}\n{}", flag, USAGE))
+                }
+                path => opts.sources.push(Source::File(path.into())),
+            }
+        }
+        // pages are found by the DSC comments of a file, -e code and stdin don't have any
+        let files = |s: &Source| matches!(s, Source::File(_));
+        if opts.pages.is_some() && (opts.sources.is_empty() || !opts.sources.iter().all(files)) {
+            return Err("--pages only works on files".into());
+        }
+        Ok(opts)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn sources_keep_their_order() {
+        let opts = parse(&["a.ps", "-e", "3 4 add =", "-", "b.ps"]).unwrap();
+        assert_eq!(
+            opts.sources,
+            vec![
+                Source::File("a.ps".into()),
+                Source::Expr("3 4 add =".into()),
+                Source::Stdin,
+                Source::File("b.ps".into()),
+            ]
+        );
+        assert!(!opts.interactive);
+        assert!(!opts.explain);
+        assert_eq!(opts.color, ColorChoice::Auto);
+        assert_eq!(opts.mode, Mode::Run);
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(parse(&["--check", "a.ps"]).unwrap().mode, Mode::Check);
+        assert_eq!(
+            parse(&["--highlight", "html"]).unwrap().mode,
+            Mode::Highlight(Highlight::Html)
+        );
+        assert!(parse(&["--highlight", "svg"]).is_err());
+        let opts = parse(&["fmt", "a.ps"]).unwrap();
+        assert_eq!(opts.mode, Mode::Fmt);
+        assert_eq!(opts.sources, vec![Source::File("a.ps".into())]);
+        // only as the first argument
+        assert_eq!(parse(&["a.ps", "fmt"]).unwrap().sources.len(), 2);
+        assert!(parse(&["--explain", "-i"]).unwrap().explain);
+        assert!(parse(&["--deterministic"]).unwrap().deterministic);
+        assert_eq!(parse(&["--undo", "5"]).unwrap().undo_limit, Some(5));
+        assert_eq!(parse(&["--real-digits", "6"]).unwrap().real_digits, Some(6));
+        assert_eq!(parse(&["--jobs", "4", "a.ps"]).unwrap().jobs, 4);
+    }
+
+    #[test]
+    fn arguments_after_the_double_dash() {
+        let opts = parse(&["a.ps", "--", "-e", "x.dat"]).unwrap();
+        assert_eq!(opts.sources, vec![Source::File("a.ps".into())]);
+        assert_eq!(opts.arguments, vec!["-e", "x.dat"]);
+    }
+
+    #[test]
+    fn bad_arguments() {
+        assert!(parse(&["-e"]).is_err());
+        assert!(parse(&["--frobnicate"]).is_err());
+        assert!(parse(&["--pages", "x", "a.ps"]).is_err());
+        assert!(parse(&["--pages", "2", "a.ps"]).is_ok());
+        assert!(parse(&["--pages", "2", "-e", "1"]).is_err());
+        assert!(parse(&["--pages", "2", "a.ps", "-"]).is_err());
+        assert!(parse(&["--pages", "2"]).is_err());
+        assert!(
+            parse(&["--skip-prolog-errors", "a.ps"])
+                .unwrap()
+                .skip_prolog_errors
+        );
+        assert!(parse(&["--color", "sometimes"]).is_err());
+        assert!(parse(&["--undo", "lots"]).is_err());
+        assert!(parse(&["--real-digits", "0"]).is_err());
+        assert!(parse(&["--jobs", "0"]).is_err());
+        assert!(
+            parse(&["--error-format", "json", "a.ps"])
+                .unwrap()
+                .json_errors
+        );
+        assert!(parse(&["--error-format", "xml"]).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use ps_interpreter::interpreter::object::PSObject;
+use ps_interpreter::interpreter::scanner::{line_col, Scanner};
+
+// token text longer than this is cut short in the listing
+const MAX_TEXT: usize = 40;
+
+fn kind(obj: &PSObject) -> &'static str {
+    match obj {
+        PSObject::Int(_) => "integer",
+        PSObject::Real(_) => "real",
+        PSObject::Bool(_) => "boolean",
+        PSObject::String(..) => "string",
+        PSObject::Name(_, false) => "literal name",
+        PSObject::Name(_, true) => "name",
+        PSObject::Array(_) => "array",
+        PSObject::PackedArray(_) => "packed array",
+        PSObject::Procedure { .. } => "procedure",
+        PSObject::Dict(_) => "dict",
+        PSObject::File(_) => "file",
+        PSObject::Operator(_) => "operator",
+    }
+}
+
+// the source of a token on one line, whitespace squeezed and long ones shortened
+fn excerpt(src: &[u8]) -> String {
+    let text = String::from_utf8_lossy(src);
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > MAX_TEXT {
+        let cut: String = text.chars().take(MAX_TEXT - 3).collect();
+        format!("{}...", cut)
+    } else {
+        text
+    }
+}
+
+// the objects inside procedures and arrays, one per line and indented by depth
+fn tree(obj: &PSObject, depth: usize, out: &mut Vec<String>) {
+    let children = match obj {
+        PSObject::Array(items)
+        | PSObject::PackedArray(items)
+        | PSObject::Procedure { code: items, .. } => items,
+        _ => return,
+    };
+    for child in children {
+        let text = match child {
+            PSObject::Array(_) | PSObject::PackedArray(_) | PSObject::Procedure { .. } => {
+                String::new()
+            }
+            _ => child.to_string(),
+        };
+        let line = format!(
+            "{:15}{}{:<13} {}",
+            "",
+            "  ".repeat(depth),
+            kind(child),
+            text
+        );
+        out.push(line.trim_end().to_string());
+        tree(child, depth + 1, out);
+    }
+}
+
+// --dump-tokens: every top level token with its span, kind and text, then what it parsed to
+pub fn dump_tokens(src: &[u8]) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut scanner = Scanner::from_bytes(src);
+    while let Some((start, obj)) = scanner.next_with_position() {
+        // the token ends before the whitespace the scanner swallowed after it
+        let mut end = scanner.position();
+        while end > start && src[end - 1].is_ascii_whitespace() {
+            end -= 1;
+        }
+        let (l1, c1) = line_col(src, start);
+        let (l2, c2) = line_col(src, end);
+        let span = format!("{}:{}-{}:{}", l1, c1, l2, c2);
+        match obj {
+            Ok(obj) => {
+                let text = excerpt(&src[start..end]);
+                out.push(format!("{:<14} {:<13} {}", span, kind(&obj), text));
+                tree(&obj, 0, &mut out);
+            }
+            Err(e) => out.push(format!("{:<14} {:<13} {}", span, "error", e)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tokens_with_spans_and_trees() {
+        let lines = dump_tokens(b"/sq {dup [1]}\n(hi)");
+        assert_eq!(
+            lines,
+            vec![
+                "1:1-1:4        literal name  /sq",
+                "1:5-1:14       procedure     {dup [1]}",
+                "               name          dup",
+                "               array",
+                "                 integer       1",
+                "2:1-2:5        string        (hi)",
+            ]
+        );
+    }
+}
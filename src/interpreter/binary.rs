@@ -0,0 +1,267 @@
+use crate::interpreter::object::PSObject;
+
+// level 2 binary encoding: bytes 128-159 start a binary token instead of text
+pub fn is_binary_token(byte: u8) -> bool {
+    (128..=159).contains(&byte)
+}
+
+// little cursor over the token bytes that knows which byte order we're reading in
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    low_first: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let b = self
+            .bytes
+            .get(self.pos..self.pos + n)
+            .ok_or("syntaxerror")?;
+        self.pos += n;
+        Ok(b)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.take(2)?;
+        Ok(if self.low_first {
+            u16::from_le_bytes([b[0], b[1]])
+        } else {
+            u16::from_be_bytes([b[0], b[1]])
+        })
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        let b = [b[0], b[1], b[2], b[3]];
+        Ok(if self.low_first {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+}
+
+// decode the binary token at the start of bytes, giving back the objects it stands for and how
+// many bytes it used. a binary object sequence stands for the elements of its top level array,
+// which get executed one after another just like the same tokens written out as text
+pub fn decode(bytes: &[u8]) -> Result<(Vec<PSObject>, usize), String> {
+    let kind = bytes[0];
+    let mut r = Reader {
+        bytes,
+        pos: 1,
+        // odd token types are the low-order-byte-first variants
+        low_first: kind % 2 == 1,
+    };
+    let obj = match kind {
+        128..=131 => return decode_sequence(bytes, kind % 2 == 1),
+        132 | 133 => PSObject::Int(r.u32()? as i32 as i64),
+        134 | 135 => PSObject::Int(r.u16()? as i16 as i64),
+        136 => PSObject::Int(r.u8()? as i8 as i64),
+        137 => {
+            let repr = r.u8()?;
+            number(&mut r, repr)?
+        }
+        138 => {
+            r.low_first = false;
+            PSObject::Real(r.f32()? as f64)
+        }
+        139 | 140 => {
+            // native order is low order first on every platform we build for
+            r.low_first = true;
+            PSObject::Real(r.f32()? as f64)
+        }
+        141 => PSObject::Bool(r.u8()? != 0),
+        142 => {
+            let len = r.u8()? as usize;
+            string(r.take(len)?)
+        }
+        143 | 144 => {
+            r.low_first = kind == 144;
+            let len = r.u16()? as usize;
+            string(r.take(len)?)
+        }
+        // system and user name tables aren't implemented
+        145..=148 => return Err("undefined".into()),
+        149 => {
+            let repr = r.u8()?;
+            r.low_first = repr >= 128;
+            let len = r.u16()?;
+            let mut elems = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                elems.push(number(&mut r, repr)?);
+            }
+            PSObject::Array(elems)
+        }
+        _ => return Err("syntaxerror".into()),
+    };
+    Ok((vec![obj], r.pos))
+}
+
+// numbers in the fixed point / real representation used by tokens 137 and 149
+fn number(r: &mut Reader, repr: u8) -> Result<PSObject, String> {
+    r.low_first = repr >= 128;
+    let repr = repr % 128;
+    let (raw, scale) = match repr {
+        0..=31 => (r.u32()? as i32 as f64, repr),
+        32..=47 => (r.u16()? as i16 as f64, repr - 32),
+        48 => return Ok(PSObject::Real(r.f32()? as f64)),
+        49 => {
+            // native order
+            r.low_first = true;
+            return Ok(PSObject::Real(r.f32()? as f64));
+        }
+        _ => return Err("syntaxerror".into()),
+    };
+    Ok(if scale == 0 {
+        PSObject::Int(raw as i64)
+    } else {
+        PSObject::Real(raw / 2f64.powi(scale as i32))
+    })
+}
+
+fn string(bytes: &[u8]) -> PSObject {
+    PSObject::String(bytes.to_vec(), false)
+}
+
+fn decode_sequence(bytes: &[u8], low_first: bool) -> Result<(Vec<PSObject>, usize), String> {
+    let mut r = Reader {
+        bytes,
+        pos: 1,
+        low_first,
+    };
+    // a zero in the second byte means the extended header with 32 bit lengths
+    let (top_len, total) = match r.u8()? {
+        0 => (r.u16()? as usize, r.u32()? as usize),
+        n => (n as usize, r.u16()? as usize),
+    };
+    let body = bytes.get(r.pos..total).ok_or("syntaxerror")?;
+    // arrays may share elements, so cap the objects we build by the size of the sequence
+    let mut budget = body.len();
+    let objects = read_array(body, 0, top_len, low_first, 0, &mut budget)?;
+    Ok((objects, total))
+}
+
+// arrays nested deeper than this are refused rather than recursed into
+const MAX_DEPTH: usize = 100;
+
+// offsets in the object records are relative to the start of the top level array (body)
+fn read_array(
+    body: &[u8],
+    offset: usize,
+    len: usize,
+    low_first: bool,
+    depth: usize,
+    budget: &mut usize,
+) -> Result<Vec<PSObject>, String> {
+    if depth > MAX_DEPTH || len > *budget {
+        return Err("limitcheck".into());
+    }
+    *budget -= len;
+    let mut objects = Vec::with_capacity(len.min(body.len() / 8));
+    for i in 0..len {
+        let mut r = Reader {
+            bytes: body,
+            pos: offset + i * 8,
+            low_first,
+        };
+        let kind = r.u8()?;
+        let executable = kind & 0x80 != 0;
+        r.u8()?; // tag, unused
+        let length = r.u16()? as usize;
+        let value = r.u32()?;
+        let text = |start: usize| -> Result<&[u8], String> {
+            body.get(start..start + length)
+                .ok_or_else(|| "syntaxerror".to_string())
+        };
+        objects.push(match kind & 0x7f {
+            1 => PSObject::Int(value as i32 as i64),
+            2 if length == 0 => PSObject::Real(f32::from_bits(value) as f64),
+            2 => PSObject::Real(value as i32 as f64 / 2f64.powi(length as i32)),
+            // immediately evaluated names (6) are looked up when executed like any other name
+            3 | 6 => {
+                if length == 0 {
+                    return Err("undefined".into());
+                }
+                let name = String::from_utf8_lossy(text(value as usize)?).into_owned();
+                PSObject::Name(name, executable || kind & 0x7f == 6)
+            }
+            4 => PSObject::Bool(value != 0),
+            5 => string(text(value as usize)?),
+            9 => {
+                let elems = read_array(body, value as usize, length, low_first, depth + 1, budget)?;
+                if executable {
+                    PSObject::Procedure {
+                        code: elems,
+                        env: None,
+                    }
+                } else {
+                    PSObject::Array(elems)
+                }
+            }
+            // there are no null or mark objects to produce
+            _ => return Err("syntaxerror".into()),
+        });
+    }
+    Ok(objects)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn single_number_tokens() {
+        assert_eq!(decode(&[136, 0xff]).unwrap(), (vec![PSObject::Int(-1)], 2));
+        assert_eq!(
+            decode(&[132, 0, 0, 1, 0]).unwrap(),
+            (vec![PSObject::Int(256)], 5)
+        );
+        assert_eq!(decode(&[135, 1, 0]).unwrap(), (vec![PSObject::Int(1)], 3));
+        // 16 bit fixed point with 8 fraction bits: 0x0180 / 256 = 1.5
+        assert_eq!(
+            decode(&[137, 40, 0x01, 0x80]).unwrap(),
+            (vec![PSObject::Real(1.5)], 4)
+        );
+        assert!(decode(&[132, 0]).is_err());
+    }
+
+    #[test]
+    fn strings_and_bools() {
+        assert_eq!(
+            decode(&[142, 2, b'h', b'i']).unwrap(),
+            (vec![PSObject::String("hi".into(), false)], 4)
+        );
+        assert_eq!(decode(&[141, 1]).unwrap(), (vec![PSObject::Bool(true)], 2));
+    }
+
+    #[test]
+    fn object_sequence() {
+        // { 3 4 add } as a sequence of two ints and the executable name "add"
+        let mut seq = vec![128, 3, 0, 0];
+        seq.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 3]);
+        seq.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 4]);
+        seq.extend_from_slice(&[0x83, 0, 0, 3, 0, 0, 0, 24]);
+        seq.extend_from_slice(b"add");
+        let total = seq.len() as u8;
+        seq[3] = total;
+        let (objs, used) = decode(&seq).unwrap();
+        assert_eq!(used, seq.len());
+        assert_eq!(
+            objs,
+            vec![
+                PSObject::Int(3),
+                PSObject::Int(4),
+                PSObject::Name("add".into(), true)
+            ]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use crate::interpreter::object::PSObject;
+use crate::interpreter::scanner::{line_col, parse_token, Scanner, MAX_NESTING};
+use std::fmt;
+
+// a problem found in a program without running it
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    // 1-based, columns count characters
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+// scan src the way the interpreter would and report unbalanced delimiters, unterminated
+// strings, things that look like numbers but won't read as one, and nesting deeper than
+// the scanner will take
+pub fn check(src: &str) -> Vec<Diagnostic> {
+    let mut found = Vec::new();
+    check_text(src, 0, 0, &mut found);
+    found.sort_by_key(|(at, _)| *at);
+    found
+        .into_iter()
+        .map(|(at, message)| {
+            let (line, column) = line_col(src.as_bytes(), at);
+            Diagnostic {
+                line,
+                column,
+                message,
+            }
+        })
+        .collect()
+}
+
+// text starts at byte offset base in the whole program, inside depth composites
+fn check_text(text: &str, base: usize, depth: usize, found: &mut Vec<(usize, String)>) {
+    let mut scanner = Scanner::new(text).binary_tokens(false);
+    while let Some((at, raw)) = scanner.next_raw_with_position() {
+        let Some(first) = raw.chars().next() else {
+            continue;
+        };
+        let pos = base + at;
+        match first {
+            '(' if scanner.unterminated() => found.push((pos, "unterminated string".into())),
+            '{' | '[' => {
+                let (kind, close) = if first == '{' {
+                    ("procedure", '}')
+                } else {
+                    ("array", ']')
+                };
+                // look inside using the source rather than the raw token, which has comments
+                // taken out and so wouldn't line up
+                let body = &text[at + 1..scanner.position()];
+                let body = if scanner.unterminated() || !raw.ends_with(close) {
+                    found.push((pos, format!("unclosed {}", kind)));
+                    body
+                } else {
+                    &body[..body.len() - 1]
+                };
+                // the formatter and linter walk what passes here recursively, so stop at the
+                // same depth the interpreter would
+                if depth >= MAX_NESTING {
+                    found.push((pos, "nesting too deep".into()));
+                } else {
+                    check_text(body, pos + 1, depth + 1, found);
+                }
+            }
+            '}' | ']' | ')' => found.push((pos, format!("unmatched {}", first))),
+            _ if looks_numeric(&raw) && matches!(parse_token(&raw), PSObject::Name(..)) => {
+                found.push((pos, format!("invalid number {}", raw)))
+            }
+            _ => {}
+        }
+    }
+}
+
+// starts like a number: a digit, or a sign or point followed by one
+fn looks_numeric(tok: &str) -> bool {
+    let rest = tok.strip_prefix(['+', '-']).unwrap_or(tok);
+    let rest = rest.strip_prefix('.').unwrap_or(rest);
+    rest.starts_with(|c: char| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn messages(src: &str) -> Vec<String> {
+        check(src).iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn clean_program() {
+        assert!(check("/sq { dup mul } def % comment }\n[1 2.5 -3 (s)] 4 sq").is_empty());
+    }
+
+    #[test]
+    fn reports_positions() {
+        assert_eq!(
+            messages("1 2 add\n  (abc"),
+            vec!["2:3: unterminated string"]
+        );
+        assert_eq!(messages("1 } 2"), vec!["1:3: unmatched }"]);
+        assert_eq!(
+            messages("x 1.2.3 12abc"),
+            vec!["1:3: invalid number 1.2.3", "1:9: invalid number 12abc"]
+        );
+        assert_eq!(messages("1e400"), vec!["1:1: invalid number 1e400"]);
+    }
+
+    #[test]
+    fn looks_inside_composites() {
+        assert_eq!(messages("{ 1 % }\n 1x }"), vec!["2:2: invalid number 1x"]);
+        assert_eq!(
+            messages("/p { [ 1 2"),
+            vec!["1:4: unclosed procedure", "1:6: unclosed array"]
+        );
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let deep = |n| "{".repeat(n) + &"}".repeat(n);
+        assert!(check(&deep(MAX_NESTING)).is_empty());
+        assert_eq!(
+            messages(&deep(MAX_NESTING + 10)),
+            vec![format!("1:{}: nesting too deep", MAX_NESTING + 1)]
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// where currentdate and currenttime get the time from. embedders without a system clock, or
+// who want something else than it, swap in their own
+pub trait Clock {
+    // seconds since 1970-01-01 00:00:00 UTC
+    fn now(&self) -> i64;
+}
+
+// the default clock: the system's
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        }
+    }
+}
+
+// a calendar date and time of day, in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    pub fn from_unix(secs: i64) -> DateTime {
+        let days = secs.div_euclid(86400);
+        let time = secs.rem_euclid(86400) as u32;
+        // Howard Hinnant's days_from_civil, backwards: count in 400 year eras starting on
+        // March 1st so the leap day comes last
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        DateTime {
+            year: yoe + era * 400 + (month <= 2) as i64,
+            month,
+            day,
+            hour: time / 3600,
+            minute: time / 60 % 60,
+            second: time % 60,
+        }
+    }
+
+    // 2024-02-29
+    pub fn date(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    // 13:05:09
+    pub fn time(&self) -> String {
+        format!("{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn calendar_dates() {
+        let t = DateTime::from_unix(0);
+        assert_eq!(
+            (t.date(), t.time()),
+            ("1970-01-01".into(), "00:00:00".into())
+        );
+        // a leap day, and the second before the epoch
+        let t = DateTime::from_unix(1709210096);
+        assert_eq!(
+            (t.date(), t.time()),
+            ("2024-02-29".into(), "12:34:56".into())
+        );
+        let t = DateTime::from_unix(-1);
+        assert_eq!(
+            (t.date(), t.time()),
+            ("1969-12-31".into(), "23:59:59".into())
+        );
+    }
+}
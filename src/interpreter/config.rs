@@ -0,0 +1,161 @@
+use crate::interpreter::interpreter::Scoping;
+use crate::interpreter::scanner;
+use std::path::{Path, PathBuf};
+
+// what a document may do with the filesystem
+#[derive(Clone, Debug, Default)]
+pub enum SecurityPolicy {
+    // no file access at all, for untrusted documents
+    NoFilesystem,
+    // reading is allowed below these directories, writing never is
+    ReadOnly {
+        roots: Vec<PathBuf>,
+    },
+    // anything the process itself can do
+    #[default]
+    Full,
+}
+
+impl SecurityPolicy {
+    pub fn allows_read(&self, path: &Path) -> bool {
+        match self {
+            SecurityPolicy::NoFilesystem => false,
+            SecurityPolicy::ReadOnly { roots } => {
+                // compare resolved paths so ../ and symlinks can't climb out of a root
+                let Ok(path) = path.canonicalize() else {
+                    return false;
+                };
+                roots
+                    .iter()
+                    .filter_map(|r| r.canonicalize().ok())
+                    .any(|r| path.starts_with(r))
+            }
+            SecurityPolicy::Full => true,
+        }
+    }
+
+    pub fn allows_write(&self, _path: &Path) -> bool {
+        matches!(self, SecurityPolicy::Full)
+    }
+
+    // environment variables can hold secrets, so sandboxed documents don't get them
+    pub fn allows_env(&self) -> bool {
+        matches!(self, SecurityPolicy::Full)
+    }
+}
+
+// how many bits integers have. 32 is what the spec and printers use, and what prologs that
+// test for overflow expect. 64 is handier when using the interpreter as a calculator
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IntWidth {
+    #[default]
+    Bits32,
+    Bits64,
+}
+
+impl IntWidth {
+    pub fn fits(self, n: i64) -> bool {
+        match self {
+            IntWidth::Bits32 => i32::try_from(n).is_ok(),
+            IntWidth::Bits64 => true,
+        }
+    }
+}
+
+// knobs an embedder sets once when creating an interpreter
+#[derive(Clone, Debug)]
+pub struct InterpreterConfig {
+    // recognize level 2 binary tokens and object sequences (bytes 128-159), turn this off for
+    // text-only input where such bytes should never show up
+    pub binary_tokens: bool,
+    pub security: SecurityPolicy,
+    // ceiling on the VM composite objects may take, in bytes (see PSObject::vm_size). going
+    // over it raises VMerror, None for no limit
+    pub max_vm: Option<usize>,
+    // the scoping procedures get until the program says lexical or dynamic
+    pub scoping: Scoping,
+    // how deep procedures may call each other before execstackoverflow. calls in tail
+    // position don't count, since they take the place of the caller
+    pub max_call_depth: usize,
+    // integer literals and results that don't fit come out as reals, like the spec says
+    pub int_width: IntWidth,
+    // put our own operators (operators::EXTENSIONS) in systemdict. off by default, since a
+    // program may well define names like format itself
+    pub extensions: bool,
+    // what currentdate and currenttime report, in seconds since 1970 UTC, instead of asking
+    // the clock. for output that's the same on every run
+    pub fixed_time: Option<i64>,
+    // the same output for the same input on every run: currentdate and currenttime report
+    // fixed_time (1970-01-01 00:00:00 without it), realtime and usertime stay at 0 and rand
+    // always starts from the same seed. dictionaries, directory listings and numbers come out
+    // the same way every time regardless
+    pub deterministic: bool,
+    // run lib/init.ps (library::INIT) into systemdict at startup, which defines the standard
+    // operators written in postscript (xor, stack, pstack). off leaves systemdict with
+    // only the ones built into rust, for a boot environment of your own
+    pub init: bool,
+    // bundled libraries (library::LIBRARIES) to run into userdict before anything else, as if
+    // the program started with runlibfile for each. a name that isn't one of them is an
+    // undefinedfilename from Interpreter::try_with_config
+    pub libraries: Vec<String>,
+    // how many objects one run may execute (procedure calls count too) before it stops with
+    // timeout, None for no limit. keeps untrusted or fuzzed programs from looping forever
+    pub max_steps: Option<u64>,
+    // how deeply procedures and arrays in the program may nest before limitcheck, and how deep
+    // packedarray may nest them at run time
+    pub max_nesting: usize,
+    // significant digits =, == and cvs print reals with, rounding like %g (Ghostscript's
+    // output is Some(6)). None prints the shortest text that reads back as the same real
+    pub real_digits: Option<usize>,
+}
+
+impl Default for InterpreterConfig {
+    fn default() -> Self {
+        InterpreterConfig {
+            binary_tokens: true,
+            security: SecurityPolicy::default(),
+            max_vm: None,
+            scoping: Scoping::default(),
+            max_call_depth: 1000,
+            int_width: IntWidth::default(),
+            extensions: false,
+            fixed_time: None,
+            deterministic: false,
+            init: true,
+            libraries: Vec::new(),
+            max_steps: None,
+            max_nesting: scanner::MAX_NESTING,
+            real_digits: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_only_roots() {
+        let root = std::env::temp_dir().join("ps-interpreter-policy-test");
+        std::fs::create_dir_all(root.join("inside")).unwrap();
+        std::fs::write(root.join("inside/a.ps"), "1").unwrap();
+        std::fs::write(root.join("b.ps"), "2").unwrap();
+
+        let policy = SecurityPolicy::ReadOnly {
+            roots: vec![root.join("inside")],
+        };
+        assert!(policy.allows_read(&root.join("inside/a.ps")));
+        assert!(!policy.allows_read(&root.join("inside/../b.ps")));
+        assert!(!policy.allows_read(&root.join("inside/missing.ps")));
+        assert!(!policy.allows_write(&root.join("inside/a.ps")));
+    }
+
+    #[test]
+    fn no_filesystem_and_full() {
+        let here = Path::new(".");
+        assert!(!SecurityPolicy::NoFilesystem.allows_read(here));
+        assert!(!SecurityPolicy::NoFilesystem.allows_write(here));
+        assert!(SecurityPolicy::Full.allows_read(here));
+        assert!(SecurityPolicy::Full.allows_write(here));
+    }
+}
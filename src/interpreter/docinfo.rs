@@ -0,0 +1,175 @@
+use crate::interpreter::dsc::DscDocument;
+use crate::interpreter::eps::postscript_section;
+
+// what print management software wants to know about a job before sending it anywhere,
+// read from the document's structuring comments without running it. the interpreter has
+// no painting operators, so running the job couldn't tell any more than the comments do
+#[derive(Debug, Default, PartialEq)]
+pub struct DocInfo {
+    // the %%Page: comments found, or what %%Pages: claims when there are none. None for a
+    // document that says nothing about its pages
+    pub page_count: Option<usize>,
+    // width and height in points of each page found: its %%PageBoundingBox, otherwise the
+    // first %%DocumentMedia or the document's %%BoundingBox
+    pub page_sizes: Vec<Option<(f64, f64)>>,
+    // fonts the document uses, whether it brings them along or needs the printer's
+    pub fonts: Vec<String>,
+    // whether it uses colors other than black, None when the comments don't say
+    pub color: Option<bool>,
+    pub language_level: Option<u32>,
+    // printer features from %%Requirements:, e.g. duplex or numcopies(2)
+    pub requirements: Vec<String>,
+    // resources the printer has to supply, as (type, name): font, procset, file, ...
+    pub needed_resources: Vec<(String, String)>,
+}
+
+// analyze a document (DOS EPS binaries included). only a broken DOS EPS header is an error,
+// a document without structuring comments just gives an info with little in it
+pub fn analyze(bytes: &[u8]) -> Result<DocInfo, String> {
+    let src = postscript_section(bytes)?;
+    let doc = DscDocument::parse(&src);
+    // (atend) that the trailer never resolved says nothing
+    let value = |key: &str| doc.header_value(key).filter(|v| *v != "(atend)");
+    let words = |key: &str| value(key).map_or(Vec::new(), |v| v.split_whitespace().collect());
+
+    let default_size = value("DocumentMedia")
+        .and_then(media_size)
+        .or(doc.bounding_box.map(|b| (b.width(), b.height())));
+    let page_sizes = doc
+        .pages
+        .iter()
+        .map(|page| {
+            page.bounding_box
+                .map(|b| (b.width(), b.height()))
+                .or(default_size)
+        })
+        .collect();
+    let page_count = match doc.pages.len() {
+        0 => doc.declared_pages.map(|n| n as usize),
+        n => Some(n),
+    };
+
+    let needed_resources = resources(value("DocumentNeededResources"))
+        .into_iter()
+        .chain(
+            words("DocumentNeededFonts")
+                .into_iter()
+                .map(|f| ("font".to_string(), f.to_string())),
+        )
+        .fold(Vec::new(), push_new);
+    let supplied = resources(value("DocumentSuppliedResources"));
+    let fonts = words("DocumentFonts")
+        .into_iter()
+        .chain(words("DocumentSuppliedFonts"))
+        .map(str::to_string)
+        .chain(
+            needed_resources
+                .iter()
+                .chain(&supplied)
+                .filter(|(kind, _)| kind == "font")
+                .map(|(_, name)| name.clone()),
+        )
+        .fold(Vec::new(), push_new);
+
+    let requirements: Vec<String> = words("Requirements")
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let process = words("DocumentProcessColors");
+    let color = if !words("DocumentCustomColors").is_empty()
+        || process.iter().any(|c| *c != "Black")
+        || requirements.iter().any(|r| r == "color")
+    {
+        Some(true)
+    } else if !process.is_empty() {
+        Some(false)
+    } else {
+        None
+    };
+
+    Ok(DocInfo {
+        page_count,
+        page_sizes,
+        fonts,
+        color,
+        language_level: value("LanguageLevel").and_then(|l| l.parse().ok()),
+        requirements,
+        needed_resources,
+    })
+}
+
+fn push_new<T: PartialEq>(mut all: Vec<T>, item: T) -> Vec<T> {
+    if !all.contains(&item) {
+        all.push(item);
+    }
+    all
+}
+
+// %%DocumentMedia: name width height weight color type, the first medium's size
+fn media_size(value: &str) -> Option<(f64, f64)> {
+    let words: Vec<&str> = value.split_whitespace().collect();
+    Some((words.get(1)?.parse().ok()?, words.get(2)?.parse().ok()?))
+}
+
+// a resource list, each line starting with the type of what follows, or carrying on with the
+// type of the line before. a procset is followed by its version and revision
+fn resources(value: Option<&str>) -> Vec<(String, String)> {
+    const TYPES: &[&str] = &["font", "procset", "file", "pattern", "form", "encoding"];
+    let mut found = Vec::new();
+    let mut kind = "font";
+    for line in value.unwrap_or("").lines() {
+        let mut words = line.split_whitespace().peekable();
+        if let Some(first) = words.next_if(|w| TYPES.contains(w)) {
+            kind = first;
+        }
+        let step = if kind == "procset" { 3 } else { 1 };
+        for name in words.step_by(step) {
+            found.push((kind.to_string(), name.to_string()));
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn job_metadata() {
+        let src = "%!PS-Adobe-3.0
+%%Pages: (atend)
+%%BoundingBox: 0 0 612 792
+%%DocumentNeededResources: font Times-Roman Helvetica
+%%+ procset Adobe_Tools 1.0 0
+%%DocumentSuppliedResources: font MyFont
+%%DocumentProcessColors: Cyan Black
+%%Requirements: duplex numcopies(2)
+%%LanguageLevel: 2
+%%EndComments
+%%Page: 1 1
+%%PageBoundingBox: 0 0 595 842
+%%Page: 2 2
+%%Trailer
+%%Pages: 2
+%%EOF
+";
+        let info = analyze(src.as_bytes()).unwrap();
+        assert_eq!(info.page_count, Some(2));
+        assert_eq!(
+            info.page_sizes,
+            vec![Some((595.0, 842.0)), Some((612.0, 792.0))]
+        );
+        assert_eq!(info.fonts, vec!["Times-Roman", "Helvetica", "MyFont"]);
+        assert_eq!(info.color, Some(true));
+        assert_eq!(info.language_level, Some(2));
+        assert_eq!(info.requirements, vec!["duplex", "numcopies(2)"]);
+        assert_eq!(
+            info.needed_resources[2],
+            ("procset".to_string(), "Adobe_Tools".to_string())
+        );
+        assert_eq!(info.needed_resources.len(), 3);
+
+        // nothing to go on
+        assert_eq!(analyze(b"1 2 add").unwrap(), DocInfo::default());
+    }
+}
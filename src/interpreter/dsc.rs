@@ -0,0 +1,342 @@
+use std::ops::Range;
+
+// %%BoundingBox: llx lly urx ury, in default user space units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub llx: f64,
+    pub lly: f64,
+    pub urx: f64,
+    pub ury: f64,
+}
+
+impl BoundingBox {
+    // four numbers separated by whitespace, none for (atend) or anything malformed
+    pub fn parse(value: &str) -> Option<Self> {
+        let nums: Vec<f64> = value
+            .split_whitespace()
+            .map(|n| n.parse::<f64>())
+            .collect::<Result<_, _>>()
+            .ok()?;
+        match nums[..] {
+            [llx, lly, urx, ury] => Some(BoundingBox { llx, lly, urx, ury }),
+            _ => None,
+        }
+    }
+
+    pub fn width(&self) -> f64 {
+        self.urx - self.llx
+    }
+
+    pub fn height(&self) -> f64 {
+        self.ury - self.lly
+    }
+}
+
+#[derive(Debug)]
+pub struct DscPage {
+    // the first %%Page: argument, e.g. "3" or "(iv)"
+    pub label: String,
+    // the second %%Page: argument, the page's position in the document
+    pub ordinal: u32,
+    pub bounding_box: Option<BoundingBox>,
+    // byte range of the page in the source, starting at its %%Page: comment
+    pub range: Range<usize>,
+}
+
+// a document split up along its %% comments
+#[derive(Debug)]
+pub struct DscDocument<'a> {
+    src: &'a str,
+    // whatever follows %! on the first line, e.g. "PS-Adobe-3.0 EPSF-3.0"
+    pub version: Option<String>,
+    // header comments in order, with (atend) values filled in from the trailer. %%+
+    // continuation lines are joined on, each on a line of its own
+    pub header: Vec<(String, String)>,
+    pub bounding_box: Option<BoundingBox>,
+    // what %%Pages: claims, which may not match the pages we actually found
+    pub declared_pages: Option<u32>,
+    // everything before the first page: header, prolog and setup
+    pub prolog: Range<usize>,
+    pub pages: Vec<DscPage>,
+    // from %%Trailer (or %%EOF when there is no trailer) to the end
+    pub trailer: Range<usize>,
+    pub has_eof: bool,
+}
+
+impl<'a> DscDocument<'a> {
+    pub fn parse(src: &'a str) -> Self {
+        let mut doc = DscDocument {
+            src,
+            version: None,
+            header: Vec::new(),
+            bounding_box: None,
+            declared_pages: None,
+            prolog: 0..src.len(),
+            pages: Vec::new(),
+            trailer: src.len()..src.len(),
+            has_eof: false,
+        };
+        let mut in_header = true;
+        let mut in_trailer = false;
+        let mut trailer_start = None;
+        // %%Page: comments of embedded documents don't split our pages
+        let mut embedded = 0;
+        // the header entry %%+ continues
+        let mut last = None;
+
+        let mut offset = 0;
+        for line in src.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+            let text = line.trim_end_matches(['\r', '\n']);
+
+            if start == 0 && text.starts_with("%!") {
+                doc.version = Some(text[2..].trim().to_string());
+                continue;
+            }
+            if !text.starts_with("%%") {
+                // the header is the first run of comment lines
+                if !text.starts_with('%') {
+                    in_header = false;
+                }
+                continue;
+            }
+            let (key, value) = match text[2..].split_once(':') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => (text[2..].trim(), ""),
+            };
+
+            match key {
+                "BeginDocument" => embedded += 1,
+                "EndDocument" => embedded -= 1,
+                _ if embedded > 0 => {}
+                "EndComments" => in_header = false,
+                "Page" => {
+                    in_header = false;
+                    if let Some(prev) = doc.pages.last_mut() {
+                        prev.range.end = start;
+                    } else {
+                        doc.prolog.end = start;
+                    }
+                    let (label, ordinal) = match value.rsplit_once(char::is_whitespace) {
+                        Some((l, o)) => (l.trim(), o.parse().unwrap_or(0)),
+                        None => (value, 0),
+                    };
+                    doc.pages.push(DscPage {
+                        label: label.to_string(),
+                        ordinal,
+                        bounding_box: None,
+                        range: start..src.len(),
+                    });
+                }
+                "PageBoundingBox" => {
+                    if let Some(page) = doc.pages.last_mut() {
+                        page.bounding_box = BoundingBox::parse(value);
+                    }
+                }
+                "Trailer" | "EOF" => {
+                    if key == "EOF" {
+                        doc.has_eof = true;
+                    } else {
+                        in_trailer = true;
+                    }
+                    if trailer_start.is_none() {
+                        trailer_start = Some(start);
+                        if let Some(prev) = doc.pages.last_mut() {
+                            prev.range.end = start;
+                        } else {
+                            doc.prolog.end = start;
+                        }
+                    }
+                }
+                _ if key.starts_with('+') && (in_header || in_trailer) => {
+                    if let Some(entry) = last.and_then(|i: usize| doc.header.get_mut(i)) {
+                        entry.1.push('\n');
+                        entry.1.push_str(text[3..].trim());
+                    }
+                }
+                _ if in_header => {
+                    doc.header.push((key.to_string(), value.to_string()));
+                    last = Some(doc.header.len() - 1);
+                }
+                // the trailer resolves header values deferred with (atend)
+                _ if in_trailer => match doc.header.iter().position(|(k, _)| k == key) {
+                    Some(i) => {
+                        doc.header[i].1 = value.to_string();
+                        last = Some(i);
+                    }
+                    None => {
+                        doc.header.push((key.to_string(), value.to_string()));
+                        last = Some(doc.header.len() - 1);
+                    }
+                },
+                _ => {}
+            }
+        }
+        if let Some(start) = trailer_start {
+            doc.trailer = start..src.len();
+        }
+
+        doc.bounding_box = doc.header_value("BoundingBox").and_then(BoundingBox::parse);
+        doc.declared_pages = doc.header_value("Pages").and_then(|p| p.parse().ok());
+        doc
+    }
+
+    // value of a header comment, e.g. header_value("Title")
+    pub fn header_value(&self, key: &str) -> Option<&str> {
+        self.header
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // claims to follow the structuring conventions at all
+    pub fn is_conforming(&self) -> bool {
+        self.version
+            .as_deref()
+            .is_some_and(|v| v.starts_with("PS-Adobe-"))
+    }
+
+    pub fn is_eps(&self) -> bool {
+        self.version.as_deref().is_some_and(|v| v.contains("EPSF"))
+    }
+
+    pub fn prolog_source(&self) -> &'a str {
+        &self.src[self.prolog.clone()]
+    }
+
+    pub fn trailer_source(&self) -> &'a str {
+        &self.src[self.trailer.clone()]
+    }
+
+    // source of the nth page, counting from 1
+    pub fn page_source(&self, n: usize) -> Option<&'a str> {
+        let page = self.pages.get(n.checked_sub(1)?)?;
+        Some(&self.src[page.range.clone()])
+    }
+
+    // a runnable program with the prolog, the selected pages (counting from 1) and the trailer
+    pub fn extract(&self, selection: &PageRanges) -> String {
+        String::from(self.prolog_source()) + &self.body(Some(selection))
+    }
+
+    // what follows the prolog: the selected pages (all of them for None) and the trailer
+    pub fn body(&self, selection: Option<&PageRanges>) -> String {
+        let mut out = String::new();
+        for n in 1..=self.pages.len() {
+            if selection.is_none_or(|s| s.contains(n)) {
+                out.push_str(self.page_source(n).unwrap());
+            }
+        }
+        out.push_str(self.trailer_source());
+        out
+    }
+}
+
+// a page selection like "2-5" or "3,5-7", an open end like "4-" runs to the last page
+#[derive(Debug, PartialEq)]
+pub struct PageRanges(Vec<(usize, usize)>);
+
+impl PageRanges {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut ranges = Vec::new();
+        for part in spec.split(',') {
+            let part = part.trim();
+            let bad = || format!("invalid page range '{}'", part);
+            let (first, last) = match part.split_once('-') {
+                Some((a, "")) => (a.parse().map_err(|_| bad())?, usize::MAX),
+                Some((a, b)) => (a.parse().map_err(|_| bad())?, b.parse().map_err(|_| bad())?),
+                None => {
+                    let n = part.parse().map_err(|_| bad())?;
+                    (n, n)
+                }
+            };
+            if first == 0 || first > last {
+                return Err(bad());
+            }
+            ranges.push((first, last));
+        }
+        Ok(PageRanges(ranges))
+    }
+
+    pub fn contains(&self, page: usize) -> bool {
+        self.0.iter().any(|&(a, b)| a <= page && page <= b)
+    }
+
+    // whether any range starts after the last of count pages, so selects nothing at all
+    pub fn beyond(&self, count: usize) -> bool {
+        self.0.iter().any(|&(a, _)| a > count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DOC: &str = "%!PS-Adobe-3.0
+%%Title: test
+%%BoundingBox: (atend)
+%%Pages: 2
+%%EndComments
+/sq { dup mul } def
+%%EndProlog
+%%Page: 1 1
+3 sq
+%%Page: (ii) 2
+%%PageBoundingBox: 0 0 10 20
+4 sq
+%%Trailer
+%%BoundingBox: 0 0 612 792
+%%EOF
+";
+
+    #[test]
+    fn parses_header_and_pages() {
+        let doc = DscDocument::parse(DOC);
+        assert!(doc.is_conforming());
+        assert!(!doc.is_eps());
+        assert_eq!(doc.header_value("Title"), Some("test"));
+        assert_eq!(doc.declared_pages, Some(2));
+        assert_eq!(doc.pages.len(), 2);
+        assert_eq!(doc.pages[1].label, "(ii)");
+        assert_eq!(doc.pages[1].ordinal, 2);
+        assert_eq!(doc.pages[1].bounding_box.unwrap().height(), 20.0);
+        assert_eq!(doc.page_source(1), Some("%%Page: 1 1\n3 sq\n"));
+        assert!(doc.has_eof);
+    }
+
+    #[test]
+    fn atend_resolved_from_trailer() {
+        let doc = DscDocument::parse(DOC);
+        assert_eq!(
+            doc.bounding_box,
+            Some(BoundingBox {
+                llx: 0.0,
+                lly: 0.0,
+                urx: 612.0,
+                ury: 792.0
+            })
+        );
+    }
+
+    #[test]
+    fn extract_keeps_prolog_and_trailer() {
+        let doc = DscDocument::parse(DOC);
+        let out = doc.extract(&PageRanges::parse("2").unwrap());
+        assert!(out.contains("/sq { dup mul } def"));
+        assert!(out.contains("4 sq"));
+        assert!(!out.contains("3 sq"));
+        assert!(out.ends_with("%%EOF\n"));
+    }
+
+    #[test]
+    fn page_ranges() {
+        let r = PageRanges::parse("3,5-7,10-").unwrap();
+        assert!(r.contains(3) && r.contains(6) && r.contains(12));
+        assert!(!r.contains(4) && !r.contains(8));
+        assert!(r.beyond(9) && !r.beyond(10));
+        assert!(PageRanges::parse("0").is_err());
+        assert!(PageRanges::parse("5-2").is_err());
+        assert!(PageRanges::parse("x").is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::interpreter::lexer::{Lexer, Token, TokenKind};
+use crate::interpreter::operators::{self, KnownName};
+
+// what an editor asks about a program while it's being written, for the language server.
+// positions are byte offsets, offset() gets one from a line and column
+
+// byte offset of a 0-based line and column (in characters), clamped to the line's end
+pub fn offset(src: &str, line: usize, column: usize) -> usize {
+    let start: usize = src.split_inclusive('\n').take(line).map(str::len).sum();
+    let rest = &src[start.min(src.len())..];
+    let line_len = rest.find('\n').unwrap_or(rest.len());
+    start
+        + rest[..line_len]
+            .char_indices()
+            .nth(column)
+            .map_or(line_len, |(i, _)| i)
+}
+
+// the token at an offset, including one that ends right there (the cursor after a word)
+pub fn token_at(src: &str, at: usize) -> Option<Token> {
+    Lexer::new(src)
+        .take_while(|t| t.span.start <= at)
+        .find(|t| t.span.contains(&at) || t.span.end == at)
+}
+
+// usage and description of the operator under the cursor
+pub fn hover(src: &str, at: usize) -> Option<String> {
+    let token = token_at(src, at)?;
+    let name = match token.kind {
+        TokenKind::Name | TokenKind::Boolean => token.text.as_str(),
+        TokenKind::LiteralName => &token.text[1..],
+        _ => return None,
+    };
+    let op = operators::info(name)?;
+    Some(format!("{}\n\n{}", op.usage, op.description))
+}
+
+// names that could finish the one being typed at an offset: operators (the extensions too)
+// and whatever the program defines, in that order
+pub fn completions(src: &str, at: usize) -> Vec<KnownName> {
+    let typed = token_at(src, at)
+        .filter(|t| matches!(t.kind, TokenKind::Name | TokenKind::LiteralName))
+        .map(|t| {
+            t.text[..at - t.span.start]
+                .trim_start_matches('/')
+                .to_string()
+        })
+        .unwrap_or_default();
+    let mut names: Vec<KnownName> = operators::builtins(true)
+        .filter(|op| op.name.starts_with(&typed))
+        .map(|op| KnownName {
+            name: op.name.to_string(),
+            operator: Some(op),
+        })
+        .collect();
+    for token in Lexer::new(src) {
+        let name = &token.text[1..];
+        if token.kind == TokenKind::LiteralName
+            && name.starts_with(&typed)
+            && name != typed
+            && !names.iter().any(|n| n.name == name)
+        {
+            names.push(KnownName {
+                name: name.to_string(),
+                operator: None,
+            });
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn positions_hover_and_completion() {
+        let src = "/square { dup mul } def\nsq";
+        assert_eq!(offset(src, 1, 1), 25);
+        assert_eq!(offset(src, 0, 99), 23);
+        assert_eq!(token_at(src, 12).unwrap().text, "dup");
+        assert_eq!(token_at(src, 13).unwrap().text, "dup");
+        assert_eq!(token_at(src, 9).unwrap().text, "{");
+
+        assert_eq!(
+            hover(src, 16).unwrap(),
+            "num1 num2 mul product\n\nmultiply two numbers"
+        );
+        assert!(hover(src, 2).is_none());
+
+        let names: Vec<(String, bool)> = completions(src, 26)
+            .into_iter()
+            .map(|n| (n.name, n.operator.is_some()))
+            .collect();
+        assert!(names.contains(&("sqrt".into(), true)));
+        assert_eq!(names.last().unwrap(), &("square".into(), false));
+        assert!(names.iter().all(|(n, _)| n.starts_with("sq")));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::interpreter::dsc::{BoundingBox, DscDocument};
+
+// DOS EPS binary header magic, followed by the PostScript section and optional previews
+const DOS_EPS_MAGIC: [u8; 4] = [0xC5, 0xD0, 0xD3, 0xC6];
+
+// what a page layout program wants to know about an EPS file before placing it
+#[derive(Debug, PartialEq)]
+pub struct EpsInfo {
+    pub bounding_box: BoundingBox,
+    pub hires_bounding_box: Option<BoundingBox>,
+    pub title: Option<String>,
+    pub creator: Option<String>,
+    pub creation_date: Option<String>,
+    pub language_level: Option<u32>,
+    // a TIFF/WMF preview in the DOS header or an interchange %%BeginPreview section
+    pub has_preview: bool,
+}
+
+impl EpsInfo {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let src = postscript_section(bytes)?;
+        let doc = DscDocument::parse(&src);
+        if !doc.is_eps() {
+            return Err("not an EPS file (missing EPSF in the %! line)".into());
+        }
+        let bounding_box = doc
+            .bounding_box
+            .ok_or("EPS file has no usable %%BoundingBox")?;
+        let owned = |key: &str| doc.header_value(key).map(|v| v.to_string());
+        Ok(EpsInfo {
+            bounding_box,
+            hires_bounding_box: doc
+                .header_value("HiResBoundingBox")
+                .and_then(BoundingBox::parse),
+            title: owned("Title"),
+            creator: owned("Creator"),
+            creation_date: owned("CreationDate"),
+            language_level: doc
+                .header_value("LanguageLevel")
+                .and_then(|l| l.parse().ok()),
+            has_preview: has_dos_preview(bytes) || src.contains("%%BeginPreview"),
+        })
+    }
+}
+
+// the PostScript part of an EPS file, skipping the DOS binary header if there is one
+pub fn postscript_section(bytes: &[u8]) -> Result<String, String> {
+    let ps = if bytes.starts_with(&DOS_EPS_MAGIC) {
+        let start = read_u32(bytes, 4)? as usize;
+        let len = read_u32(bytes, 8)? as usize;
+        bytes
+            .get(start..start.saturating_add(len))
+            .ok_or("DOS EPS header points past the end of the file")?
+    } else {
+        bytes
+    };
+    // drivers sometimes leave stray latin-1 bytes in comments, which shouldn't stop us
+    Ok(String::from_utf8_lossy(ps).into_owned())
+}
+
+fn has_dos_preview(bytes: &[u8]) -> bool {
+    // bytes 16..20 hold the WMF length and 24..28 the TIFF length
+    bytes.starts_with(&DOS_EPS_MAGIC)
+        && (read_u32(bytes, 16).unwrap_or(0) > 0 || read_u32(bytes, 24).unwrap_or(0) > 0)
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Result<u32, String> {
+    let b = bytes.get(at..at + 4).ok_or("truncated DOS EPS header")?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EPS: &str = "%!PS-Adobe-3.0 EPSF-3.0
+%%BoundingBox: 0 0 100 50
+%%HiResBoundingBox: 0 0 99.5 49.5
+%%Title: logo
+%%LanguageLevel: 2
+%%EndComments
+1 2 add
+%%EOF
+";
+
+    #[test]
+    fn reads_header_comments() {
+        let info = EpsInfo::from_bytes(EPS.as_bytes()).unwrap();
+        assert_eq!(info.bounding_box.urx, 100.0);
+        assert_eq!(info.hires_bounding_box.unwrap().ury, 49.5);
+        assert_eq!(info.title.as_deref(), Some("logo"));
+        assert_eq!(info.language_level, Some(2));
+        assert!(!info.has_preview);
+    }
+
+    #[test]
+    fn dos_header_is_skipped() {
+        let mut bytes = DOS_EPS_MAGIC.to_vec();
+        // ps offset, ps length, then empty wmf/tiff sections and a checksum
+        bytes.extend_from_slice(&30u32.to_le_bytes());
+        bytes.extend_from_slice(&(EPS.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0; 18]);
+        bytes.extend_from_slice(EPS.as_bytes());
+        assert_eq!(postscript_section(&bytes).unwrap(), EPS);
+        assert!(EpsInfo::from_bytes(&bytes).is_ok());
+    }
+
+    #[test]
+    fn plain_postscript_is_rejected() {
+        assert!(EpsInfo::from_bytes(b"%!PS-Adobe-3.0\n%%BoundingBox: 0 0 1 1\n").is_err());
+    }
+}
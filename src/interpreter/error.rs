@@ -0,0 +1,113 @@
+use crate::interpreter::operators;
+use std::fmt;
+
+// an error that stopped a run, with enough context for a front end to point at the culprit
+#[derive(Debug, Clone, PartialEq)]
+pub struct PSError {
+    // the postscript error name, e.g. "typecheck" or "stackunderflow"
+    pub name: String,
+    // the operator or name that was executing when it happened
+    pub command: Option<String>,
+    // byte offset in the program of the top level token that was running
+    pub position: Option<usize>,
+    // the operand stack at the time, bottom first, with the operands a failing operator took
+    pub stack: Vec<String>,
+    // the execution stack: what called each procedure that was running, outermost first
+    pub calls: Vec<String>,
+    // entries and capacity of each dictionary on the dictionary stack, systemdict first
+    pub dicts: Vec<(usize, usize)>,
+    // a handleerror procedure defined by the program has already reported it, so front ends
+    // shouldn't print it again
+    pub handled: bool,
+}
+
+impl PSError {
+    // the first line of the trailer, after "Error: ": /typecheck in --add--
+    pub fn summary(&self) -> String {
+        match &self.command {
+            Some(cmd) if operators::info(cmd).is_some() => format!("/{} in --{}--", self.name, cmd),
+            Some(cmd) => format!("/{} in {}", self.name, cmd),
+            None => format!("/{}", self.name),
+        }
+    }
+
+    // the stack dumps that follow the summary, laid out like ghostscript's so people (and
+    // scripts) used to reading those can read ours. the execution stack only has the chain
+    // of procedure calls, not the loops and files ghostscript also shows
+    pub fn stacks(&self) -> String {
+        let dicts: Vec<String> = self
+            .dicts
+            .iter()
+            .enumerate()
+            .map(|(i, (len, cap))| {
+                let mode = if i == 0 { "(ro)(G)" } else { "(L)" };
+                format!("--dict:{}/{}{}--", len, cap, mode)
+            })
+            .collect();
+        format!(
+            "Operand stack:\n   {}\nExecution stack:\n   {}\nDictionary stack:\n   {}",
+            self.stack.join("   "),
+            self.calls.join("   "),
+            dicts.join("   ")
+        )
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+
+    // everything ghostscript prints for an error nothing handled
+    pub fn trailer(&self) -> String {
+        format!("Error: {}\n{}", self.summary(), self.stacks())
+    }
+}
+
+impl fmt::Display for PSError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.command {
+            Some(cmd) => write!(f, "{} in {}", self.name, cmd),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl std::error::Error for PSError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ghostscript_style_trailer() {
+        let err = PSError {
+            name: "typecheck".into(),
+            command: Some("add".into()),
+            position: None,
+            stack: vec!["1".into(), "(x)".into()],
+            calls: vec!["f".into(), "--if--".into()],
+            dicts: vec![(70, 72), (1, 3)],
+            handled: false,
+        };
+        assert_eq!(
+            err.trailer(),
+            "Error: /typecheck in --add--
+Operand stack:
+   1   (x)
+Execution stack:
+   f   --if--
+Dictionary stack:
+   --dict:70/72(ro)(G)--   --dict:1/3(L)--"
+        );
+        let err = PSError {
+            name: "undefined".into(),
+            command: Some("frob".into()),
+            stack: vec![],
+            calls: vec![],
+            ..err
+        };
+        assert_eq!(err.summary(), "/undefined in frob");
+        assert!(err
+            .stacks()
+            .starts_with("Operand stack:\n\nExecution stack:\n\nDictionary stack:"));
+    }
+}
@@ -0,0 +1,203 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// the standard files a document can open by name with `file`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdFile {
+    Stdout,
+    Stderr,
+}
+
+impl StdFile {
+    // (%stdout) (w) file and friends. there's no %stdin, files to read are opened from disk
+    pub fn open(name: &str, access: &str) -> Result<StdFile, String> {
+        let file = match name {
+            "%stdout" => StdFile::Stdout,
+            "%stderr" => StdFile::Stderr,
+            _ => return Err("undefinedfilename".into()),
+        };
+        if access != "w" && access != "a" {
+            return Err("invalidfileaccess".into());
+        }
+        Ok(file)
+    }
+}
+
+// a file on disk opened for reading: what it held when it was opened, and how much of that
+// has been read. every copy of the file object shares one, so reading through any of them
+// moves them all along
+#[derive(Debug)]
+pub struct InputFile {
+    bytes: Vec<u8>,
+    pos: usize,
+}
+
+impl InputFile {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        InputFile { bytes, pos: 0 }
+    }
+
+    // what hasn't been read yet
+    pub fn unread(&self) -> &[u8] {
+        &self.bytes[self.pos..]
+    }
+
+    pub fn consume(&mut self, n: usize) {
+        self.pos = (self.pos + n).min(self.bytes.len());
+    }
+}
+
+// what a file object refers to: a standard file to write to, or a file on disk to read
+#[derive(Debug, Clone)]
+pub enum PSFile {
+    Std(StdFile),
+    Input(Rc<RefCell<InputFile>>),
+}
+
+// the same file, not just the same contents
+impl PartialEq for PSFile {
+    fn eq(&self, other: &PSFile) -> bool {
+        match (self, other) {
+            (PSFile::Std(a), PSFile::Std(b)) => a == b,
+            (PSFile::Input(a), PSFile::Input(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+// everything the interpreter prints ends up here, embedders swap in their own sink to capture
+// or redirect output
+pub trait OutputSink {
+    fn write(&mut self, file: StdFile, bytes: &[u8]) -> io::Result<()>;
+
+    fn flush(&mut self, _file: StdFile) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// the default sink: the process's own stdout and stderr
+pub struct StdioSink;
+
+impl OutputSink for StdioSink {
+    fn write(&mut self, file: StdFile, bytes: &[u8]) -> io::Result<()> {
+        match file {
+            StdFile::Stdout => io::stdout().write_all(bytes),
+            StdFile::Stderr => io::stderr().write_all(bytes),
+        }
+    }
+
+    fn flush(&mut self, file: StdFile) -> io::Result<()> {
+        match file {
+            StdFile::Stdout => io::stdout().flush(),
+            StdFile::Stderr => io::stderr().flush(),
+        }
+    }
+}
+
+// collects output in memory instead of printing it, clones share the same buffers so the
+// caller can keep one and hand the other to the interpreter
+#[derive(Clone, Default)]
+pub struct BufferSink {
+    stdout: Rc<RefCell<Vec<u8>>>,
+    stderr: Rc<RefCell<Vec<u8>>>,
+}
+
+impl BufferSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // everything written to %stdout so far, emptying the buffer
+    pub fn take_stdout(&self) -> String {
+        String::from_utf8_lossy(&self.stdout.take()).into_owned()
+    }
+
+    pub fn take_stderr(&self) -> String {
+        String::from_utf8_lossy(&self.stderr.take()).into_owned()
+    }
+}
+
+impl OutputSink for BufferSink {
+    fn write(&mut self, file: StdFile, bytes: &[u8]) -> io::Result<()> {
+        match file {
+            StdFile::Stdout => self.stdout.borrow_mut().extend_from_slice(bytes),
+            StdFile::Stderr => self.stderr.borrow_mut().extend_from_slice(bytes),
+        }
+        Ok(())
+    }
+}
+
+// hands every chunk to a callback as soon as it's written, for embedders that want to show
+// output live while a long program is still running
+pub struct CallbackSink<F: FnMut(StdFile, &[u8])> {
+    callback: F,
+}
+
+impl<F: FnMut(StdFile, &[u8])> CallbackSink<F> {
+    pub fn new(callback: F) -> Self {
+        CallbackSink { callback }
+    }
+}
+
+impl<F: FnMut(StdFile, &[u8])> OutputSink for CallbackSink<F> {
+    fn write(&mut self, file: StdFile, bytes: &[u8]) -> io::Result<()> {
+        (self.callback)(file, bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buffer_sink_shares_output() {
+        let sink = BufferSink::new();
+        let mut handle = sink.clone();
+        handle.write(StdFile::Stdout, b"hello ").unwrap();
+        handle.write(StdFile::Stdout, b"world").unwrap();
+        handle.write(StdFile::Stderr, b"oops").unwrap();
+        assert_eq!(sink.take_stdout(), "hello world");
+        assert_eq!(sink.take_stdout(), "");
+        assert_eq!(sink.take_stderr(), "oops");
+    }
+
+    #[test]
+    fn callback_sink_sees_each_chunk() {
+        let mut chunks = Vec::new();
+        let mut sink = CallbackSink::new(|file, bytes: &[u8]| chunks.push((file, bytes.to_vec())));
+        sink.write(StdFile::Stdout, b"1\n").unwrap();
+        sink.write(StdFile::Stderr, b"2\n").unwrap();
+        assert_eq!(
+            chunks,
+            vec![
+                (StdFile::Stdout, b"1\n".to_vec()),
+                (StdFile::Stderr, b"2\n".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn input_files_share_their_position() {
+        let file = PSFile::Input(Rc::new(RefCell::new(InputFile::new(b"1 2".to_vec()))));
+        let copy = file.clone();
+        if let PSFile::Input(f) = &file {
+            f.borrow_mut().consume(2);
+        }
+        match &copy {
+            PSFile::Input(f) => assert_eq!(f.borrow().unread(), b"2"),
+            _ => unreachable!(),
+        }
+        assert_eq!(file, copy);
+        let other = PSFile::Input(Rc::new(RefCell::new(InputFile::new(b"2".to_vec()))));
+        assert_ne!(file, other);
+    }
+
+    #[test]
+    fn only_standard_files_open() {
+        assert_eq!(StdFile::open("%stderr", "w"), Ok(StdFile::Stderr));
+        assert!(StdFile::open("%stdout", "r").is_err());
+        assert!(StdFile::open("data.txt", "w").is_err());
+    }
+}
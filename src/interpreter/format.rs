@@ -0,0 +1,229 @@
+use crate::interpreter::check::{check, Diagnostic};
+use crate::interpreter::scanner::Scanner;
+
+// one level of indentation in formatted output
+const INDENT: &str = "  ";
+
+// what the formatter sees of a program: tokens, comments and line breaks, with procedures
+// and arrays as nested groups
+#[derive(Debug)]
+enum Item {
+    Atom(String),
+    Comment(String),
+    // blank: there was at least one empty line
+    Break { blank: bool },
+    Group { open: char, items: Vec<Item> },
+}
+
+// reformat a program: procedures and arrays indented, one space between tokens, comments
+// and line structure (at most one blank line in a row) kept. refuses programs that don't
+// scan cleanly, since there's no telling what they were meant to look like
+pub fn format(src: &str) -> Result<String, Vec<Diagnostic>> {
+    let problems = check(src);
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    let mut out = Printer::default();
+    out.items(&parse(src));
+    Ok(out.finish())
+}
+
+fn parse(text: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut scanner = Scanner::new(text).binary_tokens(false);
+    let mut prev_end = 0;
+    while let Some((at, raw)) = scanner.next_raw_with_position() {
+        gap(&text[prev_end..at], &mut items);
+        match raw.chars().next() {
+            Some(open @ ('{' | '[')) => {
+                // the body from the source, not the raw token which has lost its comments
+                prev_end = scanner.position();
+                items.push(Item::Group {
+                    open,
+                    items: parse(&text[at + 1..prev_end - 1]),
+                });
+            }
+            _ => {
+                prev_end = at + raw.len();
+                items.push(Item::Atom(raw));
+            }
+        }
+    }
+    gap(&text[prev_end..], &mut items);
+    items
+}
+
+// the whitespace and comments between two tokens
+fn gap(text: &str, items: &mut Vec<Item>) {
+    let mut newlines = 0;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c == '%' {
+            let end = rest.find(['\n', '\r']).unwrap_or(rest.len());
+            if newlines > 0 {
+                items.push(Item::Break {
+                    blank: newlines > 1,
+                });
+            }
+            items.push(Item::Comment(rest[..end].trim_end().to_string()));
+            newlines = 0;
+            rest = &rest[end..];
+            continue;
+        }
+        // \n, \r and \r\n each end a line
+        if c == '\n' || c == '\r' {
+            newlines += 1;
+            rest = rest.strip_prefix("\r\n").unwrap_or(&rest[1..]);
+            continue;
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    if newlines > 0 {
+        items.push(Item::Break {
+            blank: newlines > 1,
+        });
+    }
+}
+
+// groups containing a comment or a line break (anywhere inside) are laid out over lines
+fn multiline(items: &[Item]) -> bool {
+    items.iter().any(|item| match item {
+        Item::Atom(_) => false,
+        Item::Comment(_) | Item::Break { .. } => true,
+        Item::Group { items, .. } => multiline(items),
+    })
+}
+
+// a group that fits on one line: { dup mul } and [1 2 3]
+fn inline(open: char, items: &[Item]) -> String {
+    let words: Vec<String> = items
+        .iter()
+        .map(|item| match item {
+            Item::Atom(a) => a.clone(),
+            Item::Group { open, items } => inline(*open, items),
+            _ => String::new(),
+        })
+        .collect();
+    match open {
+        '{' if words.is_empty() => "{}".into(),
+        '{' => format!("{{ {} }}", words.join(" ")),
+        _ => format!("[{}]", words.join(" ")),
+    }
+}
+
+#[derive(Default)]
+struct Printer {
+    lines: Vec<String>,
+    // the line being built, without its indentation
+    line: String,
+    depth: usize,
+}
+
+impl Printer {
+    fn word(&mut self, word: &str) {
+        if !self.line.is_empty() {
+            self.line.push(' ');
+        }
+        self.line.push_str(word);
+    }
+
+    fn newline(&mut self) {
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.lines
+                .push(format!("{}{}", INDENT.repeat(self.depth), line));
+        }
+    }
+
+    fn blank(&mut self) {
+        self.newline();
+        if self.lines.last().is_some_and(|l| !l.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+
+    fn items(&mut self, items: &[Item]) {
+        for item in items {
+            match item {
+                Item::Atom(a) => self.word(a),
+                Item::Comment(c) => self.word(c),
+                Item::Break { blank: true } => self.blank(),
+                Item::Break { blank: false } => self.newline(),
+                Item::Group { open, items } if !multiline(items) => {
+                    self.word(&inline(*open, items))
+                }
+                Item::Group { open, items } => {
+                    self.word(&open.to_string());
+                    self.newline();
+                    self.depth += 1;
+                    // the breaks right inside the delimiters are ours to place
+                    let start = items
+                        .iter()
+                        .position(|i| !matches!(i, Item::Break { .. }))
+                        .unwrap_or(items.len());
+                    let end = items
+                        .iter()
+                        .rposition(|i| !matches!(i, Item::Break { .. }))
+                        .map_or(start, |i| i + 1);
+                    self.items(&items[start..end]);
+                    self.newline();
+                    self.depth -= 1;
+                    self.word(if *open == '{' { "}" } else { "]" });
+                }
+            }
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.newline();
+        while self.lines.last().is_some_and(|l| l.is_empty()) {
+            self.lines.pop();
+        }
+        let mut out = self.lines.join("\n");
+        out.push('\n');
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn normalizes_spacing_and_indents() {
+        let src = "%!PS\n/sq   {dup    mul} def\n\n\n\n/f {\n1 2 % two\n[ 3 4 ]\n{\nx } if\n} def";
+        let expected = "%!PS
+/sq { dup mul } def
+
+/f {
+  1 2 % two
+  [3 4]
+  {
+    x
+  } if
+} def
+";
+        assert_eq!(format(src).unwrap(), expected);
+    }
+
+    #[test]
+    fn formatting_is_stable() {
+        let once = format("/a [1 % c\n 2] def {} (s t)").unwrap();
+        assert_eq!(format(&once).unwrap(), once);
+    }
+
+    #[test]
+    fn any_line_ending_breaks_lines() {
+        assert_eq!(format("1 %c\r2 add =\n").unwrap(), "1 %c\n2 add =\n");
+        assert_eq!(format("a\r\n\r\nb\r\nc").unwrap(), "a\n\nb\nc\n");
+        assert_eq!(
+            format("{ 1 % one\r\r2 }").unwrap(),
+            "{\n  1 % one\n\n  2\n}\n"
+        );
+    }
+
+    #[test]
+    fn broken_programs_are_refused() {
+        assert!(format("/f { 1 2").is_err());
+    }
+}
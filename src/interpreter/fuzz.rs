@@ -0,0 +1,91 @@
+use crate::interpreter::config::{InterpreterConfig, SecurityPolicy};
+use crate::interpreter::file::BufferSink;
+use crate::interpreter::interpreter::Interpreter;
+use crate::interpreter::scanner::{self, Scanner};
+
+// entry points for the fuzz targets in fuzz/, usable from any libFuzzer-style harness. they
+// take arbitrary bytes and must return whatever those are: errors are fine, a panic, a hang
+// or runaway memory is a bug
+
+// small enough that one input can't take long or use much memory, and no filesystem or
+// environment, since the input is hostile. deterministic so a crash reproduces
+pub fn config() -> InterpreterConfig {
+    InterpreterConfig {
+        security: SecurityPolicy::NoFilesystem,
+        max_vm: Some(1 << 20),
+        max_call_depth: 100,
+        max_steps: Some(100_000),
+        extensions: true,
+        deterministic: true,
+        ..InterpreterConfig::default()
+    }
+}
+
+// every way the scanner is driven: object by object, raw tokens, and all at once
+pub fn scan(data: &[u8]) {
+    let mut objects = Scanner::from_bytes(data);
+    while objects.next_with_position().is_some() {}
+    let mut raw = Scanner::from_bytes(data);
+    while raw.next_raw_with_position().is_some() {}
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = scanner::tokenize(text);
+        let _ = scanner::needs_more_input(text);
+    }
+}
+
+// run the bytes as a program with the limits above, output going nowhere
+pub fn run(data: &[u8]) {
+    let mut interp = Interpreter::with_config(config());
+    interp.set_output(Box::new(BufferSink::new()));
+    let _ = interp.run_bytes(data);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // bytes from a fixed xorshift, so failures reproduce
+    fn noise(seed: u64, len: usize) -> Vec<u8> {
+        let mut x = seed;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn survives_odd_input() {
+        let programs: [&[u8]; 9] = [
+            b"1000000000 {} repeat",
+            b"/f { f } def f",
+            b"/f { 1 f } def f",
+            b"1000000000 string",
+            b"(abc",
+            b"<4g> { ] } [ 1 -",
+            b"\x80\x01\x00\x05 \x95",
+            b"<~ 9jqo ~> 16#ffffffffffffffff 1e999 -2147483648 -1 idiv",
+            include_bytes!("../../fuzz/seeds/run/strings.ps"),
+        ];
+        for program in programs {
+            scan(program);
+            run(program);
+        }
+        // random bytes, and random choices from the characters that mean something
+        let tokens = b"{}[]()<>/% 0123456789.#e-+abcdefxyz\n\\~";
+        for seed in 1..200 {
+            let bytes = noise(seed, 64);
+            scan(&bytes);
+            run(&bytes);
+            let text: Vec<u8> = bytes
+                .iter()
+                .map(|b| tokens[*b as usize % tokens.len()])
+                .collect();
+            scan(&text);
+            run(&text);
+        }
+    }
+}
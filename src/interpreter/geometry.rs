@@ -0,0 +1,469 @@
+// plain geometry the hit testing operators will be built on, usable without an interpreter.
+// points, rectangles, matrices and paths convert to and from the objects postscript uses for
+// them, so an embedder can define one with Interpreter::define or read one back after pop
+
+use crate::interpreter::object::PSObject;
+
+// how close to an edge, in user space units, counts as on it
+const ON_EDGE: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+}
+
+// lower left and upper right corners, in the order pathbbox gives them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub llx: f64,
+    pub lly: f64,
+    pub urx: f64,
+    pub ury: f64,
+}
+
+impl Rect {
+    pub fn new(llx: f64, lly: f64, urx: f64, ury: f64) -> Rect {
+        Rect { llx, lly, urx, ury }
+    }
+
+    // the smallest rectangle around the points, None when there aren't any
+    pub fn around(points: impl IntoIterator<Item = Point>) -> Option<Rect> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let start = Rect::new(first.x, first.y, first.x, first.y);
+        Some(points.fold(start, |r, p| {
+            Rect::new(
+                r.llx.min(p.x),
+                r.lly.min(p.y),
+                r.urx.max(p.x),
+                r.ury.max(p.y),
+            )
+        }))
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        p.x >= self.llx && p.x <= self.urx && p.y >= self.lly && p.y <= self.ury
+    }
+}
+
+// [a b c d tx ty], taking (x, y) to (a x + c y + tx, b x + d y + ty) like the CTM does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+    pub tx: f64,
+    pub ty: f64,
+}
+
+impl Default for Matrix {
+    fn default() -> Self {
+        Matrix::identity()
+    }
+}
+
+impl Matrix {
+    pub fn identity() -> Matrix {
+        Matrix::scale(1.0, 1.0)
+    }
+
+    pub fn translate(tx: f64, ty: f64) -> Matrix {
+        Matrix {
+            tx,
+            ty,
+            ..Matrix::identity()
+        }
+    }
+
+    pub fn scale(sx: f64, sy: f64) -> Matrix {
+        Matrix {
+            a: sx,
+            b: 0.0,
+            c: 0.0,
+            d: sy,
+            tx: 0.0,
+            ty: 0.0,
+        }
+    }
+
+    // counterclockwise, in degrees like the rotate operator
+    pub fn rotate(degrees: f64) -> Matrix {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Matrix {
+            a: cos,
+            b: sin,
+            c: -sin,
+            d: cos,
+            tx: 0.0,
+            ty: 0.0,
+        }
+    }
+
+    // this matrix followed by next, what concatmatrix makes of self next
+    pub fn concat(&self, next: &Matrix) -> Matrix {
+        Matrix {
+            a: self.a * next.a + self.b * next.c,
+            b: self.a * next.b + self.b * next.d,
+            c: self.c * next.a + self.d * next.c,
+            d: self.c * next.b + self.d * next.d,
+            tx: self.tx * next.a + self.ty * next.c + next.tx,
+            ty: self.tx * next.b + self.ty * next.d + next.ty,
+        }
+    }
+
+    pub fn transform(&self, p: Point) -> Point {
+        Point::new(
+            self.a * p.x + self.c * p.y + self.tx,
+            self.b * p.x + self.d * p.y + self.ty,
+        )
+    }
+
+    // None for a matrix that squashes everything onto a line, where invertmatrix gives
+    // undefinedresult
+    pub fn invert(&self) -> Option<Matrix> {
+        let det = self.a * self.d - self.b * self.c;
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        Some(Matrix {
+            a: self.d / det,
+            b: -self.b / det,
+            c: -self.c / det,
+            d: self.a / det,
+            tx: (self.c * self.ty - self.d * self.tx) / det,
+            ty: (self.b * self.tx - self.a * self.ty) / det,
+        })
+    }
+}
+
+// straight line subpaths, each a polygon that's closed the way fill closes it. as an object
+// it's a procedure of moveto, lineto and closepath, like the ones user paths are made of
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Path {
+    pub subpaths: Vec<Vec<Point>>,
+}
+
+impl Path {
+    pub fn new() -> Path {
+        Path::default()
+    }
+
+    // start a new subpath at p
+    pub fn move_to(mut self, p: Point) -> Path {
+        self.subpaths.push(vec![p]);
+        self
+    }
+
+    // a line from the current point to p, starting a subpath there's none yet
+    pub fn line_to(mut self, p: Point) -> Path {
+        match self.subpaths.last_mut() {
+            Some(points) => points.push(p),
+            None => self.subpaths.push(vec![p]),
+        }
+        self
+    }
+
+    // what pathbbox reports, None for an empty path (where it's nocurrentpoint)
+    pub fn bbox(&self) -> Option<Rect> {
+        Rect::around(self.subpaths.iter().flatten().copied())
+    }
+
+    pub fn contains(&self, p: Point, rule: FillRule) -> bool {
+        contains(&self.subpaths, p, rule)
+    }
+
+    pub fn transform(&self, m: &Matrix) -> Path {
+        Path {
+            subpaths: self
+                .subpaths
+                .iter()
+                .map(|points| points.iter().map(|p| m.transform(*p)).collect())
+                .collect(),
+        }
+    }
+}
+
+impl From<Point> for PSObject {
+    fn from(p: Point) -> PSObject {
+        reals(&[p.x, p.y])
+    }
+}
+
+impl From<Rect> for PSObject {
+    fn from(r: Rect) -> PSObject {
+        reals(&[r.llx, r.lly, r.urx, r.ury])
+    }
+}
+
+impl From<Matrix> for PSObject {
+    fn from(m: Matrix) -> PSObject {
+        reals(&[m.a, m.b, m.c, m.d, m.tx, m.ty])
+    }
+}
+
+impl From<&Path> for PSObject {
+    fn from(path: &Path) -> PSObject {
+        let mut code = Vec::new();
+        for points in &path.subpaths {
+            for (i, p) in points.iter().enumerate() {
+                let op = if i == 0 { "moveto" } else { "lineto" };
+                code.extend([PSObject::Real(p.x), PSObject::Real(p.y)]);
+                code.push(PSObject::Name(op.into(), true));
+            }
+            code.push(PSObject::Name("closepath".into(), true));
+        }
+        PSObject::Procedure { code, env: None }
+    }
+}
+
+// the errors are the ones an operator given the object would raise: typecheck for the wrong
+// kind of object, rangecheck for an array of the wrong length
+impl TryFrom<&PSObject> for Point {
+    type Error = String;
+
+    fn try_from(obj: &PSObject) -> Result<Point, String> {
+        let [x, y] = numbers(obj)?;
+        Ok(Point::new(x, y))
+    }
+}
+
+impl TryFrom<&PSObject> for Rect {
+    type Error = String;
+
+    fn try_from(obj: &PSObject) -> Result<Rect, String> {
+        let [llx, lly, urx, ury] = numbers(obj)?;
+        Ok(Rect::new(llx, lly, urx, ury))
+    }
+}
+
+impl TryFrom<&PSObject> for Matrix {
+    type Error = String;
+
+    fn try_from(obj: &PSObject) -> Result<Matrix, String> {
+        let [a, b, c, d, tx, ty] = numbers(obj)?;
+        Ok(Matrix { a, b, c, d, tx, ty })
+    }
+}
+
+// a procedure (or array) of numbers, moveto, lineto and closepath
+impl TryFrom<&PSObject> for Path {
+    type Error = String;
+
+    fn try_from(obj: &PSObject) -> Result<Path, String> {
+        let code = match obj {
+            PSObject::Procedure { code, .. }
+            | PSObject::Array(code)
+            | PSObject::PackedArray(code) => code,
+            _ => return Err("typecheck".into()),
+        };
+        let mut path = Path::new();
+        let mut operands = Vec::new();
+        for obj in code {
+            let op = match obj {
+                PSObject::Name(op, true) | PSObject::Operator(op) => op.as_str(),
+                other => {
+                    operands.push(number(other)?);
+                    continue;
+                }
+            };
+            let at = match operands[..] {
+                [x, y] => Point::new(x, y),
+                _ if op == "closepath" && operands.is_empty() => continue,
+                _ => return Err("typecheck".into()),
+            };
+            path = match op {
+                "moveto" => path.move_to(at),
+                "lineto" => path.line_to(at),
+                _ => return Err("typecheck".into()),
+            };
+            operands.clear();
+        }
+        if operands.is_empty() {
+            Ok(path)
+        } else {
+            Err("typecheck".into())
+        }
+    }
+}
+
+fn reals(values: &[f64]) -> PSObject {
+    PSObject::Array(values.iter().map(|v| PSObject::Real(*v)).collect())
+}
+
+fn number(obj: &PSObject) -> Result<f64, String> {
+    match obj {
+        PSObject::Int(n) => Ok(*n as f64),
+        PSObject::Real(r) => Ok(*r),
+        _ => Err("typecheck".into()),
+    }
+}
+
+// the N numbers in an array
+fn numbers<const N: usize>(obj: &PSObject) -> Result<[f64; N], String> {
+    let (PSObject::Array(items) | PSObject::PackedArray(items)) = obj else {
+        return Err("typecheck".into());
+    };
+    if items.len() != N {
+        return Err("rangecheck".into());
+    }
+    let mut values = [0.0; N];
+    for (value, item) in values.iter_mut().zip(items) {
+        *value = number(item)?;
+    }
+    Ok(values)
+}
+
+// which points a path encloses, as for fill (nonzero) and eofill (even-odd)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FillRule {
+    #[default]
+    NonZero,
+    EvenOdd,
+}
+
+impl FillRule {
+    pub fn encloses(self, winding: i32) -> bool {
+        match self {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding % 2 != 0,
+        }
+    }
+}
+
+// how many times the outlines wind around p, counterclockwise positive. each subpath is a
+// polygon and is treated as closed, like fill does
+pub fn winding_number(subpaths: &[Vec<Point>], p: Point) -> i32 {
+    let mut winding = 0;
+    for poly in subpaths {
+        for (a, b) in edges(poly) {
+            // which side of the edge p is on, positive for the left
+            let side = (b.x - a.x) * (p.y - a.y) - (p.x - a.x) * (b.y - a.y);
+            if a.y <= p.y && b.y > p.y && side > 0.0 {
+                winding += 1;
+            } else if a.y > p.y && b.y <= p.y && side < 0.0 {
+                winding -= 1;
+            }
+        }
+    }
+    winding
+}
+
+// whether p is inside the outlines by the given rule. points right on an edge count as
+// inside, the way infill treats them
+pub fn contains(subpaths: &[Vec<Point>], p: Point, rule: FillRule) -> bool {
+    let on_edge = subpaths
+        .iter()
+        .flat_map(|poly| edges(poly))
+        .any(|(a, b)| on_segment(a, b, p));
+    on_edge || rule.encloses(winding_number(subpaths, p))
+}
+
+// the sides of a polygon including the closing one
+fn edges(poly: &[Point]) -> impl Iterator<Item = (Point, Point)> + '_ {
+    poly.iter()
+        .zip(poly.iter().cycle().skip(1))
+        .map(|(a, b)| (*a, *b))
+}
+
+// within a hair of the segment, to allow for rounding in coordinates
+fn on_segment(a: Point, b: Point, p: Point) -> bool {
+    let within =
+        p.x >= a.x.min(b.x) && p.x <= a.x.max(b.x) && p.y >= a.y.min(b.y) && p.y <= a.y.max(b.y);
+    // the cross product is the distance from the line times the edge's length
+    let cross = (b.x - a.x) * (p.y - a.y) - (p.x - a.x) * (b.y - a.y);
+    within && cross.abs() <= ON_EDGE * (b.x - a.x).hypot(b.y - a.y)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn square(x: f64, y: f64, size: f64) -> Vec<Point> {
+        vec![
+            Point::new(x, y),
+            Point::new(x + size, y),
+            Point::new(x + size, y + size),
+            Point::new(x, y + size),
+        ]
+    }
+
+    #[test]
+    fn nonzero_and_even_odd() {
+        // two squares drawn the same way round, one inside the other
+        let path = vec![square(0.0, 0.0, 10.0), square(2.0, 2.0, 6.0)];
+        let middle = Point::new(5.0, 5.0);
+        assert_eq!(winding_number(&path, middle), 2);
+        assert!(contains(&path, middle, FillRule::NonZero));
+        assert!(!contains(&path, middle, FillRule::EvenOdd));
+        assert!(contains(&path, Point::new(1.0, 1.0), FillRule::EvenOdd));
+        assert!(!contains(&path, Point::new(11.0, 5.0), FillRule::NonZero));
+    }
+
+    #[test]
+    fn matrices_and_paths() {
+        let m = Matrix::scale(2.0, 2.0).concat(&Matrix::translate(10.0, 0.0));
+        assert_eq!(m.transform(Point::new(1.0, 1.0)), Point::new(12.0, 2.0));
+        let back = m.invert().unwrap().transform(Point::new(12.0, 2.0));
+        assert_eq!(back, Point::new(1.0, 1.0));
+        assert!(Matrix::scale(0.0, 1.0).invert().is_none());
+        let turned = Matrix::rotate(90.0).transform(Point::new(1.0, 0.0));
+        assert!(turned.x.abs() < 1e-12 && (turned.y - 1.0).abs() < 1e-12);
+
+        let path = Path::new()
+            .move_to(Point::new(0.0, 0.0))
+            .line_to(Point::new(4.0, 0.0))
+            .line_to(Point::new(0.0, 3.0));
+        let bbox = path.transform(&m).bbox().unwrap();
+        assert_eq!(bbox, Rect::new(10.0, 0.0, 18.0, 6.0));
+        assert!(path.contains(Point::new(1.0, 1.0), FillRule::NonZero));
+        assert!(Path::new().bbox().is_none());
+    }
+
+    #[test]
+    fn to_and_from_objects() {
+        let obj = PSObject::from(Rect::new(0.0, 0.5, 10.0, 20.0));
+        assert_eq!(obj.to_string(), "[0.0 0.5 10.0 20.0]");
+        assert_eq!(Rect::try_from(&obj), Ok(Rect::new(0.0, 0.5, 10.0, 20.0)));
+        let m = PSObject::Array([2, 0, 0, 2, 5, 5].map(PSObject::Int).to_vec());
+        assert_eq!(
+            Matrix::try_from(&m)
+                .unwrap()
+                .transform(Point::new(1.0, 1.0)),
+            Point::new(7.0, 7.0)
+        );
+        assert_eq!(Point::try_from(&m), Err("rangecheck".into()));
+        assert_eq!(Point::try_from(&PSObject::Int(1)), Err("typecheck".into()));
+
+        let path = Path::new()
+            .move_to(Point::new(0.0, 0.0))
+            .line_to(Point::new(1.0, 0.0));
+        let obj = PSObject::from(&path);
+        assert_eq!(
+            obj.to_string(),
+            "{0.0 0.0 moveto 1.0 0.0 lineto closepath }"
+        );
+        assert_eq!(Path::try_from(&obj), Ok(path));
+        let scanned = crate::interpreter::scanner::Scanner::new("{ 1 2 moveto 3 lineto }")
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(Path::try_from(&scanned), Err("typecheck".into()));
+    }
+
+    #[test]
+    fn edges_count_as_inside() {
+        let path = vec![square(0.0, 0.0, 10.0)];
+        assert!(contains(&path, Point::new(10.0, 5.0), FillRule::NonZero));
+        assert!(contains(&path, Point::new(0.0, 0.0), FillRule::EvenOdd));
+        let reversed: Vec<Point> = path[0].iter().rev().copied().collect();
+        assert_eq!(winding_number(&[reversed], Point::new(5.0, 5.0)), -1);
+    }
+}
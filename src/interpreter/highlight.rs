@@ -0,0 +1,146 @@
+use crate::interpreter::lexer::{Lexer, Token, TokenKind};
+use crate::interpreter::operators;
+use std::ops::Range;
+
+// what a highlighter colors a token as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    // a name the interpreter has an operator for
+    Operator,
+    Name,
+    LiteralName,
+    Number,
+    String,
+    Comment,
+    // the brackets of procedures and arrays
+    Delimiter,
+}
+
+impl Class {
+    // also the css class html() gives it, after ps-
+    pub fn name(self) -> &'static str {
+        match self {
+            Class::Operator => "operator",
+            Class::Name => "name",
+            Class::LiteralName => "literal",
+            Class::Number => "number",
+            Class::String => "string",
+            Class::Comment => "comment",
+            Class::Delimiter => "delimiter",
+        }
+    }
+
+    // literals in the colors the repl shows them in
+    fn ansi(self) -> Option<&'static str> {
+        match self {
+            Class::Operator => Some("1"),
+            Class::Name => None,
+            Class::LiteralName => Some("35"),
+            Class::Number => Some("33"),
+            Class::String => Some("32"),
+            Class::Comment => Some("90"),
+            Class::Delimiter => Some("36"),
+        }
+    }
+}
+
+pub fn classify(token: &Token) -> Class {
+    match token.kind {
+        TokenKind::Integer | TokenKind::Real => Class::Number,
+        // true and false are names in systemdict, like the operators
+        TokenKind::Boolean => Class::Operator,
+        TokenKind::Name if operators::info(&token.text).is_some() => Class::Operator,
+        TokenKind::Name => Class::Name,
+        TokenKind::LiteralName => Class::LiteralName,
+        TokenKind::String | TokenKind::Unterminated => Class::String,
+        TokenKind::Comment => Class::Comment,
+        TokenKind::ProcedureOpen
+        | TokenKind::ProcedureClose
+        | TokenKind::ArrayOpen
+        | TokenKind::ArrayClose => Class::Delimiter,
+    }
+}
+
+// the class of every token in src, with its byte range. what's between them is whitespace
+pub fn classes(src: &str) -> Vec<(Class, Range<usize>)> {
+    Lexer::new(src)
+        .map(|token| (classify(&token), token.span))
+        .collect()
+}
+
+// src with ANSI color codes around the tokens, for a terminal
+pub fn ansi(src: &str) -> String {
+    render(src, |class, text| match class.ansi() {
+        Some(code) => format!("\x1b[{}m{}\x1b[0m", code, text),
+        None => text.to_string(),
+    })
+}
+
+// src as a <pre> with a <span class="ps-..."> around each token, for a web page to style
+pub fn html(src: &str) -> String {
+    let body = render(src, |class, text| {
+        format!(
+            "<span class=\"ps-{}\">{}</span>",
+            class.name(),
+            escape(text)
+        )
+    });
+    format!("<pre class=\"ps\">{}</pre>\n", body)
+}
+
+// every token of src through paint, the whitespace between them as it was (it's only
+// ever whitespace, comments are tokens)
+fn render(src: &str, paint: impl Fn(Class, &str) -> String) -> String {
+    let mut out = String::new();
+    let mut prev_end = 0;
+    for (class, span) in classes(src) {
+        out.push_str(&src[prev_end..span.start]);
+        out.push_str(&paint(class, &src[span.clone()]));
+        prev_end = span.end;
+    }
+    out.push_str(&src[prev_end..]);
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classes_and_output() {
+        use Class::*;
+        let src = "/sq { dup mul } def % x<y\nsq (s) 2";
+        let found: Vec<(Class, &str)> = classes(src)
+            .into_iter()
+            .map(|(class, span)| (class, &src[span]))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (LiteralName, "/sq"),
+                (Delimiter, "{"),
+                (Operator, "dup"),
+                (Operator, "mul"),
+                (Delimiter, "}"),
+                (Operator, "def"),
+                (Comment, "% x<y"),
+                (Name, "sq"),
+                (String, "(s)"),
+                (Number, "2"),
+            ]
+        );
+        assert_eq!(ansi("sq 2\n"), "sq \x1b[33m2\x1b[0m\n");
+        assert_eq!(
+            html("1 %<\n"),
+            "<pre class=\"ps\"><span class=\"ps-number\">1</span> \
+             <span class=\"ps-comment\">%&lt;</span>\n</pre>\n"
+        );
+    }
+}
@@ -1,313 +1,762 @@
-use crate::interpreter::object::PSObject;
+use crate::interpreter::clock::{Clock, DateTime, SystemClock};
+use crate::interpreter::config::InterpreterConfig;
+use crate::interpreter::eps;
+use crate::interpreter::error::PSError;
+use crate::interpreter::file::{BufferSink, InputFile, OutputSink, PSFile, StdFile, StdioSink};
+use crate::interpreter::json;
+use crate::interpreter::library;
+use crate::interpreter::object::{self, DictMap, PSObject, Scope, VM_ENTRY, VM_SLOT};
+use crate::interpreter::operators::{self, KnownName};
+use crate::interpreter::printf;
+use crate::interpreter::resource::{self, ResourceStatus, ResourceStore};
+use crate::interpreter::scanner::Scanner;
 use crate::interpreter::stack::Stack;
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-// can change to lexical scoping, dynamic is on by default
-#[derive(Copy, Clone, PartialEq, Eq)]
+// can change to lexical scoping, dynamic is on by default. each procedure keeps the mode
+// that was in force when it was made
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
 pub enum Scoping {
+    #[default]
     Dynamic,
     Lexical,
 }
 
-struct Frame {
-    map: HashMap<String, PSObject>,
-    // index in dict_stack
-    parent: usize,
+// everything a run produced, for embedders (like a web page) that have no terminal to print to
+#[derive(Debug, Default, PartialEq)]
+pub struct RunOutput {
+    // what went to %stdout
+    pub output: String,
+    // what went to %stderr, followed by the error that stopped the run if there was one
+    pub errors: String,
+    // the operand stack afterwards, bottom first
+    pub stack: Vec<String>,
 }
 
+// an operator supplied by the embedding program: gets its operands bottom first and returns
+// what should be pushed back
+pub type HostOperator = Box<dyn FnMut(Vec<PSObject>) -> Result<Vec<PSObject>, String>>;
+
+// what executive runs: an interactive session on the interpreter, supplied by the front end
+pub type Executive = Box<dyn FnMut(&mut Interpreter) -> Result<(), String>>;
+
+// teaching mode: gets a one line explanation of every step the interpreter takes, see
+// set_explainer
+pub type Explainer = Box<dyn FnMut(&str)>;
+
+// what the prompt operator prints, unless a program redefines prompt
+const DEFAULT_PROMPT: &str = "ps> ";
+
+// what languagelevel reports. there are some level 2 operators (the resource ones, binary
+// tokens, setglobal), but not <<, >>, save, restore, mark, roll or currentdict, which prologs
+// that see level 2 or more go on to use. so 1 until those exist
+pub const LANGUAGE_LEVEL: i64 = 1;
+
+// the crate version as one number for revision, 1.2.3 being 10203
+fn revision() -> i64 {
+    env!("CARGO_PKG_VERSION")
+        .split(['.', '-'])
+        .take(3)
+        .map(|part| part.parse::<i64>().unwrap_or(0))
+        .fold(0, |acc, n| acc * 100 + n)
+}
+
+// halftone and transfer settings. nothing is rendered so they don't do anything, but
+// printer prologs set them up front and some read them back
+#[derive(Clone)]
+struct Halftone {
+    // frequency, angle and spot function, from setscreen
+    screen: Vec<PSObject>,
+    // from sethalftone, or the type 1 dictionary setscreen makes
+    dict: PSObject,
+    // transfer procedures for red, green, blue and gray
+    transfer: Vec<PSObject>,
+}
+
+impl Default for Halftone {
+    // a 60 lpi screen at 45 degrees with round dots, and transfers that change nothing
+    fn default() -> Self {
+        let spot = Scanner::new("{ dup mul exch dup mul add 1 exch sub }")
+            .next_object()
+            .unwrap()
+            .unwrap();
+        let screen = vec![PSObject::Real(60.0), PSObject::Real(45.0), spot];
+        let identity = PSObject::Procedure {
+            code: vec![],
+            env: None,
+        };
+        Halftone {
+            dict: halftone_dict(&screen),
+            screen,
+            transfer: vec![identity; 4],
+        }
+    }
+}
+
+// the type 1 halftone dictionary equivalent to a setscreen
+fn halftone_dict(screen: &[PSObject]) -> PSObject {
+    let keys = ["Frequency", "Angle", "SpotFunction"];
+    let mut dict: DictMap = keys
+        .iter()
+        .map(|k| k.to_string())
+        .zip(screen.iter().cloned())
+        .collect();
+    dict.insert("HalftoneType".into(), PSObject::Int(1));
+    PSObject::Dict(dict)
+}
+
+// the state of a program at one moment, to go back to with Interpreter::restore. the
+// dictionaries on the stack are restored in place, so procedures that closed over them see
+// the old contents again too
+pub struct Snapshot {
+    op_stack: Stack,
+    dicts: Vec<(Rc<Scope>, DictMap)>,
+    scoping: Scoping,
+    global: bool,
+    packing: bool,
+    halftone: Halftone,
+    vm_used: usize,
+}
+
+// the bottom of the dictionary stack, which end can't pop: systemdict holds the operators,
+// userdict is where a program's definitions go
+const SYSTEMDICT: usize = 0;
+const USERDICT: usize = 1;
+
+// the most entries dict reserves room for up front, whatever size it's asked for
+const DICT_RESERVE: usize = 1024;
+
+// popped frames closures still link to, collected before object::sweep looks for cycles
+// among them. it's put off until there are twice as many as survived the last sweep
+const SWEEP_AFTER: usize = 256;
+
 pub struct Interpreter {
     op_stack: Stack,
     // where our dictionary operations will lay
-    dict_stack: Vec<Frame>,
+    dict_stack: Vec<Rc<Scope>>,
+    // frames that were still linked to when they were popped, and how many of them make
+    // the next sweep, see retire
+    suspects: Vec<Weak<Scope>>,
+    sweep_at: usize,
+    // the mode procedures made from now on get, set by lexical and dynamic
     scoping: Scoping,
+    // the mode of the procedure being run, None at the top level
+    running: Option<Scoping>,
+    // procedures being run inside each other right now
+    calls: usize,
+    // what called each of them (its name, or --if-- and the like), outermost first, and that
+    // chain as it was where the last error happened, for the execution stack errors report
+    call_chain: Vec<String>,
+    failed_chain: Option<Vec<String>>,
+    // the operands where the last error happened, before a failing operator took any
+    failed_operands: Option<Stack>,
+    // objects executed in this run, against config.max_steps
+    steps: u64,
     // runs until quits
     quit: bool,
+    // named resources for findresource and friends
+    resources: ResourceStore,
+    config: InterpreterConfig,
+    // where print, = and friends write to
+    output: Box<dyn OutputSink>,
+    // what currentdate and currenttime read, unless the config fixes the time
+    clock: Box<dyn Clock>,
+    // where realtime and usertime count from
+    started: Instant,
+    // rand's state and last result, from 1 to 2^31 - 2
+    seed: i64,
+    // teaching mode, when set
+    explainer: Option<Explainer>,
+    // whether the repl should copy input lines to stdout, see `echo`
+    echo: bool,
+    // the operator or name executing right now, reported when it fails
+    command: Option<String>,
+    // operators defined by the embedder with define_operator, with their operand count
+    host_ops: HashMap<String, (usize, HostOperator)>,
+    // VM taken by the composites created so far, roughly, see allocate
+    vm_used: usize,
+    // allocation mode from setglobal
+    global: bool,
+    // packing mode from setpacking
+    packing: bool,
+    halftone: Halftone,
+    // the last uncaught error with the operands at the time, for $error and handleerror, and
+    // whether it's been reported
+    last_error: Option<(PSError, Stack)>,
+    new_error: bool,
+    // the session executive starts, see set_executive
+    executive: Option<Executive>,
 }
 
-macro_rules! cmp_int {
+macro_rules! cmp_num {
     ($self:ident, $op:tt) => {{
-        let b = $self.op_stack.pop().ok_or("stackunderflow")?;
-        let a = $self.op_stack.pop().ok_or("stackunderflow")?;
-        if let (PSObject::Int(a), PSObject::Int(b)) = (a, b) {
-            $self.op_stack.push(PSObject::Bool(a $op b));
-            Ok(())
-        } else {
-            Err("typecheck".into())
-        }
+        let order = $self.compare()?;
+        $self.op_stack.push(PSObject::Bool(order $op Ordering::Equal));
+        Ok(())
     }};
 }
 
-macro_rules! unary_int {
-    ($self:ident, $body:expr) => {{
-        let v = $self.op_stack.pop().ok_or("stackunderflow")?;
-        if let PSObject::Int(n) = v {
-            $self.op_stack.push(PSObject::Int($body(n)));
-            Ok(())
-        } else {
-            Err("typecheck".into())
-        }
-    }};
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        Interpreter {
+        Self::with_config(InterpreterConfig::default())
+    }
+
+    // for a config written in code. one naming a library that isn't bundled panics, so a
+    // config from anywhere else should go through try_with_config
+    pub fn with_config(config: InterpreterConfig) -> Self {
+        Self::try_with_config(config).unwrap_or_else(|e| panic!("bad config: {}", e))
+    }
+
+    // the same, with an unknown library name (undefinedfilename) or an error running one of
+    // the libraries coming back as the error
+    pub fn try_with_config(config: InterpreterConfig) -> Result<Self, PSError> {
+        let mut interp = Interpreter {
             // initialize the properties
             op_stack: Stack::new(),
-            scoping: Scoping::Dynamic,
+            scoping: config.scoping,
+            running: None,
+            calls: 0,
+            call_chain: Vec::new(),
+            failed_chain: None,
+            failed_operands: None,
+            steps: 0,
             quit: false,
-            dict_stack: vec![Frame {
-                map: HashMap::new(),
-                parent: 0,
-            }],
+            resources: ResourceStore::new(),
+            config,
+            output: Box::new(StdioSink),
+            clock: Box::new(SystemClock),
+            started: Instant::now(),
+            seed: 1,
+            explainer: None,
+            echo: true,
+            command: None,
+            host_ops: HashMap::new(),
+            vm_used: 0,
+            global: false,
+            packing: false,
+            halftone: Halftone::default(),
+            last_error: None,
+            new_error: false,
+            executive: None,
+            dict_stack: Vec::new(),
+            suspects: Vec::new(),
+            sweep_at: SWEEP_AFTER,
+        };
+        let systemdict = Scope::new(
+            operators::builtins(interp.config.extensions)
+                .map(|op| (op.name.to_string(), PSObject::Operator(op.name.to_string())))
+                .collect(),
+            None,
+        );
+        interp.dict_stack = vec![systemdict.clone()];
+        if interp.config.init {
+            // dynamic, so nothing it defines closes over systemdict. the program's limits are
+            // for the program, so it runs under the default ones and only fails if it's broken
+            let scoping = std::mem::replace(&mut interp.scoping, Scoping::Dynamic);
+            let defaults = InterpreterConfig {
+                extensions: interp.config.extensions,
+                ..InterpreterConfig::default()
+            };
+            let config = std::mem::replace(&mut interp.config, defaults);
+            if let Err(e) = interp.exec_source(library::INIT.as_bytes(), &mut 0) {
+                panic!("lib/init.ps failed: {}", e);
+            }
+            interp.scoping = scoping;
+            interp.config = config;
+            interp.steps = 0;
+            // systemdict isn't the program's VM, live_vm leaves it out too
+            interp.vm_used = 0;
+        }
+        let userdict = Scope::new(DictMap::new(), Some(systemdict));
+        interp.dict_stack.push(userdict);
+        if !interp.config.deterministic {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos());
+            interp.set_seed((nanos % i32::MAX as u128) as i64);
+        }
+        for name in interp.config.libraries.clone() {
+            let Some(src) = library::get(&name) else {
+                interp.command = Some(name);
+                return Err(interp.error("undefinedfilename".into(), None));
+            };
+            if let Err(e) = interp.exec_source(src.as_bytes(), &mut 0) {
+                return Err(interp.error(e, None));
+            }
         }
+        Ok(interp)
     }
 
-    // tokenize stack, return vector of objects
-    fn raw_tokens(program: &str) -> Vec<String> {
-        let mut toks = Vec::<String>::new();
-        let mut buf = String::new();
+    // make a rust function callable from postscript. it takes `arity` operands off the stack
+    // (bottom first) and whatever it returns is pushed in order. it goes in systemdict next to
+    // the built in operators, so a program can shadow it with its own definition
+    pub fn define_operator(
+        &mut self,
+        name: &str,
+        arity: usize,
+        f: impl FnMut(Vec<PSObject>) -> Result<Vec<PSObject>, String> + 'static,
+    ) {
+        self.dict_stack[SYSTEMDICT]
+            .map
+            .borrow_mut()
+            .insert(name.to_string(), PSObject::Operator(name.to_string()));
+        self.host_ops.insert(name.to_string(), (arity, Box::new(f)));
+    }
 
-        let mut in_str = false; // inside ( ... )
-        let mut brace_depth = 0; // inside { ... }
-        let mut bracket_depth = 0; // inside [ ... ]
+    fn call_host_operator(&mut self, name: &str) -> Result<(), String> {
+        let (arity, mut f) = self.host_ops.remove(name).unwrap();
+        let result = self.pop_operands(arity).and_then(&mut f);
+        self.host_ops.insert(name.to_string(), (arity, f));
+        for obj in result? {
+            self.op_stack.push(obj);
+        }
+        Ok(())
+    }
 
-        for c in program.chars() {
-            if in_str {
-                buf.push(c);
-                if c == ')' {
-                    in_str = false;
-                    toks.push(buf.clone());
-                    buf.clear();
-                }
-            } else if brace_depth > 0 || bracket_depth > 0 {
-                match c {
-                    '{' => {
-                        brace_depth += 1;
-                        buf.push(c);
-                    }
-                    '}' => {
-                        brace_depth -= 1;
-                        buf.push(c);
-                        if brace_depth == 0 && bracket_depth == 0 {
-                            toks.push(buf.clone());
-                            buf.clear();
-                        }
-                    }
-                    '[' => {
-                        bracket_depth += 1;
-                        buf.push(c);
-                    }
-                    ']' => {
-                        bracket_depth -= 1;
-                        buf.push(c);
-                        if brace_depth == 0 && bracket_depth == 0 {
-                            toks.push(buf.clone());
-                            buf.clear();
-                        }
-                    }
-                    _ => buf.push(c),
-                }
-            } else {
-                match c {
-                    '(' => {
-                        in_str = true;
-                        buf.push(c);
-                    }
-                    '{' => {
-                        brace_depth = 1;
-                        buf.push(c);
-                    }
-                    '[' => {
-                        bracket_depth = 1;
-                        buf.push(c);
-                    }
-                    ch if ch.is_whitespace() => {
-                        if !buf.is_empty() {
-                            toks.push(buf.clone());
-                            buf.clear();
-                        }
-                    }
-                    _ => buf.push(c),
-                }
-            }
+    // give executive an interactive session to start, like the cli's repl. without one it
+    // fails with ioerror since there's no console to talk to
+    pub fn set_executive(
+        &mut self,
+        f: impl FnMut(&mut Interpreter) -> Result<(), String> + 'static,
+    ) {
+        self.executive = Some(Box::new(f));
+    }
+
+    // the text to prompt with: whatever running prompt prints, so a program can change it by
+    // defining its own. falls back to the default when that fails
+    pub fn prompt(&mut self) -> String {
+        let sink = BufferSink::new();
+        let previous = std::mem::replace(&mut self.output, Box::new(sink.clone()));
+        let result = self.execute_object(PSObject::Name("prompt".into(), true));
+        self.output = previous;
+        self.command = None;
+        match result {
+            Ok(()) => sink.take_stdout(),
+            Err(_) => DEFAULT_PROMPT.into(),
+        }
+    }
+
+    // the top n operands, bottom first, leaving the stack alone when there aren't enough
+    fn pop_operands(&mut self, n: usize) -> Result<Vec<PSObject>, String> {
+        if (self.op_stack.count() as usize) < n {
+            return Err("stackunderflow".into());
         }
-        if !buf.is_empty() {
-            toks.push(buf);
+        let mut args: Vec<PSObject> = (0..n).filter_map(|_| self.op_stack.pop()).collect();
+        args.reverse();
+        Ok(args)
+    }
+
+    // back to a freshly started interpreter: empty stacks and no definitions, but the same
+    // config, output sink, resource directory, executive and host operators
+    pub fn reset(&mut self) {
+        let mut fresh = Self::with_config(self.config.clone());
+        fresh.output = std::mem::replace(&mut self.output, Box::new(StdioSink));
+        fresh.clock = std::mem::replace(&mut self.clock, Box::new(SystemClock));
+        fresh.explainer = self.explainer.take();
+        fresh.executive = self.executive.take();
+        fresh.resources.set_dir(self.resources.dir().cloned());
+        for (name, (arity, f)) in self.host_ops.drain() {
+            fresh.define_operator(&name, arity, f);
         }
-        toks
+        *self = fresh;
     }
 
-    // turn into ps object after tokenizing
-    fn parse_token(tok: &str) -> PSObject {
-        // string literal
-        if tok.starts_with('(') && tok.ends_with(')') {
-            PSObject::String(tok[1..tok.len() - 1].into())
+    // what the repl's :undo goes back to: operands, dictionaries and the settings programs
+    // change (scoping, allocation and packing modes, halftones)
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            op_stack: self.op_stack.clone(),
+            dicts: self
+                .dict_stack
+                .iter()
+                .map(|scope| (scope.clone(), scope.map.borrow().clone()))
+                .collect(),
+            scoping: self.scoping,
+            global: self.global,
+            packing: self.packing,
+            halftone: self.halftone.clone(),
+            vm_used: self.vm_used,
+        }
+    }
 
-        // procedure literal
-        } else if tok.starts_with('{') {
-            let inner = &tok[1..tok.len() - 1];
-            let code = Self::tokenize(inner);
-            PSObject::Procedure {
-                code,
-                env: HashMap::new(),
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.op_stack = snapshot.op_stack;
+        let dicts = snapshot
+            .dicts
+            .into_iter()
+            .map(|(scope, map)| {
+                *scope.map.borrow_mut() = map;
+                scope
+            })
+            .collect();
+        let popped = std::mem::replace(&mut self.dict_stack, dicts);
+        for frame in popped {
+            if !self.dict_stack.iter().any(|f| Rc::ptr_eq(f, &frame)) {
+                self.retire(frame);
             }
+        }
+        self.scoping = snapshot.scoping;
+        self.global = snapshot.global;
+        self.packing = snapshot.packing;
+        self.halftone = snapshot.halftone;
+        self.vm_used = snapshot.vm_used;
+    }
 
-        // array
-        } else if tok.starts_with('[') && tok.ends_with(']') {
-            let inner = &tok[1..tok.len() - 1];
-            let vec = Self::tokenize(inner);
-            PSObject::Array(vec)
+    // the program's state as JSON, for diffing before and after a change: the scoping mode,
+    // the operand stack bottom first and the dictionaries from userdict up, keys sorted.
+    // objects are written in their == form
+    pub fn dump_state(&self) -> String {
+        let scoping = match self.scoping {
+            Scoping::Dynamic => "dynamic",
+            Scoping::Lexical => "lexical",
+        };
+        let operands: Vec<String> = self.stack().iter().map(|o| json::quote(o)).collect();
+        let dicts: Vec<String> = self
+            .dictionaries()
+            .into_iter()
+            .map(|defs| {
+                let fields: Vec<(String, String)> = defs
+                    .into_iter()
+                    .map(|(k, v)| (k, json::quote(&v)))
+                    .collect();
+                json::object(&fields, 2)
+            })
+            .collect();
+        let mut out = json::object(
+            &[
+                ("scoping".into(), json::quote(scoping)),
+                ("operands".into(), json::array(&operands, 1)),
+                ("dictionaries".into(), json::array(&dicts, 1)),
+            ],
+            0,
+        );
+        out.push('\n');
+        out
+    }
 
-        // integer
-        } else if let Ok(n) = tok.parse::<i32>() {
-            PSObject::Int(n)
-        // boolean
-        } else if tok == "true" {
-            PSObject::Bool(true)
-        } else if tok == "false" {
-            PSObject::Bool(false)
+    // the operand stack, bottom first
+    pub fn stack(&self) -> Vec<String> {
+        self.op_stack.iter().map(|o| o.to_string()).collect()
+    }
 
-        // literal names
-        } else if tok.starts_with('/') {
-            PSObject::Name(tok.into())
+    // the operand stack objects themselves, bottom first, for front ends that format them
+    pub fn operands(&self) -> impl Iterator<Item = &PSObject> {
+        self.op_stack.iter()
+    }
 
-        // executable name
-        } else {
-            PSObject::Name(tok.into())
+    // every name the program has defined with its value, sorted by name. when a name is
+    // defined in several dictionaries the topmost one wins, like a lookup would
+    pub fn definitions(&self) -> Vec<(String, String)> {
+        let mut defs: HashMap<String, String> = HashMap::new();
+        for frame in &self.dict_stack[USERDICT..] {
+            for (k, v) in frame.map.borrow().iter() {
+                defs.insert(k.clone(), v.to_string());
+            }
         }
+        let mut defs: Vec<(String, String)> = defs.into_iter().collect();
+        defs.sort();
+        defs
     }
 
-    // run raw_toekns and parse tokens for convienence
-    fn tokenize(program: &str) -> Vec<PSObject> {
-        Self::raw_tokens(program)
-            .into_iter()
-            .map(|raw| Self::parse_token(&raw))
+    // each dictionary on the dictionary stack from userdict up, with its entries sorted by
+    // name (systemdict is just the operators, see known_names)
+    pub fn dictionaries(&self) -> Vec<Vec<(String, String)>> {
+        self.dict_stack[USERDICT..]
+            .iter()
+            .map(|frame| {
+                let mut defs: Vec<(String, String)> = frame
+                    .map
+                    .borrow()
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.to_string()))
+                    .collect();
+                defs.sort();
+                defs
+            })
             .collect()
     }
 
-    // main loop: for each token, look it up, dispatch it (operator), or push in as data
-    pub fn run(&mut self, program: &str) -> Result<(), String> {
-        for mut obj in Self::tokenize(program) {
-            if self.quit {
-                break;
+    // everything a program could refer to right now: the built in operators with their
+    // metadata, then host operators and defined names, sorted by name
+    pub fn known_names(&self) -> Vec<KnownName> {
+        let mut names: Vec<KnownName> = operators::builtins(self.config.extensions)
+            .map(|op| KnownName {
+                name: op.name.to_string(),
+                operator: Some(op),
+            })
+            .collect();
+        // systemdict too, for what lib/init.ps defines
+        let defined = self.host_ops.keys().cloned().chain(
+            self.dict_stack
+                .iter()
+                .flat_map(|f| f.map.borrow().keys().cloned().collect::<Vec<_>>()),
+        );
+        for name in defined {
+            if !self.is_operator(&name) {
+                names.push(KnownName {
+                    name,
+                    operator: None,
+                });
             }
+        }
+        names.sort_by(|a, b| a.name.cmp(&b.name));
+        names.dedup_by(|a, b| a.name == b.name);
+        names
+    }
 
-            // lexical attachment
-            if let PSObject::Procedure { ref mut env, .. } = obj {
-                if env.is_empty() {
-                    *env = self.dict_stack.last().unwrap().map.clone();
-                }
-            }
+    // directory findresource falls back to, laid out as <dir>/<Category>/<key>
+    // (refused unless the security policy lets documents read it)
+    pub fn set_resource_dir(&mut self, dir: impl Into<PathBuf>) -> Result<(), String> {
+        let dir = dir.into();
+        if !self.config.security.allows_read(&dir) {
+            return Err("invalidfileaccess".into());
+        }
+        self.resources.set_dir(Some(dir));
+        Ok(())
+    }
+
+    // send %stdout and %stderr somewhere else than the process's own
+    pub fn set_output(&mut self, sink: Box<dyn OutputSink>) {
+        self.output = sink;
+    }
+
+    // the command line arguments a program gets, as the array ARGUMENTS in userdict
+    pub fn set_arguments(&mut self, args: &[String]) {
+        let args = args
+            .iter()
+            .map(|a| PSObject::String(a.clone().into_bytes(), false))
+            .collect();
+        self.dict_stack[USERDICT]
+            .map
+            .borrow_mut()
+            .insert("ARGUMENTS".into(), PSObject::Array(args));
+    }
+
+    // def for embedders: bind name in the current dictionary, which is userdict unless a
+    // program is running
+    pub fn define(&mut self, name: &str, value: PSObject) {
+        let frame = self.dict_stack.last().unwrap();
+        frame.map.borrow_mut().insert(name.to_string(), value);
+    }
+
+    // take the top of the operand stack, for embedders reading back what a program left
+    pub fn pop(&mut self) -> Option<PSObject> {
+        self.op_stack.pop()
+    }
+
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    // teaching mode: explain each step as it runs ("push 3", "add: pops 3 and 4, pushes 7",
+    // "name lookup /x → 42"). None turns it off again
+    pub fn set_explainer(&mut self, f: Option<Explainer>) {
+        self.explainer = f;
+    }
+
+    // the text is only made when someone is listening
+    fn explain(&mut self, text: impl FnOnce() -> String) {
+        if let Some(f) = &mut self.explainer {
+            f(&text());
+        }
+    }
+
+    // set by the `echo` operator
+    pub fn echo(&self) -> bool {
+        self.echo
+    }
+
+    // write to one of the standard files through the output sink
+    fn emit(&mut self, file: StdFile, bytes: &[u8]) -> Result<(), String> {
+        self.output
+            .write(file, bytes)
+            .map_err(|_| "ioerror".to_string())
+    }
+
+    // every file a document asks for is read through here so the security policy applies
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>, String> {
+        if !self.config.security.allows_read(path) {
+            return Err("invalidfileaccess".into());
+        }
+        std::fs::read(path).map_err(|_| "undefinedfilename".into())
+    }
+
+    // run an EPS file the way printer drivers bracket one (BeginEPSF/EndEPSF): in its own
+    // dictionary, with whatever it leaves on the operand and dictionary stacks cleaned up
+    // afterwards, even when it fails
+    pub fn include_eps(&mut self, bytes: &[u8]) -> Result<(), PSError> {
+        self.command = None;
+        self.failed_chain = None;
+        self.failed_operands = None;
+        let result = self.exec_eps(bytes);
+        result.map_err(|name| self.error(name, None))
+    }
+
+    fn exec_eps(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let src = eps::postscript_section(bytes)?;
+        let depth = self.op_stack.count();
+        let dicts = self.dict_stack.len();
+        self.push_scope(DictMap::new());
+        let result = self.exec_source(src.as_bytes(), &mut 0);
+        // an EPS file calling quit shouldn't end the including job
+        self.quit = false;
+        while self.op_stack.count() > depth {
+            self.op_stack.pop();
+        }
+        while self.dict_stack.len() > dicts {
+            self.pop_scope();
+        }
+        result
+    }
+
+    // main loop: for each token, look it up, dispatch it (operator), or push in as data
+    pub fn run(&mut self, program: &str) -> Result<(), PSError> {
+        self.run_bytes(program.as_bytes())
+    }
+
+    // run with all output captured instead of printed, then hand back the output, any error and
+    // the operand stack. the previous output sink is put back afterwards
+    pub fn run_captured(&mut self, program: &str) -> RunOutput {
+        let sink = BufferSink::new();
+        let previous = std::mem::replace(&mut self.output, Box::new(sink.clone()));
+        let result = self.run(program);
+        self.output = previous;
+
+        let mut errors = sink.take_stderr();
+        match result {
+            Err(e) if !e.handled => errors.push_str(&format!("Error: {}\n", e)),
+            _ => {}
+        }
+        RunOutput {
+            output: sink.take_stdout(),
+            errors,
+            stack: self.stack(),
+        }
+    }
+
+    // same as run, for input that may contain binary tokens
+    pub fn run_bytes(&mut self, program: &[u8]) -> Result<(), PSError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("run", bytes = program.len()).entered();
+        self.command = None;
+        self.failed_chain = None;
+        self.failed_operands = None;
+        self.steps = 0;
+        let mut at = 0;
+        let result = self.exec_source(program, &mut at);
+        result.map_err(|name| {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(error = %name, command = ?self.command, at, "uncaught error");
+            let err = self.error(name, Some(at));
+            self.handle_error(err)
+        })
+    }
+
+    // an error reached the top: save it for $error, then give a handleerror procedure the
+    // program defined a chance to report it. the built in handleerror is left to the front
+    // end, which knows how to show it best
+    fn handle_error(&mut self, mut err: PSError) -> PSError {
+        // a copy on write snapshot, so this is cheap however big the operands are
+        let operands = self
+            .failed_operands
+            .take()
+            .unwrap_or_else(|| self.op_stack.clone());
+        self.last_error = Some((err.clone(), operands));
+        self.new_error = true;
+        if let Some(handler @ PSObject::Procedure { .. }) = self.lookup_name("handleerror") {
+            let command = self.command.take();
+            err.handled = self.exec_proc(handler).is_ok();
+            self.command = command;
+            self.quit = false;
+        }
+        if err.handled {
+            self.new_error = false;
+        }
+        err
+    }
+
+    // wrap an error name with what we know about where it happened
+    fn error(&mut self, name: String, position: Option<usize>) -> PSError {
+        PSError {
+            name,
+            command: self.command.take(),
+            position,
+            stack: self
+                .failed_operands
+                .as_ref()
+                .unwrap_or(&self.op_stack)
+                .iter()
+                .map(|o| o.to_string())
+                .collect(),
+            calls: self.failed_chain.take().unwrap_or_default(),
+            dicts: self
+                .dict_stack
+                .iter()
+                .map(|f| {
+                    let map = f.map.borrow();
+                    (map.len(), map.capacity().max(map.len()))
+                })
+                .collect(),
+            handled: false,
+        }
+    }
+
+    // scan and execute one token at a time, keeping at pointing to the current token
+    fn exec_source(&mut self, program: &[u8], at: &mut usize) -> Result<(), String> {
+        let mut scanner = Scanner::from_bytes(program)
+            .binary_tokens(self.config.binary_tokens)
+            .int_width(self.config.int_width)
+            .max_depth(self.config.max_nesting);
+        while !self.quit {
+            let Some((pos, obj)) = scanner.next_with_position() else {
+                break;
+            };
+            *at = pos;
+            let obj = obj?;
 
             // ── dispatch ──
-            let cur_top = self.dict_stack.len() - 1;
-            self.execute_object(obj, cur_top)?;
+            self.execute_object(obj)?;
         }
         Ok(())
     }
 
+    // the procedure being run decides how its names are looked up, outside of any the
+    // current setting does
+    fn active_scoping(&self) -> Scoping {
+        self.running.unwrap_or(self.scoping)
+    }
+
     // Matches if its dynamic or lexical
     fn lookup_name(&self, name: &str) -> Option<PSObject> {
-        match self.scoping {
+        match self.active_scoping() {
             Scoping::Dynamic => {
                 for frame in self.dict_stack.iter().rev() {
-                    if let Some(v) = frame.map.get(name) {
+                    if let Some(v) = frame.map.borrow().get(name) {
                         return Some(v.clone());
                     }
                 }
                 None
             }
-            Scoping::Lexical => {
-                let mut idx = self.dict_stack.len() - 1;
-                loop {
-                    let frame = &self.dict_stack[idx];
-                    if let Some(v) = frame.map.get(name) {
-                        return Some(v.clone());
-                    }
-                    if frame.parent == idx {
-                        // reached the bottom
-                        return None;
-                    }
-                    idx = frame.parent;
-                }
-            }
+            // follow the static links from the innermost scope
+            Scoping::Lexical => self.dict_stack.last().unwrap().lookup(name),
         }
     }
 
     // check if its a built in operator
     // utilizing pattern matching to efficient gathering
     fn is_operator(&self, name: &str) -> bool {
-        matches!(
-            name,
-            "add"
-                | "sub"
-                | "eq"
-                | "ne"
-                | "gt"
-                | "lt"
-                | "ge"
-                | "le"
-                | "and"
-                | "or"
-                | "not"
-                | "mul"
-                | "div"
-                | "mod"
-                | "exch"
-                | "pop"
-                | "dup"
-                | "copy"
-                | "clear"
-                | "count"
-                | "dict"
-                | "begin"
-                | "end"
-                | "def"
-                | "length"
-                | "maxlength"
-                | "get"
-                | "getinterval"
-                | "putinterval"
-                | "eq"
-                | "ne"
-                | "gt"
-                | "lt"
-                | "ge"
-                | "le"
-                | "and"
-                | "or"
-                | "not"
-                | "true"
-                | "false"
-                | "if"
-                | "ifelse"
-                | "for"
-                | "repeat"
-                | "quit"
-                | "print"
-                | "="
-                | "=="
-                | "dict"
-                | "begin"
-                | "end"
-                | "def"
-                | "length"
-                | "maxlength"
-                | "get"
-                | "getinterval"
-                | "putinterval"
-                | "idiv"
-                | "abs"
-                | "neg"
-                | "ceiling"
-                | "floor"
-                | "round"
-                | "sqrt"
-                | "lexical"
-                | "dynamic"
-                | "exec"
-        )
+        operators::builtins(self.config.extensions).any(|op| op.name == name)
     }
 
     // Dispatch to the appropriate operator method
@@ -331,6 +780,7 @@ impl Interpreter {
                 Ok(())
             }
             "copy" => self.op_copy(),
+            "index" => self.op_index(),
             "dict" => self.op_dict(),
             "begin" => self.op_begin(),
             "end" => self.op_end(),
@@ -344,6 +794,41 @@ impl Interpreter {
 
             "get" => self.op_get(),
             "getinterval" => self.op_getinterval(),
+            "cvs" => self.op_cvs(),
+            "format" => self.op_format(),
+            "currentdate" => {
+                self.push_constant(PSObject::String(self.now().date().into_bytes(), false))
+            }
+            "currenttime" => {
+                self.push_constant(PSObject::String(self.now().time().into_bytes(), false))
+            }
+            "getenv" => self.op_getenv(),
+            "help" => self.op_help(),
+            "over" => {
+                self.charge_copy(1)?;
+                Self::underflow(self.op_stack.over())
+            }
+            "nip" => Self::underflow(self.op_stack.nip()),
+            "tuck" => {
+                // the copy is of the top, but it needs something under it to go below
+                if self.op_stack.count() < 2 {
+                    return Err("stackunderflow".into());
+                }
+                self.charge_copy(0)?;
+                Self::underflow(self.op_stack.tuck())
+            }
+            "ndup" => self.op_ndup(),
+            "utf8length" => {
+                let s = self.pop_string()?;
+                self.push_constant(PSObject::Int(s.chars().count() as i64))
+            }
+            "utf8get" => self.op_utf8get(),
+            "utf8decode" => {
+                let s = self.pop_string()?;
+                let points = s.chars().map(|c| PSObject::Int(c as i64)).collect();
+                self.push_constant(PSObject::Array(points))
+            }
+            "utf8encode" => self.op_utf8encode(),
             "putinterval" => self.op_putinterval(),
             "eq" => self.op_eq(),
             "ne" => self.op_ne(),
@@ -365,7 +850,10 @@ impl Interpreter {
             "ceiling" => self.op_ceiling(),
             "floor" => self.op_floor(),
             "round" => self.op_round(),
+            "truncate" => self.op_truncate(),
             "sqrt" => self.op_sqrt(),
+            "exp" => self.op_exp(),
+            "ln" => self.op_ln(),
             "lexical" => {
                 self.scoping = Scoping::Lexical;
                 Ok(())
@@ -374,57 +862,137 @@ impl Interpreter {
                 self.scoping = Scoping::Dynamic;
                 Ok(())
             }
+            "currentscoping" => self.push_constant(PSObject::Name(
+                match self.scoping {
+                    Scoping::Dynamic => "dynamic",
+                    Scoping::Lexical => "lexical",
+                }
+                .into(),
+                false,
+            )),
             "exec" => self.op_exec(),
-            _ => Err(format!("Unknown operator {}", op)),
+            "cvx" => self.op_cvx(),
+            "executive" => self.op_executive(),
+            "prompt" => self.emit(StdFile::Stdout, DEFAULT_PROMPT.as_bytes()),
+            "token" => self.op_token(),
+            "forall" => self.op_forall(),
+            "packedarray" => self.op_packedarray(),
+            "setpacking" => self.op_setpacking(),
+            "currentpacking" => self.push_constant(PSObject::Bool(self.packing)),
+            "defineresource" => self.op_defineresource(),
+            "findresource" => self.op_findresource(),
+            "resourcestatus" => self.op_resourcestatus(),
+            "resourceforall" => self.op_resourceforall(),
+            "epsinclude" => self.op_epsinclude(),
+            "runlibfile" => self.op_runlibfile(),
+            "status" => self.op_status(),
+            "deletefile" => self.op_deletefile(),
+            "renamefile" => self.op_renamefile(),
+            "filenameforall" => self.op_filenameforall(),
+            "file" => self.op_file(),
+            "writestring" => self.op_writestring(),
+            "write" => self.op_write(),
+            "flush" => self.op_flush(),
+            "flushfile" => self.op_flushfile(),
+            "echo" => self.op_echo(),
+            "vmstatus" => self.op_vmstatus(),
+            "vmreclaim" => self.op_vmreclaim(),
+            "setglobal" => self.op_setglobal(),
+            "currentglobal" => self.push_constant(PSObject::Bool(self.global)),
+            "setscreen" => self.op_setscreen(),
+            "currentscreen" => {
+                for obj in self.halftone.screen.clone() {
+                    self.op_stack.push(obj);
+                }
+                Ok(())
+            }
+            "sethalftone" => self.op_sethalftone(),
+            "currenthalftone" => self.push_constant(self.halftone.dict.clone()),
+            "settransfer" => self.op_settransfer(),
+            "currenttransfer" => self.push_constant(self.halftone.transfer[3].clone()),
+            "setcolortransfer" => self.op_setcolortransfer(),
+            "currentcolortransfer" => {
+                for obj in self.halftone.transfer.clone() {
+                    self.op_stack.push(obj);
+                }
+                Ok(())
+            }
+            "errordict" => self.op_errordict(),
+            "$error" => self.push_constant(self.dollar_error()),
+            "handleerror" => self.op_handleerror(),
+            "languagelevel" => self.push_constant(PSObject::Int(LANGUAGE_LEVEL)),
+            "product" => self.push_constant(PSObject::String(env!("CARGO_PKG_NAME").into(), false)),
+            "version" => {
+                self.push_constant(PSObject::String(env!("CARGO_PKG_VERSION").into(), false))
+            }
+            "revision" => self.push_constant(PSObject::Int(revision())),
+            "serialnumber" => self.push_constant(PSObject::Int(0)),
+            "realtime" | "usertime" => self.push_constant(PSObject::Int(self.millis())),
+            "rand" => {
+                // park and miller's minimal standard generator
+                self.seed = self.seed * 16807 % i32::MAX as i64;
+                self.push_constant(PSObject::Int(self.seed))
+            }
+            "srand" => {
+                let seed = self.pop_int()?;
+                self.set_seed(seed);
+                Ok(())
+            }
+            "rrand" => self.push_constant(PSObject::Int(self.seed)),
+            // systemdict is built from the operator table, so only a name missing here gets this
+            _ => unreachable!("{} is in the operator table but has no implementation", op),
         }
     }
 
     // PS ARITHMETIC
-    fn op_add(&mut self) -> Result<(), String> {
-        let b = self.op_stack.pop().ok_or("stackunderflow")?;
-        let a = self.op_stack.pop().ok_or("stackunderflow")?;
-        if let (PSObject::Int(a), PSObject::Int(b)) = (a, b) {
-            self.op_stack.push(PSObject::Int(a + b));
-            Ok(())
-        } else {
-            Err("typecheck".into())
+    // an integer result, or the same value as a real when it's too big for the configured
+    // integer width
+    fn int_or_real(&self, n: Option<i64>, real: f64) -> PSObject {
+        match n {
+            Some(n) if self.config.int_width.fits(n) => PSObject::Int(n),
+            _ => PSObject::Real(real),
         }
     }
 
-    fn op_sub(&mut self) -> Result<(), String> {
+    // add, sub and mul: two ints give an int (a real when it doesn't fit), a real on either
+    // side gives a real
+    fn arithmetic(
+        &mut self,
+        int: fn(i64, i64) -> Option<i64>,
+        real: fn(f64, f64) -> f64,
+    ) -> Result<(), String> {
         let b = self.op_stack.pop().ok_or("stackunderflow")?;
         let a = self.op_stack.pop().ok_or("stackunderflow")?;
-        if let (PSObject::Int(a), PSObject::Int(b)) = (a, b) {
-            self.op_stack.push(PSObject::Int(a - b));
-            Ok(())
-        } else {
-            Err("typecheck".into())
+        if let (PSObject::Int(a), PSObject::Int(b)) = (&a, &b) {
+            let result = self.int_or_real(int(*a, *b), real(*a as f64, *b as f64));
+            self.op_stack.push(result);
+            return Ok(());
         }
+        self.push_real(real(number(&a)?, number(&b)?))
+    }
+
+    fn op_add(&mut self) -> Result<(), String> {
+        self.arithmetic(i64::checked_add, |a, b| a + b)
+    }
+
+    fn op_sub(&mut self) -> Result<(), String> {
+        self.arithmetic(i64::checked_sub, |a, b| a - b)
     }
     fn op_mul(&mut self) -> Result<(), String> {
-        let b = self.op_stack.pop().ok_or("stackunderflow")?;
-        let a = self.op_stack.pop().ok_or("stackunderflow")?;
-        if let (PSObject::Int(a), PSObject::Int(b)) = (a, b) {
-            self.op_stack.push(PSObject::Int(a * b));
-            Ok(())
-        } else {
-            Err("typecheck".into())
-        }
+        self.arithmetic(i64::checked_mul, |a, b| a * b)
     }
+    // always a real, even for ints that divide exactly. idiv is the one that truncates
     fn op_div(&mut self) -> Result<(), String> {
-        let b = self.op_stack.pop().ok_or("stackunderflow")?;
-        let a = self.op_stack.pop().ok_or("stackunderflow")?;
-        if let (PSObject::Int(a), PSObject::Int(b)) = (a, b) {
-            if b == 0 {
-                // PostScript error for /0
-                return Err("undefinedresult".into());
-            }
-            self.op_stack.push(PSObject::Int(a / b));
-            Ok(())
-        } else {
-            Err("typecheck".into())
+        let b = self.pop_number()?;
+        let a = self.pop_number()?;
+        if b == 0.0 {
+            // PostScript error for /0
+            return Err("undefinedresult".into());
         }
+        self.push_real(a / b)
     }
+    // the remainder takes the sign of the dividend, which is what rust's % does too.
+    // wrapping_rem because i64::MIN % -1 overflows, though the answer (0) doesn't
     fn op_mod(&mut self) -> Result<(), String> {
         let b = self.op_stack.pop().ok_or("stackunderflow")?;
         let a = self.op_stack.pop().ok_or("stackunderflow")?;
@@ -432,7 +1000,7 @@ impl Interpreter {
             if b == 0 {
                 return Err("undefinedresult".into());
             }
-            self.op_stack.push(PSObject::Int(a % b));
+            self.op_stack.push(PSObject::Int(a.wrapping_rem(b)));
             Ok(())
         } else {
             Err("typecheck".into())
@@ -457,6 +1025,7 @@ impl Interpreter {
     }
 
     fn op_dup(&mut self) -> Result<(), String> {
+        self.charge_copy(0)?;
         if self.op_stack.dup() {
             Ok(())
         } else {
@@ -464,6 +1033,54 @@ impl Interpreter {
         }
     }
 
+    // dup, over and tuck share the object they copy, and popping one of the two separates
+    // them. like ndup's copies, that's charged up front: a slot and the object's VM again
+    fn charge_copy(&mut self, from_top: usize) -> Result<(), String> {
+        let obj = self
+            .op_stack
+            .iter()
+            .rev()
+            .nth(from_top)
+            .ok_or("stackunderflow")?;
+        let bytes = VM_SLOT + obj.vm_size();
+        self.allocate(bytes)
+    }
+
+    // the extensions from forth, the work's all in Stack
+    fn underflow(done: bool) -> Result<(), String> {
+        if done {
+            Ok(())
+        } else {
+            Err("stackunderflow".into())
+        }
+    }
+
+    fn op_ndup(&mut self) -> Result<(), String> {
+        let n = match self.op_stack.pop() {
+            Some(PSObject::Int(i)) => i,
+            _ => return Err("typecheck".into()),
+        };
+        if n < 0 {
+            return Err("rangecheck".into());
+        }
+        // every copy takes a slot, and the top's VM again once popping separates them. that's
+        // charged, and the room reserved, before the stack grows
+        let top = self.op_stack.peek().ok_or("stackunderflow")?;
+        let bytes = (n as usize).saturating_mul(VM_SLOT + top.vm_size());
+        self.allocate(bytes)?;
+        if !self.op_stack.reserve(n as usize) {
+            return Err("VMerror".into());
+        }
+        Self::underflow(self.op_stack.ndup(n))
+    }
+
+    fn op_index(&mut self) -> Result<(), String> {
+        let n = self.pop_int()?;
+        let n = usize::try_from(n).map_err(|_| "rangecheck")?;
+        self.charge_copy(n)?;
+        Self::underflow(self.op_stack.index(n))
+    }
+
     fn op_clear(&mut self) -> Result<(), String> {
         self.op_stack.clear();
         Ok(())
@@ -480,6 +1097,19 @@ impl Interpreter {
             Some(PSObject::Int(i)) => i,
             _ => return Err("typecheck".into()),
         };
+        // the copies are deep, so they're new VM
+        let copied = usize::try_from(n)
+            .ok()
+            .filter(|&n| n <= self.op_stack.iter().len())
+            .ok_or("rangecheck")?;
+        let bytes = self
+            .op_stack
+            .iter()
+            .rev()
+            .take(copied)
+            .map(|o| o.vm_size())
+            .sum();
+        self.allocate(bytes)?;
         if self.op_stack.copy(n) {
             Ok(())
         } else {
@@ -492,11 +1122,15 @@ impl Interpreter {
     // create a new dictionary with specified size
     fn op_dict(&mut self) -> Result<(), String> {
         let n = match self.op_stack.pop() {
-            Some(PSObject::Int(i)) => i as usize,
+            Some(PSObject::Int(i)) if i >= 0 => i as usize,
+            Some(PSObject::Int(_)) => return Err("rangecheck".into()),
             _ => return Err("typecheck".into()),
         };
-        self.op_stack
-            .push(PSObject::Dict(HashMap::with_capacity(n)));
+        // charged for all n entries before anything is reserved, so a huge n is VMerror rather
+        // than an allocation the process can't survive. past DICT_RESERVE it grows as it fills
+        self.allocate(n.saturating_mul(VM_ENTRY))?;
+        let dict = PSObject::Dict(DictMap::with_capacity(n.min(DICT_RESERVE)));
+        self.op_stack.push(dict);
         Ok(())
     }
 
@@ -504,8 +1138,7 @@ impl Interpreter {
     fn op_begin(&mut self) -> Result<(), String> {
         match self.op_stack.pop() {
             Some(PSObject::Dict(d)) => {
-                let parent = self.dict_stack.len() - 1;
-                self.dict_stack.push(Frame { map: d, parent });
+                self.push_scope(d);
                 Ok(())
             }
             _ => Err("typecheck".into()),
@@ -514,10 +1147,10 @@ impl Interpreter {
 
     // pop from the top frame
     fn op_end(&mut self) -> Result<(), String> {
-        if self.dict_stack.len() <= 1 {
+        if self.dict_stack.len() <= USERDICT + 1 {
             Err("dictstackunderflow".into())
         } else {
-            self.dict_stack.pop();
+            self.pop_scope();
             Ok(())
         }
     }
@@ -526,9 +1159,9 @@ impl Interpreter {
     fn op_def(&mut self) -> Result<(), String> {
         let value = self.op_stack.pop().ok_or("stackunderflow")?;
         let key = self.op_stack.pop().ok_or("stackunderflow")?;
-        if let PSObject::Name(name) = key {
-            let frame = self.dict_stack.last_mut().unwrap();
-            frame.map.insert(name, value);
+        if let PSObject::Name(name, _) = key {
+            let frame = self.dict_stack.last().unwrap();
+            frame.map.borrow_mut().insert(name, value);
             Ok(())
         } else {
             Err("typecheck".into())
@@ -539,9 +1172,11 @@ impl Interpreter {
     fn op_length(&mut self) -> Result<(), String> {
         let obj = self.op_stack.pop().ok_or("stackunderflow")?;
         let len = match obj {
-            PSObject::String(s) => s.chars().count() as i32,
-            PSObject::Array(a) => a.len() as i32,
-            PSObject::Dict(d) => d.len() as i32,
+            // bytes, like get and getinterval count. utf8length counts characters
+            PSObject::String(s, _) => s.len() as i64,
+            PSObject::Array(a) | PSObject::PackedArray(a) => a.len() as i64,
+            PSObject::Dict(d) => d.len() as i64,
+            PSObject::Name(n, _) => n.len() as i64,
             _ => return Err("typecheck".into()),
         };
         self.op_stack.push(PSObject::Int(len));
@@ -554,23 +1189,34 @@ impl Interpreter {
     }
 
     // string index get OR array index get
+    // or dict key get
     fn op_get(&mut self) -> Result<(), String> {
-        let idx = match self.op_stack.pop().ok_or("stackunderflow")? {
+        let key = self.op_stack.pop().ok_or("stackunderflow")?;
+        let obj = self.op_stack.pop().ok_or("stackunderflow")?;
+        if let PSObject::Dict(d) = obj {
+            let value = match key {
+                PSObject::Name(k, _) => d.get(&k).ok_or("undefined")?.clone(),
+                PSObject::String(k, _) => d.get(&*utf8(k)?).ok_or("undefined")?.clone(),
+                _ => return Err("typecheck".into()),
+            };
+            self.op_stack.push(value);
+            return Ok(());
+        }
+        let idx = match key {
             PSObject::Int(i) if i >= 0 => i as usize,
             _ => return Err("typecheck".into()),
         };
-        let obj = self.op_stack.pop().ok_or("stackunderflow")?;
         match obj {
-            PSObject::String(s) => {
+            PSObject::String(s, _) => {
                 if idx < s.len() {
-                    let byte = s.as_bytes()[idx];
-                    self.op_stack.push(PSObject::Int(byte as i32));
+                    let byte = s[idx];
+                    self.op_stack.push(PSObject::Int(byte as i64));
                     Ok(())
                 } else {
                     Err("rangecheck".into())
                 }
             }
-            PSObject::Array(a) => {
+            PSObject::Array(a) | PSObject::PackedArray(a) => {
                 if idx < a.len() {
                     self.op_stack.push(a[idx].clone());
                     Ok(())
@@ -582,31 +1228,161 @@ impl Interpreter {
         }
     }
 
-    // gets the current interval
-    fn op_getinterval(&mut self) -> Result<(), String> {
-        let count = match self.op_stack.pop().ok_or("stackunderflow")? {
-            PSObject::Int(i) if i >= 0 => i as usize,
-            _ => return Err("typecheck".into()),
+    // strings are values, so rather than being written into string this pushes the text,
+    // which is the substring cvs would have returned
+    fn op_cvs(&mut self) -> Result<(), String> {
+        let PSObject::String(room, _) = self.op_stack.pop().ok_or("stackunderflow")? else {
+            return Err("typecheck".into());
         };
-        let idx = match self.op_stack.pop().ok_or("stackunderflow")? {
-            PSObject::Int(i) if i >= 0 => i as usize,
+        let text = self
+            .op_stack
+            .pop()
+            .ok_or("stackunderflow")?
+            .cvs(self.config.real_digits);
+        if text.len() > room.len() {
+            return Err("rangecheck".into());
+        }
+        self.op_stack.push(PSObject::String(text, false));
+        Ok(())
+    }
+
+    fn now(&self) -> DateTime {
+        let fixed = match self.config.fixed_time {
+            None if self.config.deterministic => Some(0),
+            fixed => fixed,
+        };
+        DateTime::from_unix(fixed.unwrap_or_else(|| self.clock.now()))
+    }
+
+    // for realtime and usertime. there's no telling cpu time from waiting here, so both are
+    // the time since the interpreter started
+    fn millis(&self) -> i64 {
+        if self.config.deterministic {
+            return 0;
+        }
+        (self.started.elapsed().as_millis() as i64) & i32::MAX as i64
+    }
+
+    // any integer seeds rand, folded into the range its state stays in (so what rrand gave
+    // back puts it where it was)
+    fn set_seed(&mut self, seed: i64) {
+        self.seed = seed.rem_euclid(i32::MAX as i64).max(1);
+    }
+
+    // the usage line (a stack diagram) and description from the operator table, the same
+    // thing the repl's :help shows
+    fn op_help(&mut self) -> Result<(), String> {
+        let name = self.pop_key()?;
+        let name = name.trim_start_matches('/');
+        let text = match operators::info(name) {
+            Some(op) => format!("{}\n  {}\n", op.usage, op.description),
+            None => format!("no help for {}\n", name),
+        };
+        self.emit(StdFile::Stdout, text.as_bytes())
+    }
+
+    fn op_getenv(&mut self) -> Result<(), String> {
+        let name = match self.op_stack.pop().ok_or("stackunderflow")? {
+            PSObject::String(s, _) => utf8(s)?,
+            PSObject::Name(n, _) => n,
             _ => return Err("typecheck".into()),
         };
-        let obj = self.op_stack.pop().ok_or("stackunderflow")?;
-        match obj {
-            PSObject::String(s) => {
-                if idx + count <= s.len() {
-                    let substr = s[idx..idx + count].to_string();
-                    self.op_stack.push(PSObject::String(substr));
+        if !self.config.security.allows_env() {
+            return Err("invalidaccess".into());
+        }
+        match std::env::var(&name) {
+            Ok(value) => {
+                self.op_stack
+                    .push(PSObject::String(value.into_bytes(), false));
+                self.op_stack.push(PSObject::Bool(true));
+            }
+            Err(_) => self.op_stack.push(PSObject::Bool(false)),
+        }
+        Ok(())
+    }
+
+    // the UTF-8 operators count characters where get and getinterval count bytes
+    fn op_utf8get(&mut self) -> Result<(), String> {
+        let index = self.pop_int()?;
+        let s = self.pop_string()?;
+        let c = usize::try_from(index)
+            .ok()
+            .and_then(|i| s.chars().nth(i))
+            .ok_or("rangecheck")?;
+        self.push_constant(PSObject::Int(c as i64))
+    }
+
+    fn op_utf8encode(&mut self) -> Result<(), String> {
+        let points = match self.op_stack.pop().ok_or("stackunderflow")? {
+            PSObject::Array(a) | PSObject::PackedArray(a) => a,
+            _ => return Err("typecheck".into()),
+        };
+        let mut s = String::new();
+        for point in points {
+            let PSObject::Int(n) = point else {
+                return Err("typecheck".into());
+            };
+            // surrogates and numbers past U+10FFFF aren't characters
+            let c = u32::try_from(n)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or("rangecheck")?;
+            s.push(c);
+        }
+        self.push_constant(PSObject::String(s.into_bytes(), false))
+    }
+
+    fn op_format(&mut self) -> Result<(), String> {
+        let args = match self.op_stack.pop().ok_or("stackunderflow")? {
+            PSObject::Array(a) | PSObject::PackedArray(a) => a,
+            _ => return Err("typecheck".into()),
+        };
+        let PSObject::String(template, _) = self.op_stack.pop().ok_or("stackunderflow")? else {
+            return Err("typecheck".into());
+        };
+        // the template is text, bytes that aren't UTF-8 in it become U+FFFD
+        let text = printf::sprintf(&String::from_utf8_lossy(&template), &args)?;
+        self.allocate(text.len())?;
+        self.op_stack
+            .push(PSObject::String(text.into_bytes(), false));
+        Ok(())
+    }
+
+    // strings and arrays are values here, so the interval is a copy rather than a view into
+    // the original: nothing (putinterval and cvs included) writes into an object in place
+    fn op_getinterval(&mut self) -> Result<(), String> {
+        let count = match self.op_stack.pop().ok_or("stackunderflow")? {
+            PSObject::Int(i) if i >= 0 => i as usize,
+            _ => return Err("typecheck".into()),
+        };
+        let idx = match self.op_stack.pop().ok_or("stackunderflow")? {
+            PSObject::Int(i) if i >= 0 => i as usize,
+            _ => return Err("typecheck".into()),
+        };
+        let obj = self.op_stack.pop().ok_or("stackunderflow")?;
+        match obj {
+            // offsets count bytes
+            PSObject::String(s, _) => match s.get(idx..idx.saturating_add(count)) {
+                Some(substr) => {
+                    self.op_stack.push(PSObject::String(substr.to_vec(), false));
+                    Ok(())
+                }
+                None => Err("rangecheck".into()),
+            },
+            PSObject::Array(a) => {
+                if idx + count <= a.len() {
+                    let slice = a[idx..idx + count].to_vec();
+                    self.op_stack.push(PSObject::Array(slice));
                     Ok(())
                 } else {
                     Err("rangecheck".into())
                 }
             }
-            PSObject::Array(a) => {
+            // a piece of a packed array is packed too
+            PSObject::PackedArray(a) => {
                 if idx + count <= a.len() {
                     let slice = a[idx..idx + count].to_vec();
-                    self.op_stack.push(PSObject::Array(slice));
+                    self.op_stack.push(PSObject::PackedArray(slice));
                     Ok(())
                 } else {
                     Err("rangecheck".into())
@@ -626,10 +1402,10 @@ impl Interpreter {
         };
         let dest = self.op_stack.pop().ok_or("stackunderflow")?;
         match (dest, src) {
-            (PSObject::String(mut d), PSObject::String(s)) => {
+            (PSObject::String(mut d, exec), PSObject::String(s, _)) => {
                 if idx + s.len() <= d.len() {
-                    d.replace_range(idx..idx + s.len(), &s);
-                    self.op_stack.push(PSObject::String(d));
+                    d[idx..idx + s.len()].copy_from_slice(&s);
+                    self.op_stack.push(PSObject::String(d, exec));
                     Ok(())
                 } else {
                     Err("rangecheck".into())
@@ -637,42 +1413,110 @@ impl Interpreter {
             }
             (PSObject::Array(mut d), PSObject::Array(s)) => {
                 if idx + s.len() <= d.len() {
-                    for i in 0..s.len() {
-                        d[idx + i] = s[i].clone();
-                    }
+                    d[idx..idx + s.len()].clone_from_slice(&s);
                     self.op_stack.push(PSObject::Array(d));
                     Ok(())
                 } else {
                     Err("rangecheck".into())
                 }
             }
+            (PSObject::PackedArray(_), _) => Err("invalidaccess".into()),
             _ => Err("typecheck".into()),
         }
     }
 
     // PRINTING LOGIC
-    // prints top of stack without new line
+    // prints top of stack with new line, strings as their text
     fn op_equals(&mut self) -> Result<(), String> {
         let obj = self.op_stack.pop().ok_or("stackunderflow")?;
-        println!("{:?}", obj); // keeps newline
-        std::io::Write::flush(&mut std::io::stdout()).unwrap();
-        Ok(())
+        let mut text = match obj {
+            PSObject::String(s, _) => s,
+            PSObject::Name(n, _) => n.into_bytes(),
+            other => other.to_text(self.config.real_digits).into_bytes(),
+        };
+        text.push(b'\n');
+        self.emit(StdFile::Stdout, &text)
     }
 
-    // prints top of stack with new line
+    // prints top of stack without new line, in the syntax that would read it back
     fn op_eqeq(&mut self) -> Result<(), String> {
         let obj = self.op_stack.pop().ok_or("stackunderflow")?;
-        print!("{:?}", obj); // no newline
-        std::io::Write::flush(&mut std::io::stdout()).unwrap();
-        Ok(())
+        let text = obj.to_text(self.config.real_digits);
+        self.emit(StdFile::Stdout, text.as_bytes())
     }
 
     // consumes string and prints it without any new line
     fn op_print(&mut self) -> Result<(), String> {
         match self.op_stack.pop() {
-            Some(PSObject::String(s)) => {
-                print!("{s}"); // NO println!()
-                std::io::Write::flush(&mut std::io::stdout()).unwrap();
+            Some(PSObject::String(s, _)) => self.emit(StdFile::Stdout, &s),
+            Some(_) => Err("typecheck".into()),
+            None => Err("stackunderflow".into()),
+        }
+    }
+
+    // FILES
+    // (%stderr) (w) file -> file, or (name) (r) file to read one on disk. it's read in whole
+    // when it's opened, the same as runlibfile does
+    fn op_file(&mut self) -> Result<(), String> {
+        let access = self.op_stack.pop().ok_or("stackunderflow")?;
+        let name = self.op_stack.pop().ok_or("stackunderflow")?;
+        let (PSObject::String(name, _), PSObject::String(access, _)) = (name, access) else {
+            return Err("typecheck".into());
+        };
+        let (name, access) = (utf8(name)?, utf8(access)?);
+        let file = if access == "r" && !name.starts_with('%') {
+            let bytes = self.read_file(Path::new(&name))?;
+            PSFile::Input(Rc::new(RefCell::new(InputFile::new(bytes))))
+        } else {
+            PSFile::Std(StdFile::open(&name, &access)?)
+        };
+        self.op_stack.push(PSObject::File(file));
+        Ok(())
+    }
+
+    // a file to write to, which a file opened for reading isn't
+    fn pop_file(&mut self) -> Result<StdFile, String> {
+        match self.op_stack.pop() {
+            Some(PSObject::File(PSFile::Std(f))) => Ok(f),
+            Some(PSObject::File(PSFile::Input(_))) => Err("invalidaccess".into()),
+            Some(_) => Err("typecheck".into()),
+            None => Err("stackunderflow".into()),
+        }
+    }
+
+    // file string writestring
+    fn op_writestring(&mut self) -> Result<(), String> {
+        let s = match self.op_stack.pop() {
+            Some(PSObject::String(s, _)) => s,
+            Some(_) => return Err("typecheck".into()),
+            None => return Err("stackunderflow".into()),
+        };
+        let file = self.pop_file()?;
+        self.emit(file, &s)
+    }
+
+    // file int write, writes the low 8 bits as one byte
+    fn op_write(&mut self) -> Result<(), String> {
+        let byte = self.pop_int()?;
+        let file = self.pop_file()?;
+        self.emit(file, &[byte as u8])
+    }
+
+    fn op_flush(&mut self) -> Result<(), String> {
+        self.output
+            .flush(StdFile::Stdout)
+            .map_err(|_| "ioerror".into())
+    }
+
+    fn op_flushfile(&mut self) -> Result<(), String> {
+        let file = self.pop_file()?;
+        self.output.flush(file).map_err(|_| "ioerror".into())
+    }
+
+    fn op_echo(&mut self) -> Result<(), String> {
+        match self.op_stack.pop() {
+            Some(PSObject::Bool(b)) => {
+                self.echo = b;
                 Ok(())
             }
             Some(_) => Err("typecheck".into()),
@@ -685,27 +1529,35 @@ impl Interpreter {
     fn op_eq(&mut self) -> Result<(), String> {
         let b = self.op_stack.pop().ok_or("stackunderflow")?;
         let a = self.op_stack.pop().ok_or("stackunderflow")?;
-        self.op_stack.push(PSObject::Bool(a == b));
+        self.op_stack.push(PSObject::Bool(a.deep_eq(&b)));
         Ok(())
     }
 
     fn op_ne(&mut self) -> Result<(), String> {
         let b = self.op_stack.pop().ok_or("stackunderflow")?;
         let a = self.op_stack.pop().ok_or("stackunderflow")?;
-        self.op_stack.push(PSObject::Bool(a != b));
+        self.op_stack.push(PSObject::Bool(!a.deep_eq(&b)));
         Ok(())
     }
+    // how the top two numbers compare, ints and reals alike. NaN has no order, so comparing
+    // it is undefinedresult
+    fn compare(&mut self) -> Result<Ordering, String> {
+        let b = self.pop_number()?;
+        let a = self.pop_number()?;
+        a.partial_cmp(&b).ok_or_else(|| "undefinedresult".into())
+    }
+
     fn op_gt(&mut self) -> Result<(), String> {
-        cmp_int!(self, >)
+        cmp_num!(self, >)
     }
     fn op_lt(&mut self) -> Result<(), String> {
-        cmp_int!(self, <)
+        cmp_num!(self, <)
     }
     fn op_ge(&mut self) -> Result<(), String> {
-        cmp_int!(self, >=)
+        cmp_num!(self, >=)
     }
     fn op_le(&mut self) -> Result<(), String> {
-        cmp_int!(self, <=)
+        cmp_num!(self, <=)
     }
     fn op_and(&mut self) -> Result<(), String> {
         let b = self.op_stack.pop().ok_or("stackunderflow")?;
@@ -740,7 +1592,7 @@ impl Interpreter {
     }
 
     // helper function for for loop
-    fn pop_int(&mut self) -> Result<i32, String> {
+    fn pop_int(&mut self) -> Result<i64, String> {
         match self.op_stack.pop() {
             Some(PSObject::Int(n)) => Ok(n),
             Some(_) => Err("typecheck".into()),
@@ -748,26 +1600,47 @@ impl Interpreter {
         }
     }
 
+    // an int or real, as a real
+    fn pop_number(&mut self) -> Result<f64, String> {
+        match self.op_stack.pop() {
+            Some(PSObject::Int(n)) => Ok(n as f64),
+            Some(PSObject::Real(r)) => Ok(r),
+            Some(_) => Err("typecheck".into()),
+            None => Err("stackunderflow".into()),
+        }
+    }
+
     // CONTROL STATEMENTS
     fn op_if(&mut self) -> Result<(), String> {
+        match self.pick_if()? {
+            Some(proc) => self.exec_proc(proc),
+            None => Ok(()),
+        }
+    }
+
+    fn op_ifelse(&mut self) -> Result<(), String> {
+        let proc = self.pick_ifelse()?;
+        self.exec_proc(proc)
+    }
+
+    // take if's operands, giving back the procedure to run if there's one. split from op_if
+    // so a tail call can run it in place (same for ifelse and exec)
+    fn pick_if(&mut self) -> Result<Option<PSObject>, String> {
         let proc = self.op_stack.pop().ok_or("stackunderflow")?;
         let cond = self.op_stack.pop().ok_or("stackunderflow")?;
         if let PSObject::Bool(b) = cond {
-            if b {
-                self.exec_proc(proc)?;
-            }
-            Ok(())
+            Ok(b.then_some(proc))
         } else {
             Err("typecheck".into())
         }
     }
 
-    fn op_ifelse(&mut self) -> Result<(), String> {
+    fn pick_ifelse(&mut self) -> Result<PSObject, String> {
         let proc_f = self.op_stack.pop().ok_or("stackunderflow")?;
         let proc_t = self.op_stack.pop().ok_or("stackunderflow")?;
         let cond = self.op_stack.pop().ok_or("stackunderflow")?;
         if let PSObject::Bool(b) = cond {
-            self.exec_proc(if b { proc_t } else { proc_f })
+            Ok(if b { proc_t } else { proc_f })
         } else {
             Err("typecheck".into())
         }
@@ -785,15 +1658,39 @@ impl Interpreter {
         Ok(())
     }
 
+    // the control variable is an integer when initial and increment are, whatever the limit
+    // is, and a real otherwise
     fn op_for(&mut self) -> Result<(), String> {
         let proc = self.op_stack.pop().ok_or("stackunderflow")?;
-        let limit = self.pop_int()?;
-        let inc = self.pop_int()?;
-        let mut var = self.pop_int()?;
+        let limit = self.op_stack.pop().ok_or("stackunderflow")?;
+        let inc = self.op_stack.pop().ok_or("stackunderflow")?;
+        let initial = self.op_stack.pop().ok_or("stackunderflow")?;
+        let (PSObject::Int(initial), PSObject::Int(inc)) = (&initial, &inc) else {
+            let (initial, inc, limit) = (number(&initial)?, number(&inc)?, number(&limit)?);
+            return self.for_reals(initial, inc, limit, proc);
+        };
+        // the last integer the limit lets in, and none at all for NaN
+        let limit = match limit {
+            PSObject::Int(l) => l,
+            PSObject::Real(l) if l.is_nan() => return Ok(()),
+            PSObject::Real(l) if *inc > 0 => l.floor() as i64,
+            PSObject::Real(l) => l.ceil() as i64,
+            _ => return Err("typecheck".into()),
+        };
+        self.for_ints(*initial, *inc, limit, proc)
+    }
+
+    fn for_ints(
+        &mut self,
+        mut var: i64,
+        inc: i64,
+        limit: i64,
+        proc: PSObject,
+    ) -> Result<(), String> {
         if inc == 0 {
             return Err("rangecheck".into());
         }
-        let cmp: fn(i32, i32) -> bool = if inc > 0 {
+        let cmp: fn(i64, i64) -> bool = if inc > 0 {
             |v, l| v <= l
         } else {
             |v, l| v >= l
@@ -801,90 +1698,410 @@ impl Interpreter {
         while cmp(var, limit) {
             self.op_stack.push(PSObject::Int(var));
             self.exec_proc(proc.clone())?;
-            var += inc;
+            // past the end of the integers is past the limit too
+            match var.checked_add(inc) {
+                Some(next) => var = next,
+                None => break,
+            }
         }
         Ok(())
     }
 
-    // lookup function in order to help lexical scoping
-    fn lookup_from(&self, mut idx: usize, name: &str) -> Option<PSObject> {
-        loop {
-            let frame = &self.dict_stack[idx];
-            if let Some(v) = frame.map.get(name) {
-                return Some(v.clone());
+    // each value is worked out from the start rather than by adding up increments, so the
+    // rounding error doesn't build up: 0 .1 1 ends on 1.0 instead of 0.9999999999999999
+    fn for_reals(
+        &mut self,
+        initial: f64,
+        inc: f64,
+        limit: f64,
+        proc: PSObject,
+    ) -> Result<(), String> {
+        if inc == 0.0 {
+            return Err("rangecheck".into());
+        }
+        for n in 0u64.. {
+            let var = initial + n as f64 * inc;
+            let more = if inc > 0.0 {
+                var <= limit
+            } else {
+                var >= limit
+            };
+            if !more {
+                break;
+            }
+            self.op_stack.push(PSObject::Real(var));
+            self.exec_proc(proc.clone())?;
+        }
+        Ok(())
+    }
+
+    // push each element and run proc: bytes of a string as ints, and key then value for a dict
+    fn op_forall(&mut self) -> Result<(), String> {
+        let proc = self.op_stack.pop().ok_or("stackunderflow")?;
+        let obj = self.op_stack.pop().ok_or("stackunderflow")?;
+        if !matches!(proc, PSObject::Procedure { .. }) {
+            return Err("typecheck".into());
+        }
+        let rounds: Vec<Vec<PSObject>> = match obj {
+            PSObject::Array(a) | PSObject::PackedArray(a) => {
+                a.into_iter().map(|o| vec![o]).collect()
             }
-            if frame.parent == idx {
-                // hit bottom (the system dict)
-                return None;
+            PSObject::String(s, _) => s.iter().map(|&b| vec![PSObject::Int(b as i64)]).collect(),
+            PSObject::Dict(d) => d
+                .into_iter()
+                .map(|(k, v)| vec![PSObject::Name(k, false), v])
+                .collect(),
+            _ => return Err("typecheck".into()),
+        };
+        for round in rounds {
+            for o in round {
+                self.op_stack.push(o);
             }
-            idx = frame.parent; // follow static-link
+            self.exec_proc(proc.clone())?;
+        }
+        Ok(())
+    }
+
+    // push a dictionary onto the dictionary stack, linked to the one under it
+    fn push_scope(&mut self, map: DictMap) {
+        let parent = self.dict_stack.last().cloned();
+        self.dict_stack.push(Scope::new(map, parent));
+    }
+
+    fn pop_scope(&mut self) {
+        if let Some(frame) = self.dict_stack.pop() {
+            self.retire(frame);
+        }
+    }
+
+    // a frame off the dictionary stack usually goes right away. one a closure still links to
+    // may only be kept alive by closures defined in it, so it's a suspect for the next sweep
+    fn retire(&mut self, frame: Rc<Scope>) {
+        if Rc::strong_count(&frame) > 1 {
+            self.suspects.push(Rc::downgrade(&frame));
         }
+        drop(frame);
+        if self.suspects.len() >= self.sweep_at {
+            self.sweep_scopes();
+        }
+    }
+
+    fn sweep_scopes(&mut self) {
+        let suspects = self
+            .suspects
+            .drain(..)
+            .filter_map(|s| s.upgrade())
+            .collect();
+        let alive = object::sweep(suspects, &self.dict_stack);
+        self.suspects = alive.iter().map(Rc::downgrade).collect();
+        self.sweep_at = (2 * self.suspects.len()).max(SWEEP_AFTER);
     }
 
     // executes our current object based on lexical / dynamic
     // our run function runs this
-    fn execute_object(&mut self, obj: PSObject, start_from: usize) -> Result<(), String> {
+    fn execute_object(&mut self, obj: PSObject) -> Result<(), String> {
+        self.step()?;
         match obj {
-            // check for operator
-            PSObject::Name(ref n) if self.is_operator(n) => self.execute_operator(n),
-
             // check for function declare
-            PSObject::Name(ref n) if n.starts_with('/') => {
-                self.op_stack.push(PSObject::Name(n[1..].to_string()));
+            PSObject::Name(n, false) => {
+                self.explain(|| format!("push /{}", n));
+                self.op_stack.push(PSObject::Name(n, false));
                 Ok(())
             }
 
-            // checks whether or not its lexical/ dyanmic
-            PSObject::Name(ref n) => {
-                // pick lookup strategy depending on current scoping mode
-                let val = if self.scoping == Scoping::Dynamic {
-                    self.lookup_name(n) // dynamic search
-                } else {
-                    self.lookup_from(start_from, n) // lexical/static search
-                };
-                val.ok_or_else(|| format!("undefined name {}", n))
-                    .map(|v| self.op_stack.push(v))
+            // everything else goes through the dictionary stack, operators included since
+            // they live in systemdict. checks whether or not its lexical/ dyanmic
+            PSObject::Name(ref n, true) => {
+                self.command = Some(n.clone());
+                match self.lookup_name(n).ok_or("undefined")? {
+                    PSObject::Operator(op) => self.call_operator(&op),
+                    proc @ PSObject::Procedure { .. } => {
+                        self.explain(|| format!("call {}", n));
+                        self.exec_proc(proc)
+                    }
+                    // bound to an executable name or string, which runs in turn
+                    obj @ (PSObject::Name(_, true) | PSObject::String(_, true)) => {
+                        self.exec_proc(PSObject::Procedure {
+                            code: vec![obj],
+                            env: None,
+                        })
+                    }
+                    v => {
+                        self.explain(|| format!("name lookup /{} → {}", n, v));
+                        self.allocate(v.vm_size())?;
+                        self.op_stack.push(v);
+                        Ok(())
+                    }
+                }
+            }
+
+            PSObject::Operator(ref op) => {
+                self.command = Some(op.clone());
+                self.call_operator(op)
+            }
+
+            // procedure literals made under lexical scoping close over the scope they're made
+            // in, which is the top of the dictionary stack (inside a lexical procedure that's
+            // its own frame, so closures nest)
+            PSObject::Procedure { code, .. } => {
+                let proc = self.closure(code);
+                self.explain(|| format!("push {}", proc));
+                self.allocate(proc.vm_size())?;
+                self.op_stack.push(proc);
+                Ok(())
+            }
+
+            // an executable string runs as the program it holds, scanned now
+            PSObject::String(src, true) => {
+                let code = Scanner::from_bytes(&src)
+                    .binary_tokens(self.config.binary_tokens)
+                    .int_width(self.config.int_width)
+                    .max_depth(self.config.max_nesting)
+                    .collect::<Result<_, _>>()?;
+                let proc = self.closure(code);
+                self.exec_proc(proc)
             }
 
-            // PSObject literals
+            // PSObject literals. composites are values, so each push (a procedure's too, every
+            // time it runs) is a new one that counts against VM
             other => {
+                self.explain(|| format!("push {}", other));
+                self.allocate(other.vm_size())?;
                 self.op_stack.push(other);
                 Ok(())
             }
         }
     }
 
-    // helper function to help execute
+    fn closure(&self, code: Vec<PSObject>) -> PSObject {
+        let env = match self.scoping {
+            Scoping::Dynamic => None,
+            Scoping::Lexical => self.dict_stack.last().cloned(),
+        };
+        PSObject::Procedure { code, env }
+    }
+
+    fn step(&mut self) -> Result<(), String> {
+        self.steps += 1;
+        match self.config.max_steps {
+            Some(max) if self.steps > max => Err("timeout".into()),
+            _ => Ok(()),
+        }
+    }
+
+    // for operators that just push a value
+    fn push_constant(&mut self, obj: PSObject) -> Result<(), String> {
+        self.op_stack.push(obj);
+        Ok(())
+    }
+
+    // run an operator found in systemdict, whether built in or from define_operator
+    fn call_operator(&mut self, op: &str) -> Result<(), String> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("operator", name = op).entered();
+        // what it's given, for the report if it fails, the way ghostscript leaves the
+        // operands of an operator that fails on the stack
+        self.op_stack.mark();
+        let result = if self.explainer.is_some() {
+            self.explain_operator(op)
+        } else if self.host_ops.contains_key(op) {
+            self.call_host_operator(op)
+        } else {
+            self.execute_operator(op)
+        };
+        if result.is_err() {
+            self.record_operands(true);
+        }
+        result
+    }
+
+    // the innermost place an error passes through saves the operands: an operator's as they
+    // were before it ran, anything else's as they are
+    fn record_operands(&mut self, before_operator: bool) {
+        if self.failed_operands.is_none() {
+            self.failed_operands = Some(if before_operator {
+                self.op_stack.marked()
+            } else {
+                self.op_stack.clone()
+            });
+        }
+    }
+
+    // teaching mode's view of an operator: what it took off the stack and what it left. the
+    // table's arity says how much it takes, and anything above what's left of the stack
+    // after that is what it pushed
+    fn explain_operator(&mut self, op: &str) -> Result<(), String> {
+        let arity = match self.host_ops.get(op) {
+            Some((arity, _)) => *arity,
+            None => operators::info(op).map_or(0, |info| info.arity),
+        };
+        let depth = self.op_stack.iter().len();
+        let taken = depth.min(arity);
+        let below = depth - taken;
+        let popped = self.top_of_stack(taken);
+        // steps inside it (the body of a for, say) are explained as they happen, before this
+        if self.host_ops.contains_key(op) {
+            self.call_host_operator(op)?;
+        } else {
+            self.execute_operator(op)?;
+        }
+        let pushed = self.top_of_stack(self.op_stack.iter().len().saturating_sub(below));
+        let mut parts = vec![op.to_string()];
+        if !popped.is_empty() {
+            parts.push(format!("pops {}", listing(&popped)));
+        }
+        if !pushed.is_empty() {
+            parts.push(format!("pushes {}", listing(&pushed)));
+        }
+        let text = match parts.len() {
+            1 => parts.remove(0),
+            _ => format!("{}: {}", parts[0], parts[1..].join(", ")),
+        };
+        self.explain(|| text);
+        Ok(())
+    }
+
+    // the top n operands as text, bottom first
+    fn top_of_stack(&self, n: usize) -> Vec<String> {
+        let skip = self.op_stack.iter().len() - n;
+        self.op_stack
+            .iter()
+            .skip(skip)
+            .map(|o| o.to_string())
+            .collect()
+    }
+
+    // helper function to help execute. a procedure whose last act is calling another (by
+    // name, or through if, ifelse or exec) is replaced by it rather than calling it, so
+    // recursion in tail position runs in constant rust stack
     fn exec_proc(&mut self, proc_obj: PSObject) -> Result<(), String> {
-        if let PSObject::Procedure { code, env } = proc_obj {
-            // Will we push the snapshot?
-            let mut pushed = false;
-            let mut env_idx = self.dict_stack.len() - 1; // current top
-
-            if self.scoping == Scoping::Lexical {
-                // 1) push the captured frame
-                let parent = env_idx;
-                self.dict_stack.push(Frame {
-                    map: env.clone(),
-                    parent,
-                });
-                env_idx = self.dict_stack.len() - 1;
-                pushed = true;
+        // calls that aren't tail calls nest on the rust stack, so stop well before it runs out
+        if self.calls >= self.config.max_call_depth {
+            return Err("execstackoverflow".into());
+        }
+        self.calls += 1;
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("procedure", depth = self.calls).entered();
+        self.call_chain.push(self.caller());
+        let result = self.run_proc(proc_obj);
+        // the innermost call an error passes through knows the whole chain
+        if result.is_err() && self.failed_chain.is_none() {
+            self.failed_chain = Some(self.call_chain.clone());
+        }
+        if result.is_err() {
+            self.record_operands(false);
+        }
+        self.call_chain.pop();
+        self.calls -= 1;
+        result
+    }
+
+    // how the execution stack shows what's calling a procedure: the name it was called by,
+    // or the operator running it
+    fn caller(&self) -> String {
+        match &self.command {
+            Some(op) if operators::info(op).is_some() => format!("--{}--", op),
+            Some(name) => name.clone(),
+            None => "--exec--".into(),
+        }
+    }
+
+    fn run_proc(&mut self, mut proc_obj: PSObject) -> Result<(), String> {
+        loop {
+            // an empty procedure still takes a step, or {} loop would never run out
+            self.step()?;
+            let PSObject::Procedure { mut code, env } = proc_obj else {
+                return Err("typecheck".into());
+            };
+            // 1) a lexical procedure gets a fresh frame for its own definitions, linked to
+            // the scope it was made in rather than to whatever called it
+            let lexical = env.is_some();
+            if let Some(env) = env {
+                self.dict_stack.push(Scope::new(DictMap::new(), Some(env)));
             }
 
-            // 2) execute
-            for obj in code {
-                self.execute_object(obj, env_idx)?;
+            // 2) execute, in the procedure's own scoping
+            let caller = self.running.replace(if lexical {
+                Scoping::Lexical
+            } else {
+                Scoping::Dynamic
+            });
+            let last = code.pop();
+            let result = code
+                .into_iter()
+                .try_for_each(|obj| self.execute_object(obj))
+                .and_then(|()| match last {
+                    Some(obj) => self.tail_call(obj),
+                    None => Ok(None),
+                });
+            self.running = caller;
+
+            // 3) pop the frame if we pushed it, before the tail call runs in its place and
+            // before an error leaves, so its definitions don't leak into the caller
+            if lexical {
+                self.pop_scope();
+            }
+            match result? {
+                Some(callee) => {
+                    // it takes the caller's place on the execution stack too
+                    let caller = self.caller();
+                    if let Some(top) = self.call_chain.last_mut() {
+                        *top = caller;
+                    }
+                    proc_obj = callee
+                }
+                None => return Ok(()),
             }
+        }
+    }
 
-            // 3) pop the temp frame if we pushed it
-            if pushed {
-                self.dict_stack.pop();
+    // run the last object of a procedure, but hand back the procedure it would call instead
+    // of calling it
+    fn tail_call(&mut self, obj: PSObject) -> Result<Option<PSObject>, String> {
+        let op = match obj {
+            PSObject::Name(ref n, true) => {
+                self.command = Some(n.clone());
+                match self.lookup_name(n).ok_or("undefined")? {
+                    proc @ PSObject::Procedure { .. } => {
+                        self.explain(|| format!("call {} (in place of the caller)", n));
+                        return Ok(Some(proc));
+                    }
+                    obj @ (PSObject::Name(_, true) | PSObject::String(_, true)) => {
+                        return Ok(Some(PSObject::Procedure {
+                            code: vec![obj],
+                            env: None,
+                        }));
+                    }
+                    PSObject::Operator(op) => op,
+                    v => {
+                        self.allocate(v.vm_size())?;
+                        self.op_stack.push(v);
+                        return Ok(None);
+                    }
+                }
             }
-            Ok(())
-        } else {
-            Err("typecheck".into())
+            PSObject::Operator(op) => {
+                self.command = Some(op.clone());
+                op
+            }
+            other => return self.execute_object(other).map(|()| None),
+        };
+        if self.host_ops.contains_key(&op) {
+            return self.call_operator(&op).map(|()| None);
         }
+        self.op_stack.mark();
+        let picked = match op.as_str() {
+            "if" => self.pick_if(),
+            "ifelse" => self.pick_ifelse().map(Some),
+            "exec" => self.pick_exec().map(Some),
+            _ => return self.call_operator(&op).map(|()| None),
+        };
+        let next = picked.inspect_err(|_| self.record_operands(true))?;
+        self.explain(|| match &next {
+            Some(proc) => format!("{}: runs {} in place of the caller", op, proc),
+            None => format!("{}: condition is false", op),
+        });
+        Ok(next)
     }
 
     fn op_idiv(&mut self) -> Result<(), String> {
@@ -893,47 +2110,2046 @@ impl Interpreter {
             return Err("undefinedresult".into());
         }
         let a = self.pop_int()?; // dividend
-        self.op_stack.push(PSObject::Int(a / b)); // trunc toward 0
+
+        // trunc toward 0. the most negative int / -1 is the one quotient that doesn't fit
+        let q = a
+            .checked_div(b)
+            .filter(|&q| self.config.int_width.fits(q))
+            .ok_or("undefinedresult")?;
+        self.op_stack.push(PSObject::Int(q));
         Ok(())
     }
 
     // MORE ARITHMETIC FUNCTIONS
+    // abs and neg keep the operand's type, except for the int whose result doesn't fit
+    fn unary(&mut self, int: fn(i64) -> Option<i64>, real: fn(f64) -> f64) -> Result<(), String> {
+        let result = match self.op_stack.pop().ok_or("stackunderflow")? {
+            PSObject::Int(n) => self.int_or_real(int(n), real(n as f64)),
+            PSObject::Real(r) => PSObject::Real(real(r)),
+            _ => return Err("typecheck".into()),
+        };
+        self.op_stack.push(result);
+        Ok(())
+    }
     fn op_abs(&mut self) -> Result<(), String> {
-        unary_int!(self, |n: i32| n.abs())
+        self.unary(i64::checked_abs, f64::abs)
     }
     fn op_neg(&mut self) -> Result<(), String> {
-        unary_int!(self, |n: i32| -n)
+        self.unary(i64::checked_neg, |r| -r)
     }
+    // ceiling, floor, round and truncate leave ints alone and give reals a whole value, still
+    // as a real: 2.5 floor is 2.0
     fn op_ceiling(&mut self) -> Result<(), String> {
-        unary_int!(self, |n| n)
+        self.unary(Some, f64::ceil)
     }
     fn op_floor(&mut self) -> Result<(), String> {
-        unary_int!(self, |n| n)
+        self.unary(Some, f64::floor)
     }
+    // halfway rounds up, so -2.5 round is -2.0
     fn op_round(&mut self) -> Result<(), String> {
-        unary_int!(self, |n| n)
+        self.unary(Some, |r| (r + 0.5).floor())
+    }
+    fn op_truncate(&mut self) -> Result<(), String> {
+        self.unary(Some, f64::trunc)
     }
 
-    fn op_sqrt(&mut self) -> Result<(), String> {
-        let n = self.pop_int()?;
-        if n < 0 {
-            return Err("typecheck".into());
+    // the math functions always give reals. a result too big for a real (or no number at
+    // all) is undefinedresult, operands outside the function's domain are rangecheck
+    fn push_real(&mut self, r: f64) -> Result<(), String> {
+        if !r.is_finite() {
+            return Err("undefinedresult".into());
         }
-        // truncate
-        let root = (n as f64).sqrt() as i32;
-        self.op_stack.push(PSObject::Int(root));
+        self.op_stack.push(PSObject::Real(r));
         Ok(())
     }
+
+    fn op_sqrt(&mut self) -> Result<(), String> {
+        let n = self.pop_number()?;
+        if n < 0.0 {
+            return Err("rangecheck".into());
+        }
+        self.push_real(n.sqrt())
+    }
+
+    // a negative base only has real powers for whole exponents
+    fn op_exp(&mut self) -> Result<(), String> {
+        let exponent = self.pop_number()?;
+        let base = self.pop_number()?;
+        if base < 0.0 && exponent.fract() != 0.0 {
+            return Err("undefinedresult".into());
+        }
+        self.push_real(base.powf(exponent))
+    }
+
+    fn op_ln(&mut self) -> Result<(), String> {
+        let n = self.pop_number()?;
+        if n <= 0.0 {
+            return Err("rangecheck".into());
+        }
+        self.push_real(n.ln())
+    }
     // when executing
     fn op_exec(&mut self) -> Result<(), String> {
-        let obj = self.op_stack.pop().ok_or("stackunderflow")?;
+        // run and leave nothing
+        let proc = self.pick_exec()?;
+        self.exec_proc(proc)
+    }
+
+    // anything else runs the way it would inside a procedure: operators and executable names
+    // do their thing and literals are pushed back
+    fn pick_exec(&mut self) -> Result<PSObject, String> {
+        let obj = match self.op_stack.pop().ok_or("stackunderflow")? {
+            proc @ PSObject::Procedure { .. } => return Ok(proc),
+            other => other,
+        };
+        Ok(PSObject::Procedure {
+            code: vec![obj],
+            env: None,
+        })
+    }
+
+    // executable arrays are procedures here, so cvx makes one out of an array's elements.
+    // names and strings stay what they are, just executable: a string is scanned when it
+    // runs, not now. anything else is already as executable as it gets
+    fn op_cvx(&mut self) -> Result<(), String> {
+        let code = match self.op_stack.pop().ok_or("stackunderflow")? {
+            PSObject::Array(code) | PSObject::PackedArray(code) => code,
+            PSObject::Name(n, _) => return self.push_constant(PSObject::Name(n, true)),
+            PSObject::String(s, _) => return self.push_constant(PSObject::String(s, true)),
+            other => {
+                self.op_stack.push(other);
+                return Ok(());
+            }
+        };
+        let proc = self.closure(code);
+        self.op_stack.push(proc);
+        Ok(())
+    }
+
+    // the session runs on this interpreter, so it's taken out while it does
+    fn op_executive(&mut self) -> Result<(), String> {
+        let mut session = self.executive.take().ok_or("ioerror")?;
+        let result = session(self);
+        self.executive = Some(session);
+        result.map_err(|_| "ioerror".to_string())
+    }
+
+    // scan one token off the front of a string: pushes the rest of the string, the object and
+    // true, or just false when nothing but whitespace is left. from a file it reads past the
+    // token and pushes the object and true, or false at the end of the file
+    fn op_token(&mut self) -> Result<(), String> {
+        let src = match self.op_stack.pop() {
+            Some(PSObject::String(s, _)) => s,
+            Some(PSObject::File(PSFile::Input(file))) => return self.token_from_file(&file),
+            Some(_) => return Err("typecheck".into()),
+            None => return Err("stackunderflow".into()),
+        };
+        let mut scanner = Scanner::from_bytes(&src)
+            .binary_tokens(self.config.binary_tokens)
+            .int_width(self.config.int_width)
+            .max_depth(self.config.max_nesting);
+        match scanner.next_object().transpose()? {
+            Some(obj) => {
+                let rest = PSObject::String(scanner.remainder().to_vec(), false);
+                self.allocate(obj.vm_size() + rest.vm_size())?;
+                self.op_stack.push(rest);
+                self.op_stack.push(obj);
+                self.op_stack.push(PSObject::Bool(true));
+            }
+            None => self.op_stack.push(PSObject::Bool(false)),
+        }
+        Ok(())
+    }
+
+    fn token_from_file(&mut self, file: &RefCell<InputFile>) -> Result<(), String> {
+        let (obj, read) = {
+            let file = file.borrow();
+            let mut scanner = Scanner::from_bytes(file.unread())
+                .binary_tokens(self.config.binary_tokens)
+                .int_width(self.config.int_width)
+                .max_depth(self.config.max_nesting);
+            let obj = scanner.next_object().transpose()?;
+            (obj, file.unread().len() - scanner.remainder().len())
+        };
+        file.borrow_mut().consume(read);
         match obj {
-            PSObject::Procedure { .. } => {
-                // run and leave nothing
-                self.exec_proc(obj)?;
-                Ok(())
+            Some(obj) => {
+                self.allocate(obj.vm_size())?;
+                self.op_stack.push(obj);
+                self.op_stack.push(PSObject::Bool(true));
             }
-            _ => Err("typecheck".into()),
+            None => self.op_stack.push(PSObject::Bool(false)),
+        }
+        Ok(())
+    }
+
+    // MEMORY
+    // count bytes of new composites against the VM ceiling. when they don't fit, reclaim
+    // what nothing refers to any more and try again before giving up with VMerror
+    fn allocate(&mut self, bytes: usize) -> Result<(), String> {
+        let Some(max) = self.config.max_vm else {
+            self.vm_used = self.vm_used.saturating_add(bytes);
+            return Ok(());
+        };
+        if self.vm_used.saturating_add(bytes) > max {
+            self.sweep_scopes();
+            self.vm_used = self.live_vm();
+        }
+        if self.vm_used.saturating_add(bytes) > max {
+            return Err("VMerror".into());
         }
+        self.vm_used += bytes;
+        Ok(())
+    }
+
+    // VM held by everything still reachable from the operand and dictionary stacks, the
+    // operand stack's own slots included, and by popped frames closures still link to
+    fn live_vm(&self) -> usize {
+        let operands: usize = self.op_stack.iter().map(|o| VM_SLOT + o.vm_size()).sum();
+        let frame = |f: &Scope| -> usize {
+            let map = f.map.borrow();
+            map.iter().map(|(k, v)| k.len() + v.vm_size()).sum()
+        };
+        let dicts: usize = self.dict_stack[USERDICT..].iter().map(|f| frame(f)).sum();
+        let closed: usize = self
+            .suspects
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|f| frame(&f))
+            .sum();
+        operands + dicts + closed
+    }
+
+    // ERRORS
+    // dictionaries are values here, so changing the copy this pushes doesn't change anything.
+    // a program installs its own reporter by defining handleerror, which this then shows
+    fn op_errordict(&mut self) -> Result<(), String> {
+        let handler = self.lookup_name("handleerror").unwrap();
+        let dict = DictMap::from([("handleerror".to_string(), handler)]);
+        self.op_stack.push(PSObject::Dict(dict));
+        Ok(())
+    }
+
+    // newerror, errorname, command and ostack for the last uncaught error
+    fn dollar_error(&self) -> PSObject {
+        let mut dict = DictMap::from([("newerror".to_string(), PSObject::Bool(self.new_error))]);
+        if let Some((err, operands)) = &self.last_error {
+            dict.insert("errorname".into(), PSObject::Name(err.name.clone(), false));
+            let command = err
+                .command
+                .clone()
+                .map_or(PSObject::Name("null".into(), false), |c| {
+                    PSObject::Name(c, false)
+                });
+            dict.insert("command".into(), command);
+            dict.insert(
+                "ostack".into(),
+                PSObject::Array(operands.iter().cloned().collect()),
+            );
+        }
+        PSObject::Dict(dict)
+    }
+
+    // the standard report: the trailer for the last error, once
+    fn op_handleerror(&mut self) -> Result<(), String> {
+        match &self.last_error {
+            Some((err, _)) if self.new_error => {
+                let trailer = format!("{}\n", err.trailer());
+                self.new_error = false;
+                self.emit(StdFile::Stdout, trailer.as_bytes())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // HALFTONES
+    fn op_setscreen(&mut self) -> Result<(), String> {
+        let screen = self.pop_operands(3)?;
+        let number = |o: &PSObject| matches!(o, PSObject::Int(_) | PSObject::Real(_));
+        // the spot function can also be a halftone dictionary
+        let spot = matches!(screen[2], PSObject::Procedure { .. } | PSObject::Dict(_));
+        if number(&screen[0]) && number(&screen[1]) && spot {
+            self.halftone.dict = halftone_dict(&screen);
+            self.halftone.screen = screen;
+            Ok(())
+        } else {
+            for obj in screen {
+                self.op_stack.push(obj);
+            }
+            Err("typecheck".into())
+        }
+    }
+
+    fn op_sethalftone(&mut self) -> Result<(), String> {
+        match self.op_stack.pop() {
+            Some(dict @ PSObject::Dict(_)) => {
+                self.halftone.dict = dict;
+                Ok(())
+            }
+            Some(_) => Err("typecheck".into()),
+            None => Err("stackunderflow".into()),
+        }
+    }
+
+    // settransfer sets all four, like the PLRM says
+    fn op_settransfer(&mut self) -> Result<(), String> {
+        match self.op_stack.pop() {
+            Some(proc @ PSObject::Procedure { .. }) => {
+                self.halftone.transfer = vec![proc; 4];
+                Ok(())
+            }
+            Some(_) => Err("typecheck".into()),
+            None => Err("stackunderflow".into()),
+        }
+    }
+
+    fn op_setcolortransfer(&mut self) -> Result<(), String> {
+        let procs = self.pop_operands(4)?;
+        if procs
+            .iter()
+            .all(|p| matches!(p, PSObject::Procedure { .. }))
+        {
+            self.halftone.transfer = procs;
+            Ok(())
+        } else {
+            for obj in procs {
+                self.op_stack.push(obj);
+            }
+            Err("typecheck".into())
+        }
+    }
+
+    // ARRAYS
+    // the top n operands as a packed array, bottom first
+    fn op_packedarray(&mut self) -> Result<(), String> {
+        let n = self.pop_int()?;
+        if n < 0 {
+            return Err("rangecheck".into());
+        }
+        let deepest = self
+            .op_stack
+            .iter()
+            .rev()
+            .take(n as usize)
+            .map(|o| o.depth());
+        if deepest.max().unwrap_or(0) >= self.config.max_nesting {
+            return Err("limitcheck".into());
+        }
+        let items = self.pop_operands(n as usize)?;
+        // only the slots are new, the elements were counted when they were made or copied
+        self.allocate(VM_SLOT * items.len())?;
+        self.op_stack.push(PSObject::PackedArray(items));
+        Ok(())
+    }
+
+    // procedures stay ordinary procedures whatever the mode, it's kept for currentpacking
+    // so programs that save and restore it work
+    fn op_setpacking(&mut self) -> Result<(), String> {
+        match self.op_stack.pop() {
+            Some(PSObject::Bool(b)) => {
+                self.packing = b;
+                Ok(())
+            }
+            Some(_) => Err("typecheck".into()),
+            None => Err("stackunderflow".into()),
+        }
+    }
+
+    // there's no save yet, so the level is always 0
+    fn op_vmstatus(&mut self) -> Result<(), String> {
+        let max = self.config.max_vm.unwrap_or(i32::MAX as usize);
+        for n in [0, self.vm_used, max] {
+            self.op_stack
+                .push(PSObject::Int(n.min(i32::MAX as usize) as i64));
+        }
+        Ok(())
+    }
+
+    // the collection modes (-2 and -1) mean nothing here, any int just reclaims now: frames
+    // only their own closures keep alive are swept up (see object::sweep) and VM recounted.
+    // nothing else can form a cycle, since composites are values
+    fn op_vmreclaim(&mut self) -> Result<(), String> {
+        self.pop_int()?;
+        self.sweep_scopes();
+        self.vm_used = self.live_vm();
+        Ok(())
+    }
+
+    // composites are copied by value here, so where one was allocated makes no difference
+    // yet: the mode is remembered for currentglobal so prologs that switch it still run.
+    // there's no gcheck, since no composite knows which mode it was made in
+    fn op_setglobal(&mut self) -> Result<(), String> {
+        match self.op_stack.pop() {
+            Some(PSObject::Bool(b)) => {
+                self.global = b;
+                Ok(())
+            }
+            Some(_) => Err("typecheck".into()),
+            None => Err("stackunderflow".into()),
+        }
+    }
+
+    // RESOURCES
+    // resource keys and categories can be names or strings
+    fn pop_key(&mut self) -> Result<String, String> {
+        match self.op_stack.pop() {
+            Some(PSObject::Name(n, _)) => Ok(n),
+            Some(PSObject::String(s, _)) => utf8(s),
+            Some(_) => Err("typecheck".into()),
+            None => Err("stackunderflow".into()),
+        }
+    }
+
+    // key instance category defineresource -> instance
+    fn op_defineresource(&mut self) -> Result<(), String> {
+        let category = self.pop_key()?;
+        let instance = self.op_stack.pop().ok_or("stackunderflow")?;
+        let key = self.pop_key()?;
+        self.resources.define(&category, &key, instance.clone());
+        self.op_stack.push(instance);
+        Ok(())
+    }
+
+    // key category findresource -> instance, loading it from the resource directory if needed
+    fn op_findresource(&mut self) -> Result<(), String> {
+        let category = self.pop_key()?;
+        let key = self.pop_key()?;
+        if !self.resources.has_category(&category) {
+            return Err("undefined".into());
+        }
+        if self.resources.get(&category, &key).is_none() {
+            if let Some(path) = self.resources.file_for(&category, &key) {
+                // the file is expected to defineresource itself, whatever it leaves on the
+                // stack (usually the instance) is dropped since we push the instance below
+                let src = self.read_file(&path)?;
+                let depth = self.op_stack.count();
+                self.exec_source(&src, &mut 0)?;
+                while self.op_stack.count() > depth {
+                    self.op_stack.pop();
+                }
+            }
+        }
+        match self.resources.get(&category, &key) {
+            Some(instance) => {
+                self.op_stack.push(instance.clone());
+                Ok(())
+            }
+            None => Err("undefinedresource".into()),
+        }
+    }
+
+    // key category resourcestatus -> status size true | false
+    fn op_resourcestatus(&mut self) -> Result<(), String> {
+        let category = self.pop_key()?;
+        let key = self.pop_key()?;
+        if !self.resources.has_category(&category) {
+            return Err("undefined".into());
+        }
+        match self.resources.status(&category, &key) {
+            Some(status) => {
+                let code = match status {
+                    ResourceStatus::Defined => 1,
+                    ResourceStatus::OnDisk => 2,
+                };
+                self.op_stack.push(PSObject::Int(code));
+                // we don't track VM sizes, -1 means unknown
+                self.op_stack.push(PSObject::Int(-1));
+                self.op_stack.push(PSObject::Bool(true));
+            }
+            None => self.op_stack.push(PSObject::Bool(false)),
+        }
+        Ok(())
+    }
+
+    // template proc scratch category resourceforall, runs proc with each matching key
+    fn op_resourceforall(&mut self) -> Result<(), String> {
+        let category = self.pop_key()?;
+        // strings are values here, so the scratch string is never written into
+        self.op_stack.pop().ok_or("stackunderflow")?;
+        let proc = self.op_stack.pop().ok_or("stackunderflow")?;
+        let template = self.pop_key()?;
+        if !self.resources.has_category(&category) {
+            return Err("undefined".into());
+        }
+        for key in self.resources.keys_matching(&category, &template) {
+            self.op_stack
+                .push(PSObject::String(key.into_bytes(), false));
+            self.exec_proc(proc.clone())?;
+        }
+        Ok(())
+    }
+
+    // (file.eps) epsinclude, see include_eps
+    fn op_epsinclude(&mut self) -> Result<(), String> {
+        let path = self.pop_string()?;
+        let bytes = self.read_file(Path::new(&path))?;
+        self.exec_eps(&bytes)
+    }
+
+    // a library compiled into the interpreter (see library::LIBRARIES) by its name, otherwise
+    // a file on disk. unlike epsinclude it runs in the current dictionary, so whatever it
+    // defines stays around
+    fn op_runlibfile(&mut self) -> Result<(), String> {
+        let name = self.pop_string()?;
+        match library::get(&name) {
+            Some(src) => self.exec_source(src.as_bytes(), &mut 0),
+            None => {
+                let bytes = self.read_file(Path::new(&name))?;
+                self.exec_source(&bytes, &mut 0)
+            }
+        }
+    }
+
+    // FILES ON DISK
+    // the standard files are always open. for a file on disk: its size in 1024 byte pages
+    // and in bytes, and when it was last read and written (seconds since 1970)
+    fn op_status(&mut self) -> Result<(), String> {
+        let path = match self.op_stack.pop().ok_or("stackunderflow")? {
+            PSObject::File(_) => return self.push_constant(PSObject::Bool(true)),
+            PSObject::String(s, _) => utf8(s)?,
+            _ => return Err("typecheck".into()),
+        };
+        if !self.config.security.allows_read(Path::new(&path)) {
+            return Err("invalidfileaccess".into());
+        }
+        let Ok(meta) = std::fs::metadata(&path) else {
+            return self.push_constant(PSObject::Bool(false));
+        };
+        let secs = |time: std::io::Result<std::time::SystemTime>| {
+            time.ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs() as i64)
+        };
+        let bytes = meta.len() as i64;
+        for n in [
+            (bytes + 1023) / 1024,
+            bytes,
+            secs(meta.accessed()),
+            secs(meta.modified()),
+        ] {
+            self.op_stack.push(PSObject::Int(n));
+        }
+        self.push_constant(PSObject::Bool(true))
+    }
+
+    fn op_deletefile(&mut self) -> Result<(), String> {
+        let path = self.pop_string()?;
+        if !self.config.security.allows_write(Path::new(&path)) {
+            return Err("invalidfileaccess".into());
+        }
+        std::fs::remove_file(&path).map_err(|e| file_error(&e))
+    }
+
+    fn op_renamefile(&mut self) -> Result<(), String> {
+        let new = self.pop_string()?;
+        let old = self.pop_string()?;
+        let security = &self.config.security;
+        if !security.allows_write(Path::new(&old)) || !security.allows_write(Path::new(&new)) {
+            return Err("invalidfileaccess".into());
+        }
+        std::fs::rename(&old, &new).map_err(|e| file_error(&e))
+    }
+
+    // template proc scratch filenameforall. * and ? match in the last part of the template
+    // only, (data/*.txt) lists the .txt files in data. names come out sorted, with the
+    // template's directory in front, and directories the policy won't let us read are empty
+    fn op_filenameforall(&mut self) -> Result<(), String> {
+        // each name is pushed as a string of its own, however long, so the scratch string's
+        // size doesn't limit them
+        self.op_stack.pop().ok_or("stackunderflow")?;
+        let proc = self.op_stack.pop().ok_or("stackunderflow")?;
+        let template = self.pop_string()?;
+        if !matches!(proc, PSObject::Procedure { .. }) {
+            return Err("typecheck".into());
+        }
+        let (dir, pattern) = match template.rfind('/') {
+            Some(i) => (&template[..=i], &template[i + 1..]),
+            None => ("", template.as_str()),
+        };
+        let read_from = if dir.is_empty() { "." } else { dir };
+        let mut names = Vec::new();
+        if self.config.security.allows_read(Path::new(read_from)) {
+            if let Ok(entries) = std::fs::read_dir(read_from) {
+                for entry in entries.flatten() {
+                    if let Some(name) = entry.file_name().to_str() {
+                        if resource::template_matches(pattern, name) {
+                            names.push(format!("{}{}", dir, name));
+                        }
+                    }
+                }
+            }
+        }
+        names.sort();
+        for name in names {
+            self.op_stack
+                .push(PSObject::String(name.into_bytes(), false));
+            self.exec_proc(proc.clone())?;
+        }
+        Ok(())
+    }
+
+    // a string operand read as text, for names of files and the like
+    fn pop_string(&mut self) -> Result<String, String> {
+        match self.op_stack.pop() {
+            Some(PSObject::String(s, _)) => utf8(s),
+            Some(_) => Err("typecheck".into()),
+            None => Err("stackunderflow".into()),
+        }
+    }
+}
+
+// an int or real as a real, for operators that take either
+fn number(obj: &PSObject) -> Result<f64, String> {
+    match obj {
+        PSObject::Int(n) => Ok(*n as f64),
+        PSObject::Real(r) => Ok(*r),
+        _ => Err("typecheck".into()),
+    }
+}
+
+// the text in a string's bytes, a rangecheck when they aren't UTF-8
+fn utf8(bytes: Vec<u8>) -> Result<String, String> {
+    String::from_utf8(bytes).map_err(|_| "rangecheck".into())
+}
+
+// the PostScript error for a failed filesystem call
+fn file_error(e: &std::io::Error) -> String {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => "undefinedfilename".into(),
+        std::io::ErrorKind::PermissionDenied => "invalidfileaccess".into(),
+        _ => "ioerror".into(),
+    }
+}
+
+// 3, 3 and 4, 1, 2 and 3
+fn listing(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [one] => one.clone(),
+        [init @ .., last] => format!("{} and {}", init.join(", "), last),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::config::{IntWidth, SecurityPolicy};
+    use proptest::prelude::*;
+
+    #[test]
+    fn run_captured_collects_output_and_stack() {
+        let mut interp = Interpreter::new();
+        let out = interp.run_captured("(hi) print 1 2 3 = (%stderr) (w) file (warn) writestring");
+        assert_eq!(out.output, "hi3\n");
+        assert_eq!(out.errors, "warn");
+        assert_eq!(out.stack, vec!["1", "2"]);
+
+        let out = interp.run_captured("clear 1 add");
+        assert_eq!(out.errors, "Error: stackunderflow in add\n");
+    }
+
+    #[test]
+    fn state_persists_until_reset() {
+        let mut interp = Interpreter::new();
+        interp.run("/x 1 def /y {x} def 5").unwrap();
+        interp.run("/x 2 def").unwrap();
+        assert_eq!(interp.stack(), vec!["5"]);
+        assert_eq!(
+            interp.definitions(),
+            vec![("x".into(), "2".into()), ("y".into(), "{x }".into())]
+        );
+        interp.reset();
+        assert!(interp.stack().is_empty());
+        assert!(interp.definitions().is_empty());
+    }
+
+    #[test]
+    fn host_operators() {
+        let mut interp = Interpreter::new();
+        interp.define_operator("sumdiff", 2, |args| match args[..] {
+            [PSObject::Int(a), PSObject::Int(b)] => {
+                Ok(vec![PSObject::Int(a + b), PSObject::Int(a - b)])
+            }
+            _ => Err("typecheck".into()),
+        });
+        interp.run("10 3 sumdiff").unwrap();
+        assert_eq!(interp.stack(), vec!["13", "7"]);
+        let err = interp.run("clear 1 sumdiff").unwrap_err();
+        assert_eq!(err.name, "stackunderflow");
+        assert_eq!(interp.stack(), vec!["1"]);
+    }
+
+    #[test]
+    fn procedures_keep_their_scoping() {
+        let mut interp = Interpreter::new();
+        // f is made under dynamic scoping and still sees the x in force when it runs, g
+        // sees the one from where it was made
+        interp
+            .run("/x 1 def /f { x } def lexical /g { x } def 1 dict begin /x 2 def f g end")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["2", "1"]);
+        interp
+            .run("clear currentscoping dynamic currentscoping")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["/lexical", "/dynamic"]);
+
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            scoping: Scoping::Lexical,
+            ..InterpreterConfig::default()
+        });
+        interp.run("currentscoping").unwrap();
+        assert_eq!(interp.stack(), vec!["/lexical"]);
+    }
+
+    #[test]
+    fn closures_link_to_where_they_were_made() {
+        let mut interp = Interpreter::new();
+        // names defined after the procedure in the same scope are seen
+        interp.run("lexical /f { y } def /y 5 def f").unwrap();
+        assert_eq!(interp.stack(), vec!["5"]);
+        // a procedure's own definitions shadow the outer ones and go away when it returns
+        interp
+            .run("clear /x 1 def /g { /x 3 def x } def g x")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["3", "1"]);
+        // each call gets its own frame, so closures made by it keep their own n
+        interp
+            .run("clear /make { /n exch def { n } } def 7 make /a exch def 8 make /b exch def a b")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["7", "8"]);
+        // and nest, seeing the frames of every procedure around them
+        interp
+            .run("clear /outer { /m 2 def { /k 3 def { m k add } } } def outer exec exec")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["5"]);
+        // the frame goes away when the procedure stops with an error too
+        let err = interp
+            .run("clear /h { /z 1 def 1 0 idiv } def h")
+            .unwrap_err();
+        assert_eq!(err.name, "undefinedresult");
+        assert_eq!(interp.run("clear z").unwrap_err().name, "undefined");
+    }
+
+    #[test]
+    fn closures_defined_into_their_own_frame_are_freed() {
+        let mut interp = Interpreter::new();
+        // a closure left on the stack, with a copy defined into the frame it links to
+        interp
+            .run("lexical /g { { 1 } dup /h exch def } def g")
+            .unwrap();
+        let frame = match interp.pop() {
+            Some(PSObject::Procedure { env: Some(env), .. }) => Rc::downgrade(&env),
+            other => panic!("Expected a lexical procedure, got {:?}", other),
+        };
+        // with that gone only a cycle keeps the frame, and a looping workload making more of
+        // them sweeps it up with the rest
+        interp
+            .run("/k { /h { 1 } def } def 10000 { k } repeat")
+            .unwrap();
+        assert!(frame.upgrade().is_none());
+        assert!(interp.suspects.len() <= SWEEP_AFTER);
+        // while closures something still refers to keep their frames
+        interp
+            .run("/make { /n exch def { n } } def 7 make /a exch def 10000 { k } repeat a")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["7"]);
+    }
+
+    #[test]
+    fn tail_calls_run_in_constant_stack() {
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            max_call_depth: 50,
+            ..InterpreterConfig::default()
+        });
+        // through if, ifelse and exec, far deeper than the limit allows for nested calls
+        interp
+            .run("/down { dup 0 gt { 1 sub down } if } def 100000 down")
+            .unwrap();
+        interp
+            .run("/sum { dup 0 eq { pop } { dup /n exch def add n 1 sub sum } ifelse } def")
+            .unwrap();
+        interp.run("clear 0 1000 sum").unwrap();
+        assert_eq!(interp.stack(), vec!["500500"]);
+        interp
+            .run("clear lexical /spin { 1 add dup 5000 lt { { spin } } { {} } ifelse exec } def 0 spin")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["5000"]);
+        // calls that aren't in tail position still nest, up to the limit
+        let err = interp
+            .run("clear dynamic /r { dup 0 gt { 1 sub r 0 add } if } def 100 r")
+            .unwrap_err();
+        assert_eq!(err.name, "execstackoverflow");
+        interp.run("clear 10 r").unwrap();
+        assert_eq!(interp.stack(), vec!["0"]);
+    }
+
+    #[test]
+    fn integers_that_overflow_become_reals() {
+        let ops = |interp: &Interpreter| interp.operands().cloned().collect::<Vec<_>>();
+        let mut interp = Interpreter::new();
+        interp
+            .run("2147483647 1 add -2147483648 neg 3000000000 46341 46341 mul")
+            .unwrap();
+        assert_eq!(
+            ops(&interp),
+            vec![
+                PSObject::Real(2147483648.0),
+                PSObject::Real(2147483648.0),
+                PSObject::Real(3000000000.0),
+                PSObject::Real(2147488281.0)
+            ]
+        );
+        // and arithmetic carries on with them as reals
+        interp
+            .run("clear 2147483647 1 add 1 sub 46341 46341 mul 2 div -2147483648 neg neg")
+            .unwrap();
+        assert_eq!(
+            ops(&interp),
+            vec![
+                PSObject::Real(2147483647.0),
+                PSObject::Real(1073744140.5),
+                PSObject::Real(-2147483648.0)
+            ]
+        );
+        let err = interp.run("clear -2147483648 -1 idiv").unwrap_err();
+        assert_eq!(err.name, "undefinedresult");
+
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            int_width: IntWidth::Bits64,
+            ..InterpreterConfig::default()
+        });
+        interp
+            .run("2147483647 1 add { 3000000000 } exec -2147483648 -1 idiv 9223372036854775807 1 add")
+            .unwrap();
+        assert_eq!(
+            ops(&interp),
+            vec![
+                PSObject::Int(2147483648),
+                PSObject::Int(3000000000),
+                PSObject::Int(2147483648),
+                PSObject::Real(9223372036854775808.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn cvs_and_format() {
+        let mut interp = Interpreter::new();
+        interp.run("42 (    ) cvs /name (    ) cvs").unwrap();
+        assert_eq!(interp.stack(), vec!["(42)", "(name)"]);
+        let err = interp.run("12345 (1234) cvs").unwrap_err();
+        assert_eq!(err.name, "rangecheck");
+        // format is an extension
+        assert_eq!(interp.run("(%d) [1] format").unwrap_err().name, "undefined");
+
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            extensions: true,
+            ..InterpreterConfig::default()
+        });
+        interp
+            .run("(%-6s|%5.1f|%03d) [(total) 12.25 7] format")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["(total | 12.2|007)"]);
+    }
+
+    #[test]
+    fn date_and_time() {
+        struct Stopped;
+        impl Clock for Stopped {
+            fn now(&self) -> i64 {
+                86399
+            }
+        }
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            extensions: true,
+            ..InterpreterConfig::default()
+        });
+        interp.set_clock(Box::new(Stopped));
+        interp.run("currentdate currenttime").unwrap();
+        assert_eq!(interp.stack(), vec!["(1970-01-01)", "(23:59:59)"]);
+
+        // the config wins over the clock
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            extensions: true,
+            fixed_time: Some(1709210096),
+            ..InterpreterConfig::default()
+        });
+        interp.set_clock(Box::new(Stopped));
+        interp.run("currentdate currenttime").unwrap();
+        assert_eq!(interp.stack(), vec!["(2024-02-29)", "(12:34:56)"]);
+    }
+
+    #[test]
+    fn init_file() {
+        let mut interp = Interpreter::new();
+        let out = interp.run_captured("(a) 2 3 0 index 3 index true false xor stack");
+        assert_eq!(out.output, "true\na\n3\n3\n2\na\n");
+        assert_eq!(out.stack, vec!["(a)", "2", "3", "3", "(a)", "true"]);
+        assert!(interp.known_names().iter().any(|k| k.name == "pstack"));
+        // the program's own definition wins
+        interp
+            .run("clear /index { pop 42 } def 1 2 0 index")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["1", "2", "42"]);
+
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            init: false,
+            ..InterpreterConfig::default()
+        });
+        assert_eq!(interp.run("true false xor").unwrap_err().name, "undefined");
+    }
+
+    #[test]
+    fn index_errors() {
+        let mut interp = Interpreter::new();
+        let err = interp.run("1 2 3 5 index").unwrap_err();
+        assert_eq!(err.to_string(), "stackunderflow in index");
+        assert_eq!(err.stack, vec!["1", "2", "3", "5"]);
+        assert_eq!(
+            interp.run("clear 1 -1 index").unwrap_err().name,
+            "rangecheck"
+        );
+        assert_eq!(
+            interp.run("clear 1 (0) index").unwrap_err().name,
+            "typecheck"
+        );
+        interp.run("clear 1 2 3 2 index").unwrap();
+        assert_eq!(interp.stack(), vec!["1", "2", "3", "1"]);
+    }
+
+    #[test]
+    fn exec_arrays_and_strings() {
+        let mut interp = Interpreter::new();
+        interp
+            .run("(3 4 add) cvx exec [2 { 5 mul } exec] cvx exec (3 4 add) exec /x exec 7 exec")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["7", "10", "(3 4 add)", "/x", "7"]);
+        // an executable name runs what it names, whether it's exec'd or defined and called
+        interp
+            .run("clear 1 2 /add cvx exec /twice { 2 mul } def /dbl /twice cvx def 5 dbl")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["3", "10"]);
+        // cvx keeps a name a name and a string a string, and only running the string scans it
+        interp
+            .run("clear /x cvx length (abc) cvx length (abc) cvx (abc) eq /s (2 3 mul) cvx def s")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["1", "3", "true", "6"]);
+        interp.run("clear (2 {) cvx length").unwrap();
+        assert_eq!(interp.stack(), vec!["3"]);
+        assert_eq!(
+            interp.run("clear (2 {) cvx exec").unwrap_err().name,
+            "syntaxerror"
+        );
+    }
+
+    #[test]
+    fn token_results_can_be_executed() {
+        let mut interp = Interpreter::new();
+        let sink = BufferSink::new();
+        interp.set_output(Box::new(sink.clone()));
+        interp
+            .run("/foo {(ran) =} def (foo) token pop exch pop exec (/foo) token pop exch pop")
+            .unwrap();
+        assert_eq!(sink.take_stdout(), "ran\n");
+        assert_eq!(interp.stack(), vec!["/foo"]);
+        // a bare name comes back as an executable name, not a procedure that runs it
+        interp
+            .run("clear (foo) token pop exch pop dup length exch (foo) token pop exch pop ==")
+            .unwrap();
+        assert_eq!(sink.take_stdout(), "foo");
+        assert_eq!(interp.stack(), vec!["3", "foo"]);
+        // the usual loop, scanning and running one token at a time
+        interp
+            .run("clear (3 4 add 2 mul) 5 { token pop exch /rest exch def exec rest } repeat pop")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["14"]);
+    }
+
+    #[test]
+    fn token_reads_files() {
+        let path = std::env::temp_dir().join("ps-interpreter-token-test.ps");
+        std::fs::write(&path, "/sq {dup mul} def\n% done\n4 sq").unwrap();
+        let p = path.to_str().unwrap();
+        let mut interp = Interpreter::new();
+        // reading through either copy of the file moves both along, and the end gives false
+        interp
+            .run(&format!(
+                "/f ({p}) (r) file def /g f def f token pop g token pop f token pop exec
+                 g token pop f token pop exec f token"
+            ))
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["16", "false"]);
+        let err = interp
+            .run(&format!("({p}) (r) file (x) writestring"))
+            .unwrap_err();
+        assert_eq!(err.name, "invalidaccess");
+
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            security: SecurityPolicy::NoFilesystem,
+            ..InterpreterConfig::default()
+        });
+        let err = interp.run(&format!("({p}) (r) file")).unwrap_err();
+        assert_eq!(err.name, "invalidfileaccess");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn for_with_reals() {
+        let mut interp = Interpreter::new();
+        interp.run("0 1 2.5 {} for").unwrap();
+        assert_eq!(interp.stack(), vec!["0", "1", "2"]);
+        interp.run("clear 1 -0.25 0 {} for").unwrap();
+        assert_eq!(interp.stack(), vec!["1.0", "0.75", "0.5", "0.25", "0.0"]);
+        interp.run("clear 0.5 1 2 {} for").unwrap();
+        assert_eq!(interp.stack(), vec!["0.5", "1.5"]);
+
+        // the control variable is a number the procedure can compute with
+        interp.run("clear 0 .5 1 {2 mul} for").unwrap();
+        assert_eq!(interp.stack(), vec!["0.0", "1.0", "2.0"]);
+        interp
+            .run("clear 0 1 1 1.5 {add} for 0.5 0.5 2 {dup mul add} for")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["8.5"]);
+
+        // adding 0.1 up ten times gives 0.9999999999999999, which would make the end drift
+        interp.run("clear 0 .1 1 {} for count exch").unwrap();
+        assert_eq!(interp.pop(), Some(PSObject::Real(1.0)));
+        assert_eq!(interp.pop(), Some(PSObject::Int(11)));
+        interp.run("clear 0 0 0.01 1000 { exch pop } for").unwrap();
+        assert_eq!(interp.pop(), Some(PSObject::Real(1000.0)));
+        interp
+            .run("clear /n 0 def 0 0.001 1 { pop /n n 1 add def } for n")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["1001"]);
+
+        interp.run("clear").unwrap();
+        assert_eq!(interp.run("0 0.0 1 {} for").unwrap_err().name, "rangecheck");
+        assert_eq!(interp.run("0 .5 (x) {} for").unwrap_err().name, "typecheck");
+    }
+
+    #[test]
+    fn arithmetic_on_reals() {
+        let mut interp = Interpreter::new();
+        interp
+            .run("1.5 2 add 2 0.5 sub 0.5 4 mul 2.5 neg -2.5 abs 3 neg")
+            .unwrap();
+        assert_eq!(
+            interp.stack(),
+            vec!["3.5", "1.5", "2.0", "-2.5", "2.5", "-3"]
+        );
+        interp
+            .run("clear 2.5 floor 2.5 ceiling 2.5 round -2.5 round -2.7 truncate 7 floor")
+            .unwrap();
+        assert_eq!(
+            interp.stack(),
+            vec!["2.0", "3.0", "3.0", "-2.0", "-2.0", "7"]
+        );
+
+        // computing with what the math functions give back
+        interp
+            .run("clear 2 sqrt dup mul 2 sub abs 1e-12 lt")
+            .unwrap();
+        interp
+            .run("9 sqrt 1 add 2 10 exp 1000 sub 1 ln 5 add")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["true", "4.0", "24.0", "5.0"]);
+        interp.run("clear").unwrap();
+        assert_eq!(
+            interp.run("1e300 1e300 mul").unwrap_err().name,
+            "undefinedresult"
+        );
+        assert_eq!(interp.run("1.5 (x) add").unwrap_err().name, "typecheck");
+        assert_eq!(interp.run("(x) floor").unwrap_err().name, "typecheck");
+    }
+
+    #[test]
+    fn math_functions_against_reference_values() {
+        let cases = [
+            ("4 sqrt", 2.0),
+            ("2 sqrt", std::f64::consts::SQRT_2),
+            ("0.25 sqrt", 0.5),
+            ("0 sqrt", 0.0),
+            ("2 10 exp", 1024.0),
+            ("9 0.5 exp", 3.0),
+            ("-2 3 exp", -8.0),
+            ("2 -1 exp", 0.5),
+            ("2.718281828459045 1 exp", std::f64::consts::E),
+            ("1 ln", 0.0),
+            ("10 ln", std::f64::consts::LN_10),
+            ("2.718281828459045 ln", 1.0),
+            ("0.5 ln", -std::f64::consts::LN_2),
+        ];
+        for (program, expected) in cases {
+            let mut interp = Interpreter::new();
+            interp.run(program).unwrap();
+            let Some(PSObject::Real(r)) = interp.pop() else {
+                panic!("{} didn't give a real", program);
+            };
+            assert!((r - expected).abs() < 1e-12, "{} gave {}", program, r);
+        }
+
+        let mut interp = Interpreter::new();
+        for (program, error) in [
+            ("-1 sqrt", "rangecheck"),
+            ("0 ln", "rangecheck"),
+            ("-2.5 ln", "rangecheck"),
+            ("-8 0.5 exp", "undefinedresult"),
+            ("0 -1 exp", "undefinedresult"),
+            ("10 400 exp", "undefinedresult"),
+            ("(4) sqrt", "typecheck"),
+        ] {
+            assert_eq!(interp.run(program).unwrap_err().name, error, "{}", program);
+        }
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        let mut interp = Interpreter::new();
+        interp
+            .run("3 3.0 eq 3 3.5 ne [1 2.0] [1.0 2] eq 2.5 3 lt 3 2.5 ge 2.0 2 le 1 2.0 gt")
+            .unwrap();
+        assert_eq!(
+            interp.stack(),
+            vec!["true", "true", "true", "true", "true", "true", "false"]
+        );
+        interp.run("clear").unwrap();
+
+        // infinity compares like any number, though a literal too big for a real is a
+        // limitcheck rather than one
+        assert_eq!(interp.run("1e999").unwrap_err().name, "limitcheck");
+        interp.op_stack.push(PSObject::Real(f64::INFINITY));
+        interp.op_stack.push(PSObject::Real(f64::NEG_INFINITY));
+        interp.run("dup eq exch 2147483647 gt").unwrap();
+        assert_eq!(interp.stack(), vec!["true", "true"]);
+        interp.run("clear").unwrap();
+        assert_eq!(interp.run("(a) 1 lt").unwrap_err().name, "typecheck");
+
+        // NaN isn't equal to anything, itself included, and has no order
+        interp.op_stack.push(PSObject::Real(f64::NAN));
+        interp.run("dup eq").unwrap();
+        assert_eq!(interp.stack(), vec!["false"]);
+        interp.run("clear").unwrap();
+        interp.op_stack.push(PSObject::Real(f64::NAN));
+        assert_eq!(interp.run("1 lt").unwrap_err().name, "undefinedresult");
+    }
+
+    #[test]
+    fn forth_stack_words() {
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.run("1 2 over").unwrap_err().name, "undefined");
+
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            extensions: true,
+            ..InterpreterConfig::default()
+        });
+        interp.run("1 2 over 3 tuck nip (x) 2 ndup").unwrap();
+        assert_eq!(
+            interp.stack(),
+            vec!["1", "2", "3", "3", "(x)", "(x)", "(x)"]
+        );
+        assert_eq!(interp.run("1 -1 ndup").unwrap_err().name, "rangecheck");
+        interp.run("clear").unwrap();
+        assert_eq!(interp.run("1 nip").unwrap_err().name, "stackunderflow");
+    }
+
+    #[test]
+    fn real_output() {
+        let printed = |digits, program| {
+            let mut interp = Interpreter::with_config(InterpreterConfig {
+                real_digits: digits,
+                ..InterpreterConfig::default()
+            });
+            let sink = BufferSink::new();
+            interp.set_output(Box::new(sink.clone()));
+            interp.run(program).unwrap();
+            sink.take_stdout()
+        };
+        let program = "1 2 div = 3.0 == ( ) print 1 3 div (xxxxxxxxxxxxxxxxxxxx) cvs = [1e20] ==";
+        assert_eq!(
+            printed(None, program),
+            "0.5\n3.0 0.3333333333333333\n[1.0e+20]"
+        );
+        assert_eq!(printed(Some(6), program), "0.5\n3.0 0.333333\n[1.0e+20]");
+
+        // a real too big to scan never makes it to = or cvs as infinity
+        let mut interp = Interpreter::new();
+        let sink = BufferSink::new();
+        interp.set_output(Box::new(sink.clone()));
+        assert_eq!(interp.run("1e400 =").unwrap_err().name, "limitcheck");
+        let err = interp.run("(1e400) token").unwrap_err();
+        assert_eq!(err.name, "limitcheck");
+        interp
+            .run("clear 1e308 (                    ) cvs =")
+            .unwrap();
+        assert_eq!(sink.take_stdout(), "1.0e+308\n");
+    }
+
+    #[test]
+    fn deterministic_runs() {
+        let run = || {
+            let mut interp = Interpreter::with_config(InterpreterConfig {
+                extensions: true,
+                deterministic: true,
+                ..InterpreterConfig::default()
+            });
+            interp
+                .run("currentdate currenttime realtime usertime rand rand rrand")
+                .unwrap();
+            interp.stack()
+        };
+        let first = run();
+        assert_eq!(first, run());
+        assert_eq!(
+            first,
+            vec![
+                "(1970-01-01)",
+                "(00:00:00)",
+                "0",
+                "0",
+                "16807",
+                "282475249",
+                "282475249"
+            ]
+        );
+
+        // a seed read back with rrand replays the sequence from there
+        let mut interp = Interpreter::new();
+        interp
+            .run("123 srand rrand rand exch srand rand eq")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["true"]);
+    }
+
+    #[test]
+    fn arguments_and_environment() {
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            extensions: true,
+            ..InterpreterConfig::default()
+        });
+        interp.set_arguments(&["in.dat".into(), "-v".into()]);
+        // cargo sets this for the tests it runs
+        interp
+            .run("ARGUMENTS length ARGUMENTS 1 get (CARGO_PKG_NAME) getenv (NO_SUCH_VARIABLE_HERE) getenv")
+            .unwrap();
+        assert_eq!(
+            interp.stack(),
+            vec!["2", "(-v)", "(ps-interpreter)", "true", "false"]
+        );
+
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            extensions: true,
+            security: SecurityPolicy::NoFilesystem,
+            ..InterpreterConfig::default()
+        });
+        let err = interp.run("(CARGO_PKG_NAME) getenv").unwrap_err();
+        assert_eq!(err.name, "invalidaccess");
+    }
+
+    #[test]
+    fn files_on_disk() {
+        let dir = std::env::temp_dir().join("ps-interpreter-file-ops-test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a.txt", "b.txt", "c.dat"] {
+            std::fs::write(dir.join(name), "12345").unwrap();
+        }
+        let d = dir.to_str().unwrap();
+        let mut interp = Interpreter::new();
+        interp
+            .run(&format!("({d}/*.txt) {{ }} (scratch) filenameforall"))
+            .unwrap();
+        assert_eq!(
+            interp.stack(),
+            vec![format!("({d}/a.txt)"), format!("({d}/b.txt)")]
+        );
+        interp
+            .run(&format!(
+                "clear ({d}/a.txt) ({d}/d.txt) renamefile ({d}/b.txt) deletefile ({d}/?.*) {{ }} () filenameforall"
+            ))
+            .unwrap();
+        assert_eq!(
+            interp.stack(),
+            vec![format!("({d}/c.dat)"), format!("({d}/d.txt)")]
+        );
+        interp
+            .run(&format!("clear ({d}/d.txt) status ({d}/a.txt) status"))
+            .unwrap();
+        // pages and bytes, then the times, then found or not
+        let stack = interp.stack();
+        assert_eq!(stack[..2], ["1", "5"]);
+        assert_eq!(stack[4..], ["true", "false"]);
+        let err = interp.run(&format!("({d}/a.txt) deletefile")).unwrap_err();
+        assert_eq!(err.name, "undefinedfilename");
+
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            security: SecurityPolicy::ReadOnly {
+                roots: vec![dir.clone()],
+            },
+            ..InterpreterConfig::default()
+        });
+        let err = interp.run(&format!("({d}/d.txt) deletefile")).unwrap_err();
+        assert_eq!(err.name, "invalidfileaccess");
+    }
+
+    #[test]
+    fn utf8_strings() {
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            extensions: true,
+            ..InterpreterConfig::default()
+        });
+        interp
+            .run("(h\u{e9}\u{1F600}) dup utf8length exch dup 1 utf8get exch utf8decode")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["3", "233", "[104 233 128512]"]);
+        // length is in bytes, so it gives the bounds getinterval takes
+        interp
+            .run("clear (h\u{e9}) length (h\u{e9}) 0 (h\u{e9}) length getinterval")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["3", "(h\u{e9})"]);
+        interp.run("clear [104 233 128512] utf8encode").unwrap();
+        assert_eq!(interp.stack(), vec!["(h\u{e9}\u{1F600})"]);
+        assert_eq!(
+            interp.run("[55296] utf8encode").unwrap_err().name,
+            "rangecheck"
+        );
+        assert_eq!(interp.run("(ab) 2 utf8get").unwrap_err().name, "rangecheck");
+
+        // intervals count bytes of the encoded text
+        interp
+            .run("clear [104 233 128512] utf8encode dup 3 4 getinterval utf8decode exch 1 2 getinterval")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["[128512]", "(\u{e9})"]);
+        interp
+            .run("clear [104 233 128512] utf8encode 1 [97 98] utf8encode putinterval")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["(hab\u{1F600})"]);
+        // one that falls inside a character gets those bytes, and they aren't text any more
+        interp
+            .run("clear [104 128512] utf8encode 2 1 getinterval dup 0 get exch length")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["159", "1"]);
+        let err = interp
+            .run("clear [233 104] utf8encode 1 [120] utf8encode putinterval utf8decode")
+            .unwrap_err();
+        assert_eq!(err.name, "rangecheck");
+
+        // an octal escape is a byte, so \200 to \377 are one each and print back the same way
+        interp
+            .run("clear (\\200\\377) dup length exch dup 1 get exch")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["2", "255", "(\\200\\377)"]);
+        interp
+            .run("clear (\\377) (\\377) eq (\\303\\251) (\u{e9}) eq")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["true", "true"]);
+    }
+
+    #[test]
+    fn help_from_the_operator_table() {
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            extensions: true,
+            ..InterpreterConfig::default()
+        });
+        let out = interp.run_captured("/add help (utf8length) help /nosuch help");
+        assert_eq!(
+            out.output,
+            "num1 num2 add sum\n  add two numbers\n\
+             string utf8length int\n  number of characters (code points) in string\n\
+             no help for nosuch\n"
+        );
+        assert!(out.stack.is_empty());
+    }
+
+    #[test]
+    fn teaching_mode_explains_each_step() {
+        let mut interp = Interpreter::new();
+        let steps = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let s = steps.clone();
+        interp.set_explainer(Some(Box::new(move |text: &str| {
+            s.borrow_mut().push(text.to_string())
+        })));
+        interp
+            .run("/x 42 def 3 4 add x /sq { dup mul } def 5 sq clear")
+            .unwrap();
+        assert_eq!(
+            *steps.borrow(),
+            vec![
+                "push /x",
+                "push 42",
+                "def: pops /x and 42",
+                "push 3",
+                "push 4",
+                "add: pops 3 and 4, pushes 7",
+                "name lookup /x → 42",
+                "push /sq",
+                "push {dup mul }",
+                "def: pops /sq and {dup mul }",
+                "push 5",
+                "call sq",
+                "dup: pops 5, pushes 5 and 5",
+                "mul: pops 5 and 5, pushes 25",
+                "clear",
+            ]
+        );
+
+        steps.borrow_mut().clear();
+        interp
+            .run("/f { 1 eq { (one) } { (other) } ifelse } def 2 f")
+            .unwrap();
+        assert_eq!(
+            steps.borrow()[4..],
+            [
+                "call f",
+                "push 1",
+                "eq: pops 2 and 1, pushes false",
+                "push {(one) }",
+                "push {(other) }",
+                "ifelse: runs {(other) } in place of the caller",
+                "push (other)",
+            ]
+        );
+
+        steps.borrow_mut().clear();
+        interp.set_explainer(None);
+        interp.run("1 2 add").unwrap();
+        assert!(steps.borrow().is_empty());
+    }
+
+    #[test]
+    fn snapshots_roll_back_stack_and_dictionaries() {
+        let mut interp = Interpreter::new();
+        interp.run("/x 1 def lexical /get { x } def 5").unwrap();
+        let before = interp.snapshot();
+        interp
+            .run("/x 2 def /y 3 def dynamic 1 dict begin clear 6")
+            .unwrap();
+        interp.restore(before);
+        assert_eq!(interp.stack(), vec!["5"]);
+        interp.run("get currentscoping").unwrap();
+        assert_eq!(interp.stack(), vec!["5", "1", "/lexical"]);
+        assert_eq!(interp.run("y").unwrap_err().name, "undefined");
+        assert_eq!(interp.dictionaries().len(), 1);
+    }
+
+    #[test]
+    fn state_as_json() {
+        let mut interp = Interpreter::new();
+        interp
+            .run("/b (two) def /a 1 def 1 dict begin 3 (x\"y)")
+            .unwrap();
+        assert_eq!(
+            interp.dump_state(),
+            r#"{
+  "scoping": "dynamic",
+  "operands": [
+    "3",
+    "(x\"y)"
+  ],
+  "dictionaries": [
+    {
+      "a": "1",
+      "b": "(two)"
+    },
+    {}
+  ]
+}
+"#
+        );
+    }
+
+    #[test]
+    fn step_limit() {
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            max_steps: Some(1000),
+            ..InterpreterConfig::default()
+        });
+        assert_eq!(interp.run("1000000 {} repeat").unwrap_err().name, "timeout");
+        assert_eq!(
+            interp.run("/f { 1 pop f } def f").unwrap_err().name,
+            "timeout"
+        );
+        // the count starts over with each run
+        interp.run("1 1 300 { pop } for").unwrap();
+        interp.run("1 1 300 { pop } for").unwrap();
+    }
+
+    // what a program leaves on a fresh interpreter's stack, or its error
+    fn results(program: &str) -> Result<Vec<String>, String> {
+        let mut interp = Interpreter::new();
+        interp.run(program).map_err(|e| e.name)?;
+        Ok(interp.stack())
+    }
+
+    proptest! {
+        #[test]
+        fn add_and_mul_commute(a in any::<i32>(), b in any::<i32>()) {
+            for op in ["add", "mul"] {
+                prop_assert_eq!(
+                    results(&format!("{a} {b} {op}")),
+                    results(&format!("{b} {a} {op}"))
+                );
+            }
+        }
+
+        #[test]
+        fn sub_undoes_add(a in any::<i32>(), b in any::<i32>()) {
+            prop_assume!(a.checked_add(b).is_some());
+            prop_assert_eq!(results(&format!("{a} {b} add {b} sub")), Ok(vec![a.to_string()]));
+        }
+
+        // a sum too big for an int is a real, which sub takes as well
+        #[test]
+        fn sub_undoes_add_past_overflow(a in any::<i32>(), b in any::<i32>()) {
+            let back = results(&format!("{a} {b} add {b} sub {a} eq"));
+            prop_assert_eq!(back, Ok(vec!["true".to_string()]));
+        }
+
+        #[test]
+        fn idiv_and_mod_put_it_back_together(a in any::<i32>(), b in any::<i32>()) {
+            prop_assume!(b != 0 && !(a == i32::MIN && b == -1));
+            prop_assert_eq!(
+                results(&format!("{a} {b} idiv {b} mul {a} {b} mod add")),
+                Ok(vec![a.to_string()])
+            );
+        }
+
+        #[test]
+        fn neg_twice_changes_nothing(a in (i32::MIN + 1)..=i32::MAX) {
+            prop_assert_eq!(results(&format!("{a} neg neg")), Ok(vec![a.to_string()]));
+        }
+
+        #[test]
+        fn lt_is_the_opposite_of_ge(a in any::<i32>(), b in any::<i32>()) {
+            prop_assert_eq!(
+                results(&format!("{a} {b} lt not")),
+                results(&format!("{a} {b} ge"))
+            );
+        }
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let mut interp = Interpreter::new();
+        let deep = format!("{}{}", "{".repeat(100_000), "}".repeat(100_000));
+        assert_eq!(interp.run(&deep).unwrap_err().name, "limitcheck");
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            max_nesting: 2,
+            ..InterpreterConfig::default()
+        });
+        interp.run("[[1]] {{2}}").unwrap();
+        assert_eq!(interp.run("[[[3]]]").unwrap_err().name, "limitcheck");
+    }
+
+    #[test]
+    fn bundled_library() {
+        let mut interp = Interpreter::new();
+        interp
+            .run("(lib/util.ps) runlibfile 2 inch 254 cm 3 5 max 3 5 min")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["144", "7200.0", "5", "3"]);
+
+        // no painting operators here, so stand in for the ones the table layout uses and
+        // see where it puts things
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            libraries: vec!["lib/util.ps".into()],
+            ..InterpreterConfig::default()
+        });
+        let drawn = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let d = drawn.clone();
+        interp.define_operator("moveto", 2, move |args| {
+            d.borrow_mut()
+                .push(format!("{} {} moveto", args[0], args[1]));
+            Ok(vec![])
+        });
+        let d = drawn.clone();
+        interp.define_operator("show", 1, move |args| {
+            d.borrow_mut().push(format!("{} show", args[0]));
+            Ok(vec![])
+        });
+        interp
+            .run("[[(a) (b)] [(c) (d)]] [10 100] 700 20 table")
+            .unwrap();
+        assert_eq!(
+            *drawn.borrow(),
+            vec![
+                "10 700 moveto",
+                "(a) show",
+                "100 700 moveto",
+                "(b) show",
+                "10 680 moveto",
+                "(c) show",
+                "100 680 moveto",
+                "(d) show",
+            ]
+        );
+        assert!(interp.stack().is_empty());
+        assert_eq!(
+            interp.run("(lib/nosuch.ps) runlibfile").unwrap_err().name,
+            "undefinedfilename"
+        );
+        // and preloading one is the same error, not a panic
+        let err = Interpreter::try_with_config(InterpreterConfig {
+            libraries: vec!["lib/util.ps".into(), "lib/nosuch.ps".into()],
+            ..InterpreterConfig::default()
+        })
+        .err()
+        .unwrap();
+        assert_eq!(err.name, "undefinedfilename");
+        assert_eq!(err.summary(), "/undefinedfilename in lib/nosuch.ps");
+    }
+
+    #[test]
+    fn configs_that_cant_start_are_errors() {
+        // the program's limits don't reach lib/init.ps, only the libraries it asks for
+        let tight = InterpreterConfig {
+            max_steps: Some(1),
+            max_vm: Some(16),
+            ..InterpreterConfig::default()
+        };
+        let mut interp = Interpreter::try_with_config(tight.clone()).unwrap();
+        assert_eq!(interp.run("1 2 add").unwrap_err().name, "timeout");
+        let err = Interpreter::try_with_config(InterpreterConfig {
+            libraries: vec!["lib/util.ps".into()],
+            ..tight
+        })
+        .err()
+        .unwrap();
+        assert_eq!(err.name, "timeout");
+    }
+
+    #[test]
+    fn dictionaries_bottom_first() {
+        let mut interp = Interpreter::new();
+        interp.run("/a 1 def 1 dict begin /b 2 def").unwrap();
+        assert_eq!(
+            interp.dictionaries(),
+            vec![
+                vec![("a".to_string(), "1".to_string())],
+                vec![("b".to_string(), "2".to_string())]
+            ]
+        );
+    }
+
+    #[test]
+    fn known_names_include_definitions() {
+        let mut interp = Interpreter::new();
+        interp
+            .run("/sq { dup mul } def /x 1 def 2 dict begin /x 2 def")
+            .unwrap();
+        let names = interp.known_names();
+        let find = |n: &str| names.iter().find(|k| k.name == n);
+        assert_eq!(find("add").unwrap().operator.unwrap().arity, 2);
+        assert!(find("sq").unwrap().operator.is_none());
+        assert_eq!(names.iter().filter(|k| k.name == "x").count(), 1);
+    }
+
+    #[test]
+    fn errors_carry_command_position_and_stack() {
+        let mut interp = Interpreter::new();
+        let err = interp.run("1 2 add (x) 3 add").unwrap_err();
+        assert_eq!(err.name, "typecheck");
+        assert_eq!(err.command.as_deref(), Some("add"));
+        assert_eq!(err.position, Some(14));
+        assert_eq!(err.to_string(), "typecheck in add");
+        assert!(interp.definitions().is_empty());
+
+        // the operands are the ones the failing operator was given, like ghostscript shows,
+        // wherever it runs
+        let err = interp.run("clear 1 2 (x) add").unwrap_err();
+        assert_eq!(err.stack, vec!["1", "2", "(x)"]);
+        let err = interp
+            .run("clear /f { exch 3 add } def 5 (y) 2 f")
+            .unwrap_err();
+        assert_eq!(err.stack, vec!["5", "2", "(y)", "3"]);
+        let err = interp.run("clear 1 true 2 ifelse").unwrap_err();
+        assert_eq!(err.stack, vec!["1", "true", "2"]);
+        let err = interp.run("clear 1 { frob } exec").unwrap_err();
+        assert_eq!(err.stack, vec!["1"]);
+        interp.run("clear $error /ostack get").unwrap();
+        assert_eq!(interp.stack(), vec!["[1]"]);
+        interp.run("clear 1 2 (x) add").unwrap_err();
+        interp.run("clear $error /ostack get").unwrap();
+        assert_eq!(interp.stack(), vec!["[1 2 (x)]"]);
+    }
+
+    #[test]
+    fn resources_include_the_standard_fonts_and_categories() {
+        let mut interp = Interpreter::new();
+        interp
+            .run("/Helvetica /Font findresource /FontName get /Font /Category findresource /Category get")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["/Helvetica", "/Font"]);
+        // keys name one file in the resource directory, never a path out of it
+        interp.set_resource_dir(".").unwrap();
+        let err = interp
+            .run("clear /src 0 /Category defineresource (../Cargo.toml) /src findresource")
+            .unwrap_err();
+        assert_eq!(err.name, "undefinedresource");
+    }
+
+    #[test]
+    fn errors_carry_the_procedures_that_were_running() {
+        let mut interp = Interpreter::new();
+        interp
+            .run("/g { 1 (x) add } def /f { g 1 pop } def")
+            .unwrap();
+        let err = interp.run("f").unwrap_err();
+        assert_eq!(err.calls, vec!["f", "g"]);
+        // a tail call takes its caller's place
+        let err = interp.run("clear /f { true { g } if } def f").unwrap_err();
+        assert_eq!(err.calls, vec!["g"]);
+        assert!(interp.run("clear 1 (x) add").unwrap_err().calls.is_empty());
+    }
+
+    #[test]
+    fn names_resolve_through_the_dictionary_stack() {
+        let mut interp = Interpreter::new();
+        let err = interp.run("1 frobnicate").unwrap_err();
+        assert_eq!(err.name, "undefined");
+        assert_eq!(err.command.as_deref(), Some("frobnicate"));
+        // procedures run when their name is executed
+        interp.run("clear /sq { dup mul } def 3 sq").unwrap();
+        assert_eq!(interp.stack(), vec!["9"]);
+        // a program's definitions shadow the operators in systemdict
+        interp.run("/add { sub } def 5 2 add").unwrap();
+        assert_eq!(interp.stack(), vec!["9", "3"]);
+        assert_eq!(interp.run("end").unwrap_err().name, "dictstackunderflow");
+    }
+
+    #[test]
+    fn every_operator_has_an_implementation() {
+        for op in operators::builtins(true) {
+            let mut interp = Interpreter::with_config(InterpreterConfig {
+                extensions: true,
+                ..InterpreterConfig::default()
+            });
+            interp.set_output(Box::new(BufferSink::new()));
+            // errors are fine, the dispatch just mustn't fall through
+            let _ = interp.run(op.name);
+        }
+    }
+
+    #[test]
+    fn printing_stops_at_the_depth_limit() {
+        let mut nested = PSObject::Int(1);
+        for _ in 0..1000 {
+            nested = PSObject::Array(vec![nested]);
+        }
+        let mut interp = Interpreter::new();
+        interp.op_stack.push(nested);
+        let out = interp.run_captured("dup ==");
+        let expected = format!("{}...{}", "[".repeat(100), "]".repeat(100));
+        assert_eq!(out.output, expected);
+        assert_eq!(out.stack, vec![expected]);
+        let out = interp.run_captured("clear (a) = (a) ==");
+        assert_eq!(out.output, "a\n(a)");
+    }
+
+    #[test]
+    fn eqeq_prints_what_reads_back() {
+        let mut interp = Interpreter::new();
+        let out =
+            interp.run_captured("/abc == ( ) print (a\\(b\\)\\\\\\n) == ( ) print [/x (y)] ==");
+        assert_eq!(out.output, "/abc (a\\(b\\)\\\\\\n) [/x (y)]");
+        for printed in out.output.split(' ').take(2) {
+            let out = interp.run_captured(&format!("{} ==", printed));
+            assert_eq!(out.output, printed);
+        }
+    }
+
+    #[test]
+    fn vm_accounting_and_ceiling() {
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            max_vm: Some(4096),
+            ..Default::default()
+        });
+        interp.run("vmstatus").unwrap();
+        assert_eq!(interp.stack(), vec!["0", "0", "4096"]);
+        interp
+            .run("clear (abcd) pop vmstatus pop exch pop")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["4"]);
+        // garbage is reclaimed when the ceiling is hit, live objects are not
+        interp.run("clear 100 { 100 dict pop } repeat").unwrap();
+        let err = interp
+            .run("/a 100 dict def /b 100 dict def /c 100 dict def")
+            .unwrap_err();
+        assert_eq!(err.name, "VMerror");
+        interp
+            .run("clear 0 vmreclaim vmstatus pop exch pop")
+            .unwrap();
+        assert!(interp.stack()[0].parse::<usize>().unwrap() >= 3200);
+        // a procedure makes a new copy of its literals every time it runs, and so does
+        // looking up a name
+        let err = interp
+            .run("clear 1000 { (a string kept on the stack) } repeat")
+            .unwrap_err();
+        assert_eq!(err.name, "VMerror");
+        let err = interp
+            .run("clear /s (a string kept on the stack) def 1000 { s } repeat")
+            .unwrap_err();
+        assert_eq!(err.name, "VMerror");
+    }
+
+    #[test]
+    fn looping_workloads_dont_leak() {
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            max_vm: Some(64 << 10),
+            ..Default::default()
+        });
+        let used = |interp: &mut Interpreter| {
+            interp
+                .run("clear 0 vmreclaim vmstatus pop exch pop")
+                .unwrap();
+            interp.pop().unwrap().to_string().parse::<usize>().unwrap()
+        };
+        interp
+            .run("lexical /g { /h { (a closure with some text in it) } def } def")
+            .unwrap();
+        let baseline = used(&mut interp);
+        // closures defined into the frame of the call that made them, and into dictionaries
+        // begun and ended, each make a cycle per time round. under a 64K ceiling they'd run
+        // out long before the end if those weren't reclaimed
+        interp.run("5000 { g } repeat").unwrap();
+        interp
+            .run("5000 { 10 dict begin /f { (more text) } def end } repeat")
+            .unwrap();
+        assert_eq!(used(&mut interp), baseline);
+        assert!(interp.suspects.is_empty());
+        // closures still in use keep their frames, which count as live
+        interp
+            .run("/make { /n exch def { n } } def /a (x) make def /b (y) make def")
+            .unwrap();
+        let live = used(&mut interp);
+        assert_eq!(interp.suspects.len(), 2);
+        interp.run("5000 { g } repeat a b").unwrap();
+        assert_eq!(interp.stack(), vec!["(x)", "(y)"]);
+        assert_eq!(used(&mut interp), live);
+    }
+
+    #[test]
+    fn vm_ceiling_holds_before_anything_is_allocated() {
+        let config = InterpreterConfig {
+            max_vm: Some(64 << 20),
+            extensions: true,
+            ..Default::default()
+        };
+        let mut interp = Interpreter::with_config(config.clone());
+        assert_eq!(interp.run("2000000000 dict").unwrap_err().name, "VMerror");
+        interp.run("clear 10 dict length").unwrap();
+        assert_eq!(interp.stack(), vec!["0"]);
+
+        // nesting deeper than the rust stack could follow stops where the scanner's does
+        let err = interp
+            .run("clear 1 20000 {1 packedarray} repeat")
+            .unwrap_err();
+        assert_eq!(err.name, "limitcheck");
+        assert_eq!(interp.operands().next().unwrap().depth(), 1000);
+
+        // and so are ndup's
+        interp.run("clear").unwrap();
+        assert_eq!(interp.run("1 2000000000 ndup").unwrap_err().name, "VMerror");
+        assert_eq!(
+            interp.run("clear 1 30000000 ndup").unwrap_err().name,
+            "VMerror"
+        );
+        interp.run("clear 1 1000 ndup count").unwrap();
+        assert_eq!(interp.pop(), Some(PSObject::Int(1001)));
+
+        // dup's copies separate when they're popped, so they're charged too
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            max_vm: Some(1 << 20),
+            extensions: true,
+            ..Default::default()
+        });
+        let text = format!("({})", "x".repeat(1000));
+        for copies in [
+            "19999 { dup } repeat",
+            "19999 { 0 over } repeat",
+            "19999 { 0 exch tuck } repeat",
+        ] {
+            let err = interp
+                .run(&format!("clear {} {} 20000 packedarray", text, copies))
+                .unwrap_err();
+            assert_eq!(err.name, "VMerror");
+        }
+
+        // copy's copies are new VM, 2^20 strings of 100 bytes don't fit in 64MB
+        let mut interp = Interpreter::with_config(config);
+        let err = interp
+            .run(&format!("({}) 1 20 {{count copy}} repeat", "x".repeat(100)))
+            .unwrap_err();
+        assert_eq!(err.name, "VMerror");
+    }
+
+    #[test]
+    fn allocation_mode() {
+        let mut interp = Interpreter::new();
+        interp
+            .run("currentglobal true setglobal currentglobal")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["false", "true"]);
+        assert_eq!(interp.run("1 setglobal").unwrap_err().name, "typecheck");
+    }
+
+    #[test]
+    fn identification() {
+        let mut interp = Interpreter::new();
+        interp
+            .run("languagelevel product version revision serialnumber")
+            .unwrap();
+        let stack = interp.stack();
+        assert_eq!(stack[..2], ["1", "(ps-interpreter)"]);
+        assert_eq!(stack[2], format!("({})", env!("CARGO_PKG_VERSION")));
+        assert_eq!(stack[4], "0");
+    }
+
+    #[test]
+    fn integer_division_signs_and_overflow() {
+        let mut interp = Interpreter::new();
+        interp
+            .run("7 3 idiv -7 3 idiv 7 -3 idiv 7 3 mod -7 3 mod 7 -3 mod -2147483648 -1 mod")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["2", "-2", "-2", "1", "-1", "1", "0"]);
+        let err = interp.run("clear -2147483648 -1 idiv").unwrap_err();
+        assert_eq!(err.name, "undefinedresult");
+        assert_eq!(interp.run("1 0 mod").unwrap_err().name, "undefinedresult");
+    }
+
+    #[test]
+    fn div_always_gives_a_real() {
+        let mut interp = Interpreter::new();
+        interp.run("1 3 div 6 2 div 7 2 idiv 1.5 0.5 div").unwrap();
+        let operands: Vec<&PSObject> = interp.operands().collect();
+        assert_eq!(
+            operands,
+            [
+                &PSObject::Real(1.0 / 3.0),
+                &PSObject::Real(3.0),
+                &PSObject::Int(3),
+                &PSObject::Real(3.0)
+            ]
+        );
+        assert_eq!(interp.run("1 0 div").unwrap_err().name, "undefinedresult");
+        assert_eq!(interp.run("(a) 1 div").unwrap_err().name, "typecheck");
+        assert_eq!(
+            interp.run("1e300 1e-300 div").unwrap_err().name,
+            "undefinedresult"
+        );
+
+        // the quotient is a number like any other
+        interp
+            .run("clear 1 3 div 3 mul 10 4 div 1 add 2 mul 7 2 div floor 1 4 div 0.25 eq")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["1.0", "7.0", "3.0", "true"]);
+    }
+
+    #[test]
+    fn packed_arrays_and_forall() {
+        let mut interp = Interpreter::new();
+        interp
+            .run("1 2 3 3 packedarray dup length exch dup 1 get exch 1 2 getinterval")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["3", "2", "[2 3]"]);
+        assert!(matches!(
+            interp.operands().last(),
+            Some(PSObject::PackedArray(_))
+        ));
+        let err = interp
+            .run("clear 0 packedarray 0 [] putinterval")
+            .unwrap_err();
+        assert_eq!(err.name, "invalidaccess");
+
+        // string offsets are bytes, and one inside a character takes just those bytes
+        interp
+            .run("clear (aéb) 1 2 getinterval (aéb) 1 (xy) putinterval")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["(é)", "(axyb)"]);
+        interp
+            .run("clear (é) 0 1 getinterval (aé) 1 (x) putinterval")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["(\\303)", "(ax\\251)"]);
+
+        interp
+            .run("clear 0 [1 2 3] { add } forall 0 (ab) { add } forall /abc length")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["6", "195", "3"]);
+        interp
+            .run("clear currentpacking true setpacking currentpacking")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["false", "true"]);
+    }
+
+    #[test]
+    fn halftone_settings_are_recorded() {
+        let mut interp = Interpreter::new();
+        interp
+            .run("currentscreen pop pop 85 0 { pop } setscreen currentscreen")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["60.0", "85", "0", "{pop }"]);
+        interp
+            .run("clear { 1 exch sub } settransfer currentcolortransfer")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["{1 exch sub }"; 4]);
+        interp.run("clear currenthalftone length").unwrap();
+        assert_eq!(interp.stack(), vec!["4"]);
+        let err = interp.run("clear 1 2 3 setscreen").unwrap_err();
+        assert_eq!(err.name, "typecheck");
+        assert_eq!(interp.stack(), vec!["1", "2", "3"]);
+    }
+
+    #[test]
+    fn programs_can_report_their_own_errors() {
+        let mut interp = Interpreter::new();
+        let err = interp.run("1 (x) add").unwrap_err();
+        assert!(!err.handled);
+        interp
+            .run("clear $error /errorname get $error /newerror get")
+            .unwrap();
+        assert_eq!(interp.stack(), vec!["/typecheck", "true"]);
+        let out = interp.run_captured("clear handleerror handleerror");
+        assert!(out
+            .output
+            .starts_with("Error: /typecheck in --add--\nOperand stack:"));
+        assert_eq!(out.output.matches("Error").count(), 1);
+
+        let src = "/handleerror { (oops: ) print $error /errorname get = } def frob";
+        let out = interp.run_captured(src);
+        assert_eq!(out.output, "oops: undefined\n");
+        assert_eq!(out.errors, "");
+        interp.run("errordict /handleerror get").unwrap();
+        assert!(matches!(
+            interp.operands().last(),
+            Some(PSObject::Procedure { .. })
+        ));
+    }
+
+    #[test]
+    fn prompt_and_executive() {
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.prompt(), "ps> ");
+        interp.run("/prompt { (my> ) print } def 1").unwrap();
+        assert_eq!(interp.prompt(), "my> ");
+        assert_eq!(interp.stack(), vec!["1"]);
+
+        assert_eq!(interp.run("executive").unwrap_err().name, "ioerror");
+        interp.set_executive(|interp| interp.run("2 3 add").map_err(|e| e.to_string()));
+        interp.run("executive 4").unwrap();
+        assert_eq!(interp.stack(), vec!["1", "5", "4"]);
+    }
+
+    #[test]
+    fn dictionaries_keep_definition_order() {
+        let mut interp = Interpreter::new();
+        interp.run("1 (x) add").unwrap_err();
+        interp.run("clear $error { pop } forall").unwrap();
+        assert_eq!(
+            interp.stack(),
+            vec!["/newerror", "/errorname", "/command", "/ostack"]
+        );
+        let out = interp.run_captured("clear $error ==");
+        assert!(out
+            .output
+            .starts_with("<</newerror true /errorname /typecheck /command /add "));
     }
 }
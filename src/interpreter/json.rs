@@ -0,0 +1,62 @@
+// just enough JSON writing for the state dump, without pulling in a serializer
+
+// s as a JSON string literal, quotes included
+pub fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// a JSON array with one element per line, indented one level deeper than `indent`. the
+// elements are JSON already
+pub fn array(items: &[String], indent: usize) -> String {
+    if items.is_empty() {
+        return "[]".into();
+    }
+    let inner = "  ".repeat(indent + 1);
+    let lines: Vec<String> = items
+        .iter()
+        .map(|item| format!("{}{}", inner, item))
+        .collect();
+    format!("[\n{}\n{}]", lines.join(",\n"), "  ".repeat(indent))
+}
+
+// a JSON object laid out like array, keys in the order given
+pub fn object(fields: &[(String, String)], indent: usize) -> String {
+    if fields.is_empty() {
+        return "{}".into();
+    }
+    let inner = "  ".repeat(indent + 1);
+    let lines: Vec<String> = fields
+        .iter()
+        .map(|(k, v)| format!("{}{}: {}", inner, quote(k), v))
+        .collect();
+    format!("{{\n{}\n{}}}", lines.join(",\n"), "  ".repeat(indent))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn escapes_and_layout() {
+        assert_eq!(quote("a\"b\\c\nd\u{1}"), r#""a\"b\\c\nd\u0001""#);
+        let inner = array(&[quote("x"), "1".into()], 1);
+        assert_eq!(
+            object(&[("k".into(), inner), ("e".into(), array(&[], 1))], 0),
+            "{\n  \"k\": [\n    \"x\",\n    1\n  ],\n  \"e\": []\n}"
+        );
+    }
+}
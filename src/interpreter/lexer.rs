@@ -0,0 +1,157 @@
+use crate::interpreter::object::PSObject;
+use crate::interpreter::scanner::{parse_token, Scanner};
+use std::borrow::Cow;
+use std::io::{self, Read};
+use std::ops::Range;
+
+// the scanner's tokens for tools that want to look at a program rather than run it, like
+// editors and highlighters. the tokenizing is the scanner's own, so they split a program
+// exactly where the interpreter does, but procedures and arrays come out a bracket at a
+// time and comments are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Integer,
+    Real,
+    Boolean,
+    // executable names, and anything else that doesn't read as something more specific
+    Name,
+    // /name
+    LiteralName,
+    String,
+    // a string the input ends inside of
+    Unterminated,
+    ProcedureOpen,
+    ProcedureClose,
+    ArrayOpen,
+    ArrayClose,
+    // from % to the end of the line, which isn't part of it
+    Comment,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    // byte offsets into the source
+    pub span: Range<usize>,
+}
+
+// iterator over the tokens of a program. whitespace between them is skipped
+pub struct Lexer<'a> {
+    src: Cow<'a, str>,
+    // byte offset of the next unread character
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str) -> Self {
+        Lexer {
+            src: Cow::Borrowed(src),
+            pos: 0,
+        }
+    }
+
+    // everything the reader has. bytes that aren't utf-8 become U+FFFD the way the scanner
+    // reads them, which moves the spans after them
+    pub fn from_reader(mut reader: impl Read) -> io::Result<Lexer<'static>> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Ok(Lexer {
+            src: Cow::Owned(String::from_utf8_lossy(&bytes).into_owned()),
+            pos: 0,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.src
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let rest = &self.src[self.pos..];
+        let start = self.pos + rest.find(|c: char| !c.is_ascii_whitespace())?;
+        let rest = &self.src[start..];
+        if rest.starts_with('%') {
+            let end = start + rest.find(['\n', '\r']).unwrap_or(rest.len());
+            self.pos = end;
+            return Some(self.token(TokenKind::Comment, start..end));
+        }
+        // one token at a time from a scanner over what's left, which hands out brackets on
+        // their own in flat mode
+        let mut scanner = Scanner::new(rest).binary_tokens(false).flat(true);
+        let raw = scanner.next_raw()?;
+        let kind = match raw.as_str() {
+            "{" => TokenKind::ProcedureOpen,
+            "}" => TokenKind::ProcedureClose,
+            "[" => TokenKind::ArrayOpen,
+            "]" => TokenKind::ArrayClose,
+            _ if raw.starts_with('(') && scanner.unterminated() => TokenKind::Unterminated,
+            _ if raw.starts_with('(') => TokenKind::String,
+            _ if raw.starts_with('/') => TokenKind::LiteralName,
+            _ => match parse_token(&raw) {
+                PSObject::Int(_) => TokenKind::Integer,
+                PSObject::Real(_) => TokenKind::Real,
+                PSObject::Bool(_) => TokenKind::Boolean,
+                _ => TokenKind::Name,
+            },
+        };
+        // the scanner also eats the whitespace after a token, so go by the token's length
+        let end = start + raw.len();
+        self.pos = end;
+        Some(self.token(kind, start..end))
+    }
+}
+
+impl Lexer<'_> {
+    fn token(&self, kind: TokenKind, span: Range<usize>) -> Token {
+        Token {
+            kind,
+            text: self.src[span.clone()].to_string(),
+            span,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tokens_with_spans() {
+        use TokenKind::*;
+        let src = "/sq{dup mul}def % square\n[1 2.5 true]x%y\n(a (b)) (c";
+        let tokens: Vec<Token> = Lexer::new(src).collect();
+        let kinds: Vec<(TokenKind, &str)> =
+            tokens.iter().map(|t| (t.kind, t.text.as_str())).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (LiteralName, "/sq"),
+                (ProcedureOpen, "{"),
+                (Name, "dup"),
+                (Name, "mul"),
+                (ProcedureClose, "}"),
+                (Name, "def"),
+                (Comment, "% square"),
+                (ArrayOpen, "["),
+                (Integer, "1"),
+                (Real, "2.5"),
+                (Boolean, "true"),
+                (ArrayClose, "]"),
+                (Name, "x"),
+                (Comment, "%y"),
+                (String, "(a (b))"),
+                (Unterminated, "(c"),
+            ]
+        );
+        for t in &tokens {
+            assert_eq!(&src[t.span.clone()], t.text);
+        }
+
+        let read: Vec<Token> = Lexer::from_reader("1 {2}".as_bytes()).unwrap().collect();
+        assert_eq!(read[2].span, 3..4);
+    }
+}
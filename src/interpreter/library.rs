@@ -0,0 +1,14 @@
+// postscript libraries compiled into the interpreter, so a program can load them with
+// runlibfile (or an embedder through InterpreterConfig::libraries) without any files around
+// the standard procedures every interpreter starts with, see InterpreterConfig::init
+pub const INIT: &str = include_str!("../../lib/init.ps");
+
+pub const LIBRARIES: &[(&str, &str)] = &[("lib/util.ps", include_str!("../../lib/util.ps"))];
+
+// the source of a bundled library by the name runlibfile takes
+pub fn get(name: &str) -> Option<&'static str> {
+    LIBRARIES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, src)| *src)
+}
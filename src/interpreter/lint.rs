@@ -0,0 +1,461 @@
+use crate::interpreter::check::{check, Diagnostic};
+use crate::interpreter::library;
+use crate::interpreter::object::PSObject;
+use crate::interpreter::operators;
+use crate::interpreter::scanner::{line_col, parse_token, Scanner};
+use std::collections::{HashMap, HashSet};
+
+// a token or a procedure/array body, with the byte offset it starts at
+#[derive(Debug)]
+enum Node {
+    Token(usize, String),
+    Group(usize, char, Vec<Node>),
+}
+
+fn parse(text: &str, base: usize) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let mut scanner = Scanner::new(text).binary_tokens(false);
+    while let Some((at, raw)) = scanner.next_raw_with_position() {
+        match raw.chars().next() {
+            Some(open @ ('{' | '[')) => {
+                let body = &text[at + 1..scanner.position() - 1];
+                nodes.push(Node::Group(base + at, open, parse(body, base + at + 1)));
+            }
+            _ => nodes.push(Node::Token(base + at, raw)),
+        }
+    }
+    nodes
+}
+
+// what running a stretch of code does to the operand stack: it needs `needs` operands that
+// were there before it and leaves `net` more than it found (or fewer, when negative)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Effect {
+    needs: usize,
+    net: isize,
+}
+
+// stack depth as far as we can follow it, relative to where the code started
+struct Depth {
+    depth: isize,
+    lowest: isize,
+    // the depth at the start, when it's known (it's 0 at the top of a program)
+    base: Option<isize>,
+    // lost track after something we can't see through
+    lost: bool,
+}
+
+impl Depth {
+    fn effect(&self) -> Option<Effect> {
+        (!self.lost).then_some(Effect {
+            needs: (-self.lowest) as usize,
+            net: self.depth,
+        })
+    }
+}
+
+struct Linter {
+    // every name the program could have defined, i.e. ever wrote as /name
+    defined: HashSet<String>,
+    // procedures bound with /name { ... } def, and their effect once worked out
+    procs: HashMap<String, Option<Effect>>,
+    found: Vec<(usize, String)>,
+}
+
+// warn about things that will go wrong when the program runs: operators used with too few
+// operands at the top level, if and ifelse whose procedures leave different stack depths,
+// names nothing defines (bundled libraries it loads included), and code after quit.
+// programs that don't scan get check's errors
+pub fn lint(src: &str) -> Vec<Diagnostic> {
+    let problems = check(src);
+    if !problems.is_empty() {
+        return problems;
+    }
+    let nodes = parse(src, 0);
+    let mut linter = Linter {
+        defined: HashSet::new(),
+        procs: HashMap::new(),
+        found: Vec::new(),
+    };
+    // what lib/init.ps defines is there from the start
+    let init = parse(library::INIT, 0);
+    linter.literal_names(&init);
+    linter.literal_names(&nodes);
+    // procedures calling procedures only get an effect once their callees have one, so go
+    // round until nothing changes
+    let mut bodies = bodies(&init);
+    bodies.extend(self::bodies(&nodes));
+    for _ in 0..bodies.len() {
+        let mut changed = false;
+        for (name, body) in &bodies {
+            let effect = linter.effect(body);
+            changed |= linter.procs.insert(name.to_string(), effect) != Some(effect);
+        }
+        if !changed {
+            break;
+        }
+    }
+    linter.walk(&nodes, Some(0), true);
+    linter.found.sort_by_key(|(at, _)| *at);
+    linter
+        .found
+        .into_iter()
+        .map(|(at, message)| {
+            let (line, column) = line_col(src.as_bytes(), at);
+            Diagnostic {
+                line,
+                column,
+                message,
+            }
+        })
+        .collect()
+}
+
+// /name { body } def, anywhere in the program
+fn bodies(nodes: &[Node]) -> HashMap<&str, &[Node]> {
+    let mut found = HashMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if let [Node::Token(_, name), Node::Group(_, '{', body), Node::Token(_, def)] =
+            &nodes[i..(i + 3).min(nodes.len())]
+        {
+            if def == "def" && name.starts_with('/') {
+                found.insert(&name[1..], &body[..]);
+            }
+        }
+        if let Node::Group(_, _, inner) = node {
+            found.extend(bodies(inner));
+        }
+    }
+    found
+}
+
+impl Linter {
+    fn literal_names(&mut self, nodes: &[Node]) {
+        for (i, node) in nodes.iter().enumerate() {
+            match node {
+                Node::Token(_, t) if t.starts_with('/') => {
+                    self.defined.insert(t[1..].to_string());
+                }
+                // (lib/util.ps) runlibfile defines whatever the bundled library does
+                Node::Token(_, t) if t.starts_with('(') => {
+                    let lib = library::get(t.trim_start_matches('(').trim_end_matches(')'));
+                    if let (Some(src), Some(Node::Token(_, next))) = (lib, nodes.get(i + 1)) {
+                        if next == "runlibfile" {
+                            self.literal_names(&parse(src, 0));
+                        }
+                    }
+                }
+                Node::Group(_, _, inner) => self.literal_names(inner),
+                _ => {}
+            }
+        }
+    }
+
+    // effect of a procedure body, without reporting anything about it
+    fn effect(&mut self, body: &[Node]) -> Option<Effect> {
+        let found = std::mem::take(&mut self.found);
+        let effect = self.walk(body, None, false);
+        self.found = found;
+        effect
+    }
+
+    fn warn(&mut self, report: bool, at: usize, message: String) {
+        if report {
+            self.found.push((at, message));
+        }
+    }
+
+    // follow the stack through a sequence of tokens. base is the depth at the start when
+    // known, report says whether to warn (procedure effects are worked out quietly)
+    fn walk(&mut self, nodes: &[Node], base: Option<isize>, report: bool) -> Option<Effect> {
+        let mut d = Depth {
+            depth: 0,
+            lowest: 0,
+            base,
+            lost: false,
+        };
+        for (i, node) in nodes.iter().enumerate() {
+            let (at, tok) = match node {
+                Node::Group(_, open, body) => {
+                    // procedures are checked on their own, array contents are just data
+                    if *open == '{' && report {
+                        self.walk(body, None, true);
+                    }
+                    d.depth += 1;
+                    continue;
+                }
+                Node::Token(at, tok) => (*at, tok.as_str()),
+            };
+            if !matches!(parse_token(tok), PSObject::Name(_, true)) {
+                d.depth += 1;
+                continue;
+            }
+            if tok == "quit" {
+                if let Some(Node::Token(at, _) | Node::Group(at, _, _)) = nodes.get(i + 1) {
+                    self.warn(report, *at, "unreachable code after quit".into());
+                }
+                d.lost = true;
+                break;
+            }
+            if let Some(op) = operators::info(tok) {
+                let results = if tok == "count" {
+                    Some(1)
+                } else {
+                    op.results()
+                };
+                self.apply(&mut d, at, tok, op.arity, results, report);
+                match tok {
+                    // back to empty, which we only know relative to a known start
+                    "clear" => match d.base {
+                        Some(base) => d.depth = -base,
+                        None => d.lost = true,
+                    },
+                    "if" | "ifelse" => self.branches(&mut d, nodes, i, at, report),
+                    "exec" | "repeat" | "for" | "forall" | "loop" | "stopped"
+                    | "filenameforall" | "resourceforall" => {
+                        self.runs(&mut d, nodes, i, at, report)
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            match self.procs.get(tok) {
+                Some(&Some(effect)) => self.apply_effect(&mut d, at, tok, effect, report),
+                Some(None) => d.lost = true,
+                None if self.defined.contains(tok) => d.lost = true,
+                None => {
+                    self.warn(report, at, format!("undefined name {}", tok));
+                    d.lost = true;
+                }
+            }
+        }
+        d.effect()
+    }
+
+    // take `arity` operands, warning at the top of the program when there aren't that many,
+    // then push the results (or give up following when we can't know how many there are)
+    fn apply(
+        &mut self,
+        d: &mut Depth,
+        at: usize,
+        name: &str,
+        arity: usize,
+        results: Option<usize>,
+        report: bool,
+    ) {
+        if d.lost {
+            return;
+        }
+        let after = d.depth - arity as isize;
+        if let Some(base) = d.base {
+            if base + after < 0 {
+                let message = format!(
+                    "{} needs {} operand{} but the stack only has {}",
+                    name,
+                    arity,
+                    if arity == 1 { "" } else { "s" },
+                    base + d.depth
+                );
+                self.warn(report, at, message);
+                // carry on as if they had been there
+                d.base = Some(-after);
+            }
+        }
+        d.lowest = d.lowest.min(after);
+        d.depth = after;
+        match results {
+            Some(n) => d.depth += n as isize,
+            None => d.lost = true,
+        }
+    }
+
+    fn apply_effect(&mut self, d: &mut Depth, at: usize, name: &str, e: Effect, report: bool) {
+        let results = (e.needs as isize + e.net) as usize;
+        self.apply(d, at, name, e.needs, Some(results), report);
+    }
+
+    // operators that run a procedure: their usage lines say they push nothing, but the
+    // procedure can. it's followed when it's written right there and how many times it runs
+    // can be read off literals in front of it, otherwise we lose track
+    fn runs(&mut self, d: &mut Depth, nodes: &[Node], i: usize, at: usize, report: bool) {
+        let Node::Token(_, op) = &nodes[i] else {
+            return;
+        };
+        let before = |back: usize| i.checked_sub(back).map(|j| &nodes[j]);
+        let int = |back: usize| match before(back) {
+            Some(Node::Token(_, t)) => match parse_token(t) {
+                PSObject::Int(n) => Some(n),
+                _ => None,
+            },
+            _ => None,
+        };
+        // how many times it runs, and what each time pushes before the procedure does
+        let times = match op.as_str() {
+            "exec" => Some((1, 0)),
+            "repeat" => int(2).map(|n| (n.max(0), 0)),
+            "for" => match (int(4), int(3), int(2)) {
+                (Some(init), Some(incr), Some(limit)) if incr != 0 => {
+                    Some((((limit - init) / incr + 1).max(0), 1))
+                }
+                _ => None,
+            },
+            "forall" => match before(2) {
+                Some(Node::Group(_, '[', items)) => Some((items.len() as i64, 1)),
+                Some(Node::Token(_, t)) => match parse_token(t) {
+                    PSObject::String(s, _) => Some((s.len() as i64, 1)),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        };
+        let body = match before(1) {
+            Some(Node::Group(_, '{', body)) => self.effect(body),
+            _ => None,
+        };
+        let (Some((times, pushed)), Some(e)) = (times, body) else {
+            d.lost = true;
+            return;
+        };
+        // once round is enough when the depth comes back where it was, and more than a
+        // few hundred times round is more than is worth following
+        let each = e.net + pushed;
+        let rounds = if each == 0 { times.min(1) } else { times };
+        if rounds > 1000 {
+            d.lost = true;
+            return;
+        }
+        let name = format!("{} procedure", op);
+        for _ in 0..rounds {
+            d.depth += pushed;
+            self.apply_effect(d, at, &name, e, report);
+        }
+    }
+
+    // the procedures given to if/ifelse (when written right there) should leave the stack
+    // the same depth whichever way the condition goes
+    fn branches(&mut self, d: &mut Depth, nodes: &[Node], i: usize, at: usize, report: bool) {
+        let op = if matches!(&nodes[i], Node::Token(_, t) if t == "if") {
+            "if"
+        } else {
+            "ifelse"
+        };
+        let body = |back: usize| match i.checked_sub(back).map(|j| &nodes[j]) {
+            Some(Node::Group(_, '{', body)) => Some(&body[..]),
+            _ => None,
+        };
+        let effect = if op == "if" {
+            match body(1).map(|b| self.effect(b)) {
+                Some(Some(e)) if e.net != 0 => {
+                    let message = format!(
+                        "if procedure changes the stack depth by {:+}, so it depends on the condition",
+                        e.net
+                    );
+                    self.warn(report, at, message);
+                    None
+                }
+                Some(e) => e,
+                None => None,
+            }
+        } else {
+            match (
+                body(2).map(|b| self.effect(b)),
+                body(1).map(|b| self.effect(b)),
+            ) {
+                (Some(Some(a)), Some(Some(b))) if a.net != b.net => {
+                    let message = format!(
+                        "ifelse branches leave different stack depths ({:+} and {:+})",
+                        a.net, b.net
+                    );
+                    self.warn(report, at, message);
+                    None
+                }
+                (Some(Some(a)), Some(Some(b))) => Some(Effect {
+                    needs: a.needs.max(b.needs),
+                    net: a.net,
+                }),
+                _ => None,
+            }
+        };
+        match effect {
+            Some(e) => self.apply_effect(d, at, &format!("{} procedure", op), e, report),
+            None => d.lost = true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn messages(src: &str) -> Vec<String> {
+        lint(src).iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn clean_program() {
+        let src = "/sq { dup mul } def\n3 sq 2 1 gt { 1 add } if 4 eq { (y) } { (n) } ifelse print";
+        assert_eq!(messages(src), Vec::<String>::new());
+        // callers defined before their callees
+        let src = "/quad { sq sq } def /sq { dup mul } def quad";
+        assert_eq!(
+            messages(src),
+            vec!["1:41: quad needs 1 operand but the stack only has 0"]
+        );
+        assert!(messages("1 2 clear count pop").is_empty());
+        // operators that run procedures push what the procedure does
+        assert!(messages("{ 1 2 } exec add").is_empty());
+        assert!(messages("0 1 3 {} for add add add").is_empty());
+        assert!(messages("[1 2 3] {} forall add add").is_empty());
+        assert!(messages("(ab) {} forall add 3 { 1 } repeat add add").is_empty());
+        assert_eq!(
+            messages("2 { 1 } repeat add add"),
+            vec!["1:20: add needs 2 operands but the stack only has 1"]
+        );
+        // and when it can't be followed, nothing is said
+        assert!(messages("/n 3 def n { 1 } repeat add").is_empty());
+    }
+
+    #[test]
+    fn underflow_at_the_top_level() {
+        assert_eq!(
+            messages("1 add"),
+            vec!["1:3: add needs 2 operands but the stack only has 1"]
+        );
+        // through a procedure with a known effect
+        assert_eq!(
+            messages("/sq { dup mul } def sq"),
+            vec!["1:21: sq needs 1 operand but the stack only has 0"]
+        );
+    }
+
+    #[test]
+    fn unbalanced_branches() {
+        assert_eq!(
+            messages("/f { { 1 } { } ifelse } def"),
+            vec!["1:16: ifelse branches leave different stack depths (+1 and +0)"]
+        );
+        assert_eq!(
+            messages("true { 1 2 } if"),
+            vec![
+                "1:14: if procedure changes the stack depth by +2, so it depends on the condition"
+            ]
+        );
+    }
+
+    #[test]
+    fn undefined_names_and_dead_code() {
+        assert_eq!(
+            messages("frobnicate /x 1 def x quit 1"),
+            vec![
+                "1:1: undefined name frobnicate",
+                "1:28: unreachable code after quit"
+            ]
+        );
+        // names from a bundled library, once it's loaded
+        assert!(messages("(lib/util.ps) runlibfile 1 inch pop").is_empty());
+        assert_eq!(messages("1 inch pop"), vec!["1:3: undefined name inch"]);
+        // and from lib/init.ps, always
+        assert!(messages("true false xor pstack").is_empty());
+    }
+}
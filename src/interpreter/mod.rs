@@ -1,3 +1,27 @@
+pub mod binary;
+pub mod check;
+pub mod clock;
+pub mod config;
+pub mod docinfo;
+pub mod dsc;
+pub mod editor;
+pub mod eps;
+pub mod error;
+pub mod file;
+pub mod format;
+pub mod fuzz;
+pub mod geometry;
+pub mod highlight;
+#[allow(clippy::module_inception)]
 pub mod interpreter;
+pub mod json;
+pub mod lexer;
+pub mod library;
+pub mod lint;
 pub mod object;
+pub mod operators;
+pub mod pages;
+pub mod printf;
+pub mod resource;
+pub mod scanner;
 pub mod stack;
@@ -1,42 +1,481 @@
-use std::collections::HashMap;
+use crate::interpreter::file::PSFile;
+use crate::interpreter::scanner;
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::rc::Rc;
+
+// dictionaries keep their entries in the order they were first defined, so forall and ==
+// come out the same on every run
+pub type DictMap = IndexMap<String, PSObject>;
+
+// a dictionary on the dictionary stack, linked to the one it was pushed on top of. lexical
+// procedures keep the scope they were made in alive and look names up through it, so they
+// see what gets defined there after them too
+pub struct Scope {
+    pub map: RefCell<DictMap>,
+    pub parent: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    pub fn new(map: DictMap, parent: Option<Rc<Scope>>) -> Rc<Scope> {
+        Rc::new(Scope {
+            map: RefCell::new(map),
+            parent,
+        })
+    }
+
+    // the innermost definition of name, following the static links out to systemdict
+    pub fn lookup(&self, name: &str) -> Option<PSObject> {
+        let mut scope = Some(self);
+        while let Some(s) = scope {
+            if let Some(v) = s.map.borrow().get(name) {
+                return Some(v.clone());
+            }
+            scope = s.parent.as_deref();
+        }
+        None
+    }
+
+    // the scopes this one keeps alive: its parent and the static link of every procedure in
+    // it, however deeply nested
+    fn links(&self) -> Vec<Rc<Scope>> {
+        let map = self.map.borrow();
+        let mut links: Vec<Rc<Scope>> = self.parent.iter().cloned().collect();
+        let mut todo: Vec<&PSObject> = map.values().collect();
+        while let Some(obj) = todo.pop() {
+            match obj {
+                PSObject::Procedure { code, env } => {
+                    links.extend(env.iter().cloned());
+                    todo.extend(code);
+                }
+                PSObject::Array(items) | PSObject::PackedArray(items) => todo.extend(items),
+                PSObject::Dict(d) => todo.extend(d.values()),
+                _ => {}
+            }
+        }
+        links
+    }
+}
+
+// a lexical procedure defined into the frame it was made in keeps that frame alive through
+// its static link while the frame keeps it: a cycle Rc alone never frees. given frames that
+// were still linked to when they were popped, find the ones nothing but such cycles keep
+// alive, by taking the links between them off their reference counts (trial deletion), and
+// empty them so they go. roots (the dictionary stack) are alive whatever links to them.
+// hands back the suspects that are still alive, since they can become garbage later
+pub fn sweep(suspects: Vec<Rc<Scope>>, roots: &[Rc<Scope>]) -> Vec<Rc<Scope>> {
+    let roots: HashSet<*const Scope> = roots.iter().map(Rc::as_ptr).collect();
+    // every scope the suspects reach short of the roots, held once each, with its links
+    let mut nodes: HashMap<*const Scope, (Rc<Scope>, Vec<*const Scope>)> = HashMap::new();
+    let mut todo = suspects;
+    while let Some(scope) = todo.pop() {
+        let key = Rc::as_ptr(&scope);
+        if roots.contains(&key) || nodes.contains_key(&key) {
+            continue;
+        }
+        let links = scope.links();
+        let edges = links.iter().map(Rc::as_ptr).collect();
+        todo.extend(links);
+        nodes.insert(key, (scope, edges));
+    }
+    let mut inside: HashMap<*const Scope, usize> = HashMap::new();
+    for (_, edges) in nodes.values() {
+        for edge in edges.iter().filter(|e| nodes.contains_key(e)) {
+            *inside.entry(*edge).or_default() += 1;
+        }
+    }
+    // referred to from outside (other than by nodes itself) means alive, and so is
+    // everything it links to
+    let mut todo: Vec<*const Scope> = nodes
+        .iter()
+        .filter(|(key, (scope, _))| {
+            Rc::strong_count(scope) - 1 > inside.get(key).copied().unwrap_or(0)
+        })
+        .map(|(key, _)| *key)
+        .collect();
+    let mut alive = HashSet::new();
+    while let Some(key) = todo.pop() {
+        if alive.insert(key) {
+            todo.extend(nodes[&key].1.iter().filter(|e| nodes.contains_key(e)));
+        }
+    }
+    let mut survivors = Vec::new();
+    for (key, (scope, _)) in nodes {
+        if alive.contains(&key) {
+            survivors.push(scope);
+        } else {
+            let garbage = std::mem::take(&mut *scope.map.borrow_mut());
+            drop(garbage);
+        }
+    }
+    survivors
+}
+
+// scopes are compared by identity: two procedures are only the same if they were made in the
+// same place. printing one would print everything it can see, so it's just its size
+impl PartialEq for Scope {
+    fn eq(&self, other: &Scope) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Scope({} entries)", self.map.borrow().len())
+    }
+}
 
 // enumerated type PSStack to contain all necessary default values of PS
 // we NEED the debug here for us to print out the values of psobject, and when we want to make
 // clones of our objects
 #[derive(Debug, Clone, PartialEq)]
 pub enum PSObject {
-    Int(i32),
+    Int(i64),
     Bool(bool),
     Real(f64),
-    String(String),
-    Dict(HashMap<String, PSObject>),
+    // bytes, which are often but not always UTF-8 text, and whether it's executable: cvx
+    // makes one that's scanned and run when it's executed
+    String(Vec<u8>, bool),
+    Dict(DictMap),
     Array(Vec<PSObject>),
-    // represents literal and executable names
-    Name(String),
+    // from packedarray: read only, so operators that write into arrays refuse it
+    PackedArray(Vec<PSObject>),
+    // a name and whether it's executable: `foo` is and runs what it's bound to, `/foo` isn't
+    // and pushes itself
+    Name(String, bool),
+
+    // from `file`: one of the standard files, or a file on disk being read
+    File(PSFile),
+
+    // a built in operator, as bound in systemdict
+    Operator(String),
 
     // CARRIES A STATIC LINK IN PROCEDURES
     Procedure {
         code: Vec<PSObject>,
-        env: HashMap<String, PSObject>,
+        // the scope a procedure made under lexical scoping was made in. None for dynamic
+        // ones, and for procedures that haven't been pushed yet (still inside other code)
+        env: Option<Rc<Scope>>,
     },
 }
 
+// rough bytes of VM a composite's elements take, per element and per dict entry
+pub const VM_SLOT: usize = 8;
+pub const VM_ENTRY: usize = 2 * VM_SLOT;
+
+impl PSObject {
+    // approximately how much VM the object holds: string bytes, array and procedure slots and
+    // dict entries (by capacity, since that's what dict allocates), counting what's nested
+    // inside too. simple objects live on the stacks and take none. walks a work list rather
+    // than recursing, for the same reason as deep_copy
+    pub fn vm_size(&self) -> usize {
+        let mut size = 0;
+        let mut todo = vec![self];
+        while let Some(obj) = todo.pop() {
+            match obj {
+                PSObject::String(s, _) => size += s.len(),
+                PSObject::Array(items)
+                | PSObject::PackedArray(items)
+                | PSObject::Procedure { code: items, .. } => {
+                    size += VM_SLOT * items.len();
+                    todo.extend(items);
+                }
+                PSObject::Dict(d) => {
+                    size += VM_ENTRY * d.capacity().max(d.len());
+                    for (k, v) in d {
+                        size += k.len();
+                        todo.push(v);
+                    }
+                }
+                _ => {}
+            }
+        }
+        size
+    }
+
+    // how many composites deep it goes, 0 for a simple object. dropping and printing recurse,
+    // so operators that nest objects at run time hold them to the same limit as the scanner
+    pub fn depth(&self) -> usize {
+        let mut deepest = 0;
+        let mut todo = vec![(self, 0)];
+        while let Some((obj, level)) = todo.pop() {
+            let items: Box<dyn Iterator<Item = &PSObject>> = match obj {
+                PSObject::Array(items)
+                | PSObject::PackedArray(items)
+                | PSObject::Procedure { code: items, .. } => Box::new(items.iter()),
+                PSObject::Dict(d) => Box::new(d.values()),
+                _ => continue,
+            };
+            deepest = deepest.max(level + 1);
+            todo.extend(items.map(|item| (item, level + 1)));
+        }
+        deepest
+    }
+}
+
+impl PSObject {
+    // the text cvs makes of the object: strings as they are, names without the slash, numbers,
+    // booleans and operator names. anything else has none. reals get `digits` significant
+    // digits, see format_real
+    pub fn cvs(&self, digits: Option<usize>) -> Vec<u8> {
+        match self {
+            PSObject::String(s, _) => s.clone(),
+            PSObject::Name(n, _) => n.as_bytes().to_vec(),
+            PSObject::Operator(n) => n.as_bytes().to_vec(),
+            PSObject::Int(_) | PSObject::Real(_) | PSObject::Bool(_) => {
+                self.to_text(digits).into_bytes()
+            }
+            _ => "--nostringval--".into(),
+        }
+    }
+
+    // what Display prints, with reals (nested ones too) to `digits` significant digits
+    pub fn to_text(&self, digits: Option<usize>) -> String {
+        Limited(self, MAX_PRINT_DEPTH, digits).to_string()
+    }
+}
+
+// a real the way postscript interpreters print it: always with a point or an exponent, so it
+// reads back as a real (3.0, not 3), and in exponent form when it's very big or small
+// (1.0e+20, 1.5e-07). digits rounds it to that many significant digits like %g, Ghostscript
+// uses 6. None gives the fewest digits that read back as the same number
+pub fn format_real(r: f64, digits: Option<usize>) -> String {
+    if !r.is_finite() {
+        return r.to_string();
+    }
+    // d.ddde±x, rounded or as short as it can be
+    let sci = match digits {
+        Some(digits) => format!("{:.*e}", digits.max(1) - 1, r),
+        None => format!("{:e}", r),
+    };
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let mantissa = trim_zeros(mantissa);
+    // %g switches at the precision, shortest switches where f64 stops being exact
+    let limit = digits.map_or(16, |d| d.max(1) as i32);
+    if exp < -4 || exp >= limit {
+        let sign = if exp < 0 { '-' } else { '+' };
+        return format!("{}e{}{:02}", mantissa, sign, exp.abs());
+    }
+    let fixed = match digits {
+        Some(digits) => format!("{:.*}", (digits.max(1) as i32 - 1 - exp).max(0) as usize, r),
+        None => r.to_string(),
+    };
+    trim_zeros(&fixed)
+}
+
+// 2.500 -> 2.5 and 3.000 -> 3.0
+fn trim_zeros(text: &str) -> String {
+    if !text.contains('.') {
+        return format!("{}.0", text);
+    }
+    let text = text.trim_end_matches('0');
+    match text.strip_suffix('.') {
+        Some(whole) => format!("{}.0", whole),
+        None => text.to_string(),
+    }
+}
+
+// a composite being rebuilt by deep_copy, waiting for its copied elements
+enum Rebuild {
+    Array(usize),
+    PackedArray(usize),
+    // the keys, and the room the original has, which is VM it was charged for
+    Dict(Vec<String>, usize),
+    // code length and the static link, which the copy shares
+    Procedure(usize, Option<Rc<Scope>>),
+}
+
+impl PSObject {
+    // a copy of the object and everything in it, made with an explicit work list so nesting
+    // deeper than the rust stack copies fine (derived clone recurses)
+    pub fn deep_copy(&self) -> PSObject {
+        enum Work<'a> {
+            Copy(&'a PSObject),
+            Build(Rebuild),
+        }
+        let mut work = vec![Work::Copy(self)];
+        let mut done: Vec<PSObject> = Vec::new();
+        while let Some(item) = work.pop() {
+            let obj = match item {
+                Work::Copy(obj) => obj,
+                Work::Build(rebuild) => {
+                    let take = |done: &mut Vec<PSObject>, n: usize| done.split_off(done.len() - n);
+                    let built = match rebuild {
+                        Rebuild::Array(n) => PSObject::Array(take(&mut done, n)),
+                        Rebuild::PackedArray(n) => PSObject::PackedArray(take(&mut done, n)),
+                        Rebuild::Dict(keys, room) => {
+                            let values = take(&mut done, keys.len());
+                            let mut dict = DictMap::with_capacity(room);
+                            dict.extend(keys.into_iter().zip(values));
+                            PSObject::Dict(dict)
+                        }
+                        Rebuild::Procedure(n, env) => PSObject::Procedure {
+                            code: take(&mut done, n),
+                            env,
+                        },
+                    };
+                    done.push(built);
+                    continue;
+                }
+            };
+            // elements are pushed in reverse so they come off the work list in order
+            match obj {
+                PSObject::Array(items) => {
+                    work.push(Work::Build(Rebuild::Array(items.len())));
+                    work.extend(items.iter().rev().map(Work::Copy));
+                }
+                PSObject::PackedArray(items) => {
+                    work.push(Work::Build(Rebuild::PackedArray(items.len())));
+                    work.extend(items.iter().rev().map(Work::Copy));
+                }
+                PSObject::Dict(d) => {
+                    let (keys, values): (Vec<&String>, Vec<&PSObject>) = d.iter().unzip();
+                    work.push(Work::Build(Rebuild::Dict(
+                        keys.into_iter().cloned().collect(),
+                        d.capacity(),
+                    )));
+                    work.extend(values.into_iter().rev().map(Work::Copy));
+                }
+                PSObject::Procedure { code, env } => {
+                    work.push(Work::Build(Rebuild::Procedure(code.len(), env.clone())));
+                    work.extend(code.iter().rev().map(Work::Copy));
+                }
+                simple => done.push(simple.clone()),
+            }
+        }
+        done.pop().unwrap()
+    }
+
+    // what eq means: the same as == except that numbers compare by value, walking pairs with
+    // a work list instead of recursing, for the same reason as deep_copy
+    pub fn deep_eq(&self, other: &PSObject) -> bool {
+        let mut pairs = vec![(self, other)];
+        while let Some(pair) = pairs.pop() {
+            match pair {
+                (PSObject::Array(a), PSObject::Array(b))
+                | (PSObject::PackedArray(a), PSObject::PackedArray(b)) => {
+                    if a.len() != b.len() {
+                        return false;
+                    }
+                    pairs.extend(a.iter().zip(b));
+                }
+                (PSObject::Dict(a), PSObject::Dict(b)) => {
+                    if !same_entries(a, b, &mut pairs) {
+                        return false;
+                    }
+                }
+                (
+                    PSObject::Procedure { code: a, env: ea },
+                    PSObject::Procedure { code: b, env: eb },
+                ) => {
+                    if ea != eb || a.len() != b.len() {
+                        return false;
+                    }
+                    pairs.extend(a.iter().zip(b));
+                }
+                (
+                    PSObject::Array(_)
+                    | PSObject::PackedArray(_)
+                    | PSObject::Dict(_)
+                    | PSObject::Procedure { .. },
+                    _,
+                )
+                | (
+                    _,
+                    PSObject::Array(_)
+                    | PSObject::PackedArray(_)
+                    | PSObject::Dict(_)
+                    | PSObject::Procedure { .. },
+                ) => return false,
+                // numbers are equal by value whatever their type, 3 eq 3.0
+                (PSObject::Int(i), PSObject::Real(r)) | (PSObject::Real(r), PSObject::Int(i)) => {
+                    if *i as f64 != *r {
+                        return false;
+                    }
+                }
+                // whether they're executable doesn't matter, cvx doesn't change the value
+                (PSObject::String(a, _), PSObject::String(b, _)) => {
+                    if a != b {
+                        return false;
+                    }
+                }
+                (PSObject::Name(a, _), PSObject::Name(b, _)) => {
+                    if a != b {
+                        return false;
+                    }
+                }
+                (a, b) => {
+                    if a != b {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+// same keys on both sides, queueing the values to compare
+fn same_entries<'a>(
+    a: &'a DictMap,
+    b: &'a DictMap,
+    pairs: &mut Vec<(&'a PSObject, &'a PSObject)>,
+) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    for (k, va) in a {
+        match b.get(k) {
+            Some(vb) => pairs.push((va, vb)),
+            None => return false,
+        }
+    }
+    true
+}
+
+// how deep the printer follows nested composites before writing ... instead, so a huge
+// nesting can't overflow the rust stack when it's printed by == or in an error
+pub const MAX_PRINT_DEPTH: usize = 100;
+
 impl fmt::Display for PSObject {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
+        Limited(self, MAX_PRINT_DEPTH, None).fmt(f)
+    }
+}
+
+// an object printed with at most `depth` more levels of nesting, reals to the digits asked for
+struct Limited<'a>(&'a PSObject, usize, Option<usize>);
+
+impl fmt::Display for Limited<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Limited(obj, depth, digits) = *self;
+        match obj {
+            PSObject::Array(_)
+            | PSObject::PackedArray(_)
+            | PSObject::Dict(_)
+            | PSObject::Procedure { .. }
+                if depth == 0 =>
+            {
+                write!(f, "...")
+            }
             PSObject::Int(n) => write!(f, "{n}"),
-            PSObject::Real(r) => write!(f, "{r}"),
+            PSObject::Real(r) => write!(f, "{}", format_real(*r, digits)),
             PSObject::Bool(b) => write!(f, "{}", if *b { "true" } else { "false" }),
-            PSObject::String(s) => write!(f, "({})", s.replace(')', "\\)")),
-            PSObject::Name(n) => write!(f, "{}", n),
-            PSObject::Array(arr) => {
+            PSObject::String(s, _) => write!(f, "{}", scanner::escape(s)),
+            PSObject::Name(n, true) => write!(f, "{}", n),
+            PSObject::Name(n, false) => write!(f, "/{}", n),
+            PSObject::File(_) => write!(f, "-file-"),
+            PSObject::Operator(name) => write!(f, "--{name}--"),
+            PSObject::Array(arr) | PSObject::PackedArray(arr) => {
                 write!(f, "[")?;
                 for (i, obj) in arr.iter().enumerate() {
                     if i != 0 {
                         write!(f, " ")?;
                     }
-                    write!(f, "{obj}")?;
+                    write!(f, "{}", Limited(obj, depth - 1, digits))?;
                 }
                 write!(f, "]")
             }
@@ -44,17 +483,82 @@ impl fmt::Display for PSObject {
                 write!(f, "<<")?;
                 // print “/key value ” for each entry
                 for (k, v) in d {
-                    write!(f, "/{k} {v} ")?;
+                    write!(f, "/{k} {} ", Limited(v, depth - 1, digits))?;
                 }
                 write!(f, ">>")
             }
             PSObject::Procedure { code, .. } => {
                 write!(f, "{{")?;
                 for obj in code {
-                    write!(f, "{obj} ")?;
+                    write!(f, "{} ", Limited(obj, depth - 1, digits))?;
                 }
                 write!(f, "}}")
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn nested(depth: usize) -> PSObject {
+        let mut obj = PSObject::Int(1);
+        for _ in 0..depth {
+            obj = PSObject::Array(vec![obj, PSObject::String("s".into(), false)]);
+        }
+        obj
+    }
+
+    #[test]
+    fn reals_read_back_as_reals() {
+        assert_eq!(format_real(3.0, None), "3.0");
+        assert_eq!(format_real(0.5, None), "0.5");
+        assert_eq!(format_real(-0.001, None), "-0.001");
+        assert_eq!(format_real(0.1 + 0.2, None), "0.30000000000000004");
+        assert_eq!(format_real(1e20, None), "1.0e+20");
+        assert_eq!(format_real(1.5e-7, None), "1.5e-07");
+        assert_eq!(format_real(1.0 / 3.0, Some(6)), "0.333333");
+        assert_eq!(format_real(0.1 + 0.2, Some(6)), "0.3");
+        assert_eq!(format_real(1234567.0, Some(6)), "1.23457e+06");
+        assert_eq!(format_real(9.9999999, Some(6)), "10.0");
+        assert_eq!(format_real(f64::INFINITY, None), "inf");
+        for r in [3.0, 1e20, 1.5e-7, 0.1 + 0.2, -123.456] {
+            let text = format_real(r, None);
+            assert_eq!(text.parse::<f64>(), Ok(r));
+            assert!(matches!(
+                crate::interpreter::scanner::Scanner::new(&text).next(),
+                Some(Ok(PSObject::Real(_)))
+            ));
+        }
+    }
+
+    #[test]
+    fn deep_copy_keeps_structure() {
+        let env = Scope::new(DictMap::from([("x".to_string(), PSObject::Int(2))]), None);
+        let proc = PSObject::Procedure {
+            code: vec![PSObject::Name("x".into(), true), nested(3)],
+            env: Some(env),
+        };
+        let dict = PSObject::Dict(DictMap::from([("p".to_string(), proc)]));
+        let packed = PSObject::PackedArray(vec![dict, PSObject::Bool(true)]);
+        let copy = packed.deep_copy();
+        assert_eq!(copy, packed);
+        assert!(copy.deep_eq(&packed));
+        assert!(!nested(3).deep_eq(&nested(4)));
+        assert!(!PSObject::Array(vec![]).deep_eq(&PSObject::PackedArray(vec![])));
+    }
+
+    #[test]
+    fn deep_nesting_without_recursion() {
+        let deep = nested(200_000);
+        let copy = deep.deep_copy();
+        assert!(copy.deep_eq(&deep));
+        // dropping recurses too, so take the copies apart by hand
+        for mut obj in [deep, copy] {
+            while let PSObject::Array(mut items) = obj {
+                obj = items.swap_remove(0);
+            }
+        }
+    }
+}
@@ -0,0 +1,231 @@
+// what editors and help screens get to know about a built in operator
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OperatorInfo {
+    pub name: &'static str,
+    // operands taken off the stack (the fixed ones, copy and friends take more)
+    pub arity: usize,
+    // operands before and results after, in the PLRM's notation
+    pub usage: &'static str,
+    pub description: &'static str,
+}
+
+// a name a program can use right now, built in or defined
+#[derive(Debug, Clone, PartialEq)]
+pub struct KnownName {
+    pub name: String,
+    // None for names defined by the program (or the embedder)
+    pub operator: Option<&'static OperatorInfo>,
+}
+
+macro_rules! ops {
+    ($(($name:literal, $arity:literal, $usage:literal, $desc:literal),)*) => {
+        &[$(OperatorInfo {
+            name: $name,
+            arity: $arity,
+            usage: $usage,
+            description: $desc,
+        }),*]
+    };
+}
+
+// every built in operator, this is what makes a name an operator
+#[rustfmt::skip]
+pub const OPERATORS: &[OperatorInfo] = ops![
+    // arithmetic
+    ("add", 2, "num1 num2 add sum", "add two numbers"),
+    ("sub", 2, "num1 num2 sub difference", "subtract num2 from num1"),
+    ("mul", 2, "num1 num2 mul product", "multiply two numbers"),
+    ("div", 2, "num1 num2 div quotient", "divide num1 by num2"),
+    ("idiv", 2, "int1 int2 idiv quotient", "integer divide"),
+    ("mod", 2, "int1 int2 mod remainder", "remainder of int1 / int2"),
+    ("abs", 1, "num abs num", "absolute value"),
+    ("neg", 1, "num neg num", "negate"),
+    ("ceiling", 1, "num ceiling num", "round up to an integer"),
+    ("floor", 1, "num floor num", "round down to an integer"),
+    ("round", 1, "num round num", "round to the nearest integer"),
+    ("truncate", 1, "num truncate num", "remove the fractional part"),
+    ("sqrt", 1, "num sqrt real", "square root"),
+    ("exp", 2, "base exponent exp real", "raise base to the exponent"),
+    ("ln", 1, "num ln real", "natural logarithm"),
+    ("rand", 0, "- rand int", "pseudo-random integer from 1 to 2^31 - 2"),
+    ("srand", 1, "int srand -", "seed rand"),
+    ("rrand", 0, "- rrand int", "rand's current seed"),
+    // comparison and logic
+    ("eq", 2, "any1 any2 eq bool", "test equal"),
+    ("ne", 2, "any1 any2 ne bool", "test not equal"),
+    ("gt", 2, "num1 num2 gt bool", "test greater than"),
+    ("lt", 2, "num1 num2 lt bool", "test less than"),
+    ("ge", 2, "num1 num2 ge bool", "test greater or equal"),
+    ("le", 2, "num1 num2 le bool", "test less or equal"),
+    ("and", 2, "bool1 bool2 and bool", "logical and"),
+    ("or", 2, "bool1 bool2 or bool", "logical or"),
+    ("not", 1, "bool not bool", "logical not"),
+    ("true", 0, "- true true", "push true"),
+    ("false", 0, "- false false", "push false"),
+    // stack
+    ("exch", 2, "any1 any2 exch any2 any1", "swap the top two"),
+    ("pop", 1, "any pop -", "discard the top"),
+    ("dup", 1, "any dup any any", "duplicate the top"),
+    ("copy", 1, "any1..anyn n copy any1..anyn any1..anyn", "duplicate the top n"),
+    ("index", 1, "anyn..any0 n index anyn..any0 anyn", "copy the object n down, 0 index is dup"),
+    ("clear", 0, "any1..anyn clear -", "empty the stack"),
+    ("count", 0, "any1..anyn count any1..anyn n", "count the stack"),
+    // dictionaries
+    ("dict", 1, "int dict dict", "create a dictionary"),
+    ("begin", 1, "dict begin -", "push dict on the dictionary stack"),
+    ("end", 0, "- end -", "pop the dictionary stack"),
+    ("def", 2, "key value def -", "associate key with value"),
+    ("length", 1, "obj length int", "number of elements, bytes for a string"),
+    ("maxlength", 1, "dict maxlength int", "capacity of dict"),
+    ("lexical", 0, "- lexical -", "switch to lexical scoping"),
+    ("dynamic", 0, "- dynamic -", "switch to dynamic scoping"),
+    ("currentscoping", 0, "- currentscoping name", "/lexical or /dynamic, what new procedures get"),
+    // strings and arrays
+    ("get", 2, "obj index get any", "get one element"),
+    ("getinterval", 3, "obj index count getinterval sub", "get a subsequence"),
+    ("cvs", 2, "any string cvs substring", "text of any, rangecheck if string is too short"),
+    ("putinterval", 3, "obj1 index obj2 putinterval -", "overwrite a subsequence"),
+    ("token", 1, "string|file token post any true | any true | false", "read one token from a string or a file"),
+    ("packedarray", 1, "any0..anyn-1 n packedarray packedarray", "make a read only array of the top n"),
+    ("setpacking", 1, "bool setpacking -", "set the packing mode"),
+    ("currentpacking", 0, "- currentpacking bool", "current packing mode"),
+    // control
+    ("if", 2, "bool proc if -", "run proc if bool is true"),
+    ("ifelse", 3, "bool proc1 proc2 ifelse -", "run proc1 or proc2"),
+    ("for", 4, "init incr limit proc for -", "run proc for each value"),
+    ("repeat", 2, "int proc repeat -", "run proc int times"),
+    ("forall", 2, "obj proc forall -", "run proc for each element (key and value of a dict)"),
+    ("exec", 1, "any exec -", "execute an object"),
+    ("cvx", 1, "any cvx any", "make an array (as a procedure), string or name executable"),
+    ("quit", 0, "- quit -", "stop the interpreter"),
+    ("executive", 0, "- executive -", "start an interactive session"),
+    ("prompt", 0, "- prompt -", "print the interactive prompt"),
+    // output
+    ("print", 1, "string print -", "write string to stdout"),
+    ("=", 1, "any = -", "write text form and a newline"),
+    ("==", 1, "any == -", "write syntax form"),
+    ("file", 2, "filename access file file", "open a standard file, or a file on disk to read"),
+    ("writestring", 2, "file string writestring -", "write string to file"),
+    ("write", 2, "file int write -", "write one byte to file"),
+    ("flush", 0, "- flush -", "flush stdout"),
+    ("flushfile", 1, "file flushfile -", "flush file"),
+    ("echo", 1, "bool echo -", "turn repl input echo on or off"),
+    // files on disk
+    ("status", 1, "file|filename status bool | pages bytes referenced created true | false", "whether a file is open, or facts about a file on disk"),
+    ("deletefile", 1, "filename deletefile -", "remove a file"),
+    ("renamefile", 2, "old new renamefile -", "rename a file"),
+    ("filenameforall", 3, "template proc scratch filenameforall -", "run proc for each file matching template"),
+    // resources
+    ("defineresource", 3, "key instance category defineresource instance", "define a resource"),
+    ("findresource", 2, "key category findresource instance", "look up or load a resource"),
+    ("resourcestatus", 2, "key category resourcestatus status size true | false", "where a resource is"),
+    ("resourceforall", 4, "template proc scratch category resourceforall -", "run proc for matching keys"),
+    ("epsinclude", 1, "filename epsinclude -", "run an EPS file in isolation"),
+    ("runlibfile", 1, "filename runlibfile -", "run a bundled library, or a file"),
+    // memory
+    ("vmstatus", 0, "- vmstatus level used maximum", "save level and VM in use and available"),
+    ("vmreclaim", 1, "int vmreclaim -", "free closures' unreachable frames and recount VM"),
+    ("setglobal", 1, "bool setglobal -", "set the allocation mode, recorded but not applied"),
+    ("currentglobal", 0, "- currentglobal bool", "current allocation mode"),
+    // halftones and transfer functions, recorded but not used for anything yet
+    ("setscreen", 3, "freq angle proc setscreen -", "set the halftone screen"),
+    ("currentscreen", 0, "- currentscreen freq angle proc", "current halftone screen"),
+    ("sethalftone", 1, "halftone sethalftone -", "set the halftone dictionary"),
+    ("currenthalftone", 0, "- currenthalftone halftone", "current halftone dictionary"),
+    ("settransfer", 1, "proc settransfer -", "set the gray transfer function"),
+    ("currenttransfer", 0, "- currenttransfer proc", "current gray transfer function"),
+    ("setcolortransfer", 4, "red green blue gray setcolortransfer -", "set the transfer functions"),
+    ("currentcolortransfer", 0, "- currentcolortransfer red green blue gray", "current transfer functions"),
+    // errors
+    ("errordict", 0, "- errordict dict", "the error handlers"),
+    ("$error", 0, "- $error dict", "details of the last error"),
+    ("handleerror", 0, "- handleerror -", "report the last error"),
+    // identification
+    ("languagelevel", 0, "- languagelevel int", "PostScript language level supported"),
+    ("product", 0, "- product string", "name of the interpreter"),
+    ("version", 0, "- version string", "interpreter version"),
+    ("revision", 0, "- revision int", "interpreter version as a number"),
+    ("serialnumber", 0, "- serialnumber int", "machine serial number (always 0)"),
+    ("realtime", 0, "- realtime int", "milliseconds since the interpreter started"),
+    ("usertime", 0, "- usertime int", "milliseconds the interpreter has been running for"),
+];
+
+// operators of our own that no printer has, only in systemdict when the interpreter is
+// configured with extensions
+#[rustfmt::skip]
+pub const EXTENSIONS: &[OperatorInfo] = ops![
+    ("format", 2, "template array format string", "fill in a printf style template"),
+    ("currentdate", 0, "- currentdate string", "today's date (UTC) as YYYY-MM-DD"),
+    ("currenttime", 0, "- currenttime string", "the time of day (UTC) as HH:MM:SS"),
+    ("getenv", 1, "name getenv value true | false", "look up an environment variable"),
+    ("utf8length", 1, "string utf8length int", "number of characters (code points) in string"),
+    ("utf8get", 2, "string index utf8get int", "code point of the character at index"),
+    ("utf8decode", 1, "string utf8decode array", "the code points of string"),
+    ("utf8encode", 1, "array utf8encode string", "string of the given code points"),
+    ("help", 1, "name help -", "print an operator's usage and what it does"),
+    ("over", 2, "any1 any2 over any1 any2 any1", "copy the object under the top, like 1 index"),
+    ("nip", 2, "any1 any2 nip any2", "discard the object under the top"),
+    ("tuck", 2, "any1 any2 tuck any2 any1 any2", "copy the top under the object below it"),
+    ("ndup", 2, "any n ndup any ... any", "push n more copies of the top"),
+];
+
+impl OperatorInfo {
+    // how many results it pushes, read off the usage line. None when that depends on the
+    // operands (copy, token and the like)
+    pub fn results(&self) -> Option<usize> {
+        let usage: Vec<&str> = self.usage.split(' ').collect();
+        let after = &usage[usage.iter().position(|&w| w == self.name)? + 1..];
+        if after.iter().any(|w| w.contains("..") || *w == "|") {
+            None
+        } else if after == ["-"] {
+            Some(0)
+        } else {
+            Some(after.len())
+        }
+    }
+}
+
+// the operators an interpreter has, with or without the extensions
+pub fn builtins(extensions: bool) -> impl Iterator<Item = &'static OperatorInfo> {
+    let extras = if extensions { EXTENSIONS } else { &[] };
+    OPERATORS.iter().chain(extras)
+}
+
+pub fn info(name: &str) -> Option<&'static OperatorInfo> {
+    OPERATORS
+        .iter()
+        .chain(EXTENSIONS)
+        .find(|op| op.name == name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names_are_unique() {
+        let all: Vec<&OperatorInfo> = OPERATORS.iter().chain(EXTENSIONS).collect();
+        for (i, op) in all.iter().enumerate() {
+            assert!(
+                all[i + 1..].iter().all(|o| o.name != op.name),
+                "{} listed twice",
+                op.name
+            );
+        }
+        assert_eq!(info("add").unwrap().arity, 2);
+        assert!(info("nosuchop").is_none());
+    }
+
+    #[test]
+    fn results_from_usage() {
+        assert_eq!(info("add").unwrap().results(), Some(1));
+        assert_eq!(info("exch").unwrap().results(), Some(2));
+        assert_eq!(info("def").unwrap().results(), Some(0));
+        assert_eq!(info("copy").unwrap().results(), None);
+        assert_eq!(info("token").unwrap().results(), None);
+        // every usage line names its operator
+        for op in OPERATORS.iter().chain(EXTENSIONS) {
+            assert!(op.usage.split(' ').any(|w| w == op.name), "{}", op.name);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::interpreter::config::InterpreterConfig;
+use crate::interpreter::dsc::{DscDocument, PageRanges};
+use crate::interpreter::error::PSError;
+use crate::interpreter::file::BufferSink;
+use crate::interpreter::interpreter::Interpreter;
+use crate::interpreter::lexer::Lexer;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// what running one page of a document produced
+#[derive(Debug)]
+pub struct PageRun {
+    // counting from 1
+    pub page: usize,
+    pub output: String,
+    // what the page wrote to %stderr
+    pub errors: String,
+    pub result: Result<(), PSError>,
+}
+
+// run a document's prolog (its setup included). with skip_errors an error doesn't stop it:
+// the top level token it happened at is skipped and the rest of the prolog still runs, so
+// one broken definition doesn't take all the others with it. the skipped errors come back
+pub fn run_prolog(
+    interp: &mut Interpreter,
+    prolog: &str,
+    skip_errors: bool,
+) -> Result<Vec<PSError>, PSError> {
+    let mut skipped = Vec::new();
+    let mut rest = prolog;
+    while let Err(e) = interp.run(rest) {
+        let token = e
+            .position
+            .filter(|_| skip_errors)
+            .and_then(|at| Some(at + Lexer::new(&rest[at..]).next()?.span.end));
+        let Some(end) = token else {
+            return Err(e);
+        };
+        skipped.push(e);
+        rest = &rest[end..];
+    }
+    Ok(skipped)
+}
+
+// run the pages of a DSC document on up to `jobs` threads. the conventions say pages only
+// depend on the prolog, so each one gets a fresh interpreter that runs the prolog and then
+// the page (the last also runs the trailer). setup prepares each interpreter before that.
+// output is collected rather than printed and comes back in page order. errors in the
+// prolog that skip_prolog_errors lets by are reported on the page's %stderr. once a page
+// fails no more are started, as running them one after another would stop there, though
+// later pages already running on other threads still finish
+pub fn run_pages(
+    doc: &DscDocument,
+    selection: Option<&PageRanges>,
+    config: &InterpreterConfig,
+    jobs: usize,
+    skip_prolog_errors: bool,
+    setup: &(dyn Fn(&mut Interpreter) + Sync),
+) -> Vec<PageRun> {
+    let pages: Vec<usize> = (1..=doc.pages.len())
+        .filter(|&n| selection.is_none_or(|s| s.contains(n)))
+        .collect();
+    let last = pages.last().copied();
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let done = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, pages.len().max(1)) {
+            scope.spawn(|| {
+                while !failed.load(Ordering::Relaxed) {
+                    let Some(&page) = pages.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+                    let mut src = String::from(doc.page_source(page).unwrap());
+                    if Some(page) == last {
+                        src.push_str(doc.trailer_source());
+                    }
+                    let prolog = (doc.prolog_source(), skip_prolog_errors);
+                    let run = run_page(page, prolog, &src, config, setup);
+                    if run.result.is_err() {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                    done.lock().unwrap().push(run);
+                }
+            });
+        }
+    });
+    let mut runs = done.into_inner().unwrap();
+    runs.sort_by_key(|run| run.page);
+    runs
+}
+
+fn run_page(
+    page: usize,
+    (prolog, skip_errors): (&str, bool),
+    src: &str,
+    config: &InterpreterConfig,
+    setup: &(dyn Fn(&mut Interpreter) + Sync),
+) -> PageRun {
+    let mut interp = match Interpreter::try_with_config(config.clone()) {
+        Ok(interp) => interp,
+        Err(e) => {
+            return PageRun {
+                page,
+                output: String::new(),
+                errors: String::new(),
+                result: Err(e),
+            }
+        }
+    };
+    setup(&mut interp);
+    let sink = BufferSink::new();
+    interp.set_output(Box::new(sink.clone()));
+    let mut errors = String::new();
+    let result = run_prolog(&mut interp, prolog, skip_errors).and_then(|skipped| {
+        for e in skipped {
+            errors.push_str(&format!("prolog: {} (skipped)\n", e));
+        }
+        interp.run(src)
+    });
+    PageRun {
+        page,
+        output: sink.take_stdout(),
+        errors: errors + &sink.take_stderr(),
+        result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DOC: &str = "%!PS-Adobe-3.0
+%%Pages: 3
+%%EndComments
+/sq { dup mul } def
+%%EndProlog
+%%Page: 1 1
+3 sq =
+%%Page: 2 2
+4 sq =
+%%Page: 3 3
+5 sq = nosuch
+%%Trailer
+(done) =
+%%EOF
+";
+
+    #[test]
+    fn pages_in_order_whatever_finishes_first() {
+        let doc = DscDocument::parse(DOC);
+        let config = InterpreterConfig::default();
+        let runs = run_pages(&doc, None, &config, 3, false, &|_| {});
+        let outputs: Vec<(usize, &str)> =
+            runs.iter().map(|r| (r.page, r.output.as_str())).collect();
+        assert_eq!(outputs, vec![(1, "9\n"), (2, "16\n"), (3, "25\n")]);
+        assert!(runs[0].result.is_ok() && runs[1].result.is_ok());
+        assert_eq!(runs[2].result.as_ref().unwrap_err().name, "undefined");
+
+        let selection = PageRanges::parse("2").unwrap();
+        let runs = run_pages(&doc, Some(&selection), &config, 4, false, &|interp| {
+            interp.run("/sq { pop (overridden) } def").unwrap();
+        });
+        // the prolog runs after setup, and the trailer goes with the last page selected
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].output, "16\ndone\n");
+
+        // pages are handed out in order, so one at a time nothing after a failure runs
+        let src = DOC.replace("3 sq =", "3 sq = nosuch");
+        let runs = run_pages(&DscDocument::parse(&src), None, &config, 1, false, &|_| {});
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].result.as_ref().unwrap_err().name, "undefined");
+    }
+
+    #[test]
+    fn prolog_errors_skipped() {
+        let prolog = "/a 1 def 1 (x) add /b 2 def nosuch /c 3 def";
+        let mut interp = Interpreter::new();
+        let err = run_prolog(&mut interp, prolog, false).unwrap_err();
+        assert_eq!(err.name, "typecheck");
+        assert!(interp.run("b").is_err());
+
+        let mut interp = Interpreter::new();
+        let skipped = run_prolog(&mut interp, prolog, true).unwrap();
+        let names: Vec<&str> = skipped.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["typecheck", "undefined"]);
+        interp.run("clear a b c").unwrap();
+        assert_eq!(interp.stack(), vec!["1", "2", "3"]);
+
+        let src = DOC.replace("def\n", "def nosuch\n");
+        let doc = DscDocument::parse(&src);
+        let runs = run_pages(&doc, None, &InterpreterConfig::default(), 1, true, &|_| {});
+        assert_eq!(runs[1].output, "16\n");
+        assert_eq!(runs[1].errors, "prolog: undefined in nosuch (skipped)\n");
+    }
+}
@@ -0,0 +1,251 @@
+use crate::interpreter::object::PSObject;
+
+// widths and precisions beyond the longest string postscript allows are a rangecheck
+const MAX_FIELD: usize = 65535;
+
+// one %... directive: flags, width, precision and the conversion letter
+#[derive(Debug, Default)]
+struct Spec {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+// fill in a C printf style template for the format extension: %d %i %u %x %X %o %c %f %e
+// %E %g %G %s and %%, with the - 0 + and space flags, widths and precisions. %s takes any
+// object and uses its cvs text. too few arguments, an unknown conversion or a field over
+// MAX_FIELD is a rangecheck, an argument of the wrong type a typecheck
+pub fn sprintf(template: &str, args: &[PSObject]) -> Result<String, String> {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let mut spec = Spec::default();
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => spec.left = true,
+                '0' => spec.zero = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        spec.width = digits(&mut chars)?.unwrap_or(0);
+        if chars.next_if_eq(&'.').is_some() {
+            spec.precision = Some(digits(&mut chars)?.unwrap_or(0));
+        }
+        let conversion = chars.next().ok_or("rangecheck")?;
+        if conversion == '%' {
+            out.push('%');
+            continue;
+        }
+        let arg = args.next().ok_or("rangecheck")?;
+        let text = match conversion {
+            'd' | 'i' | 'u' => spec.sign(int(arg)?.to_string()),
+            'x' => format!("{:x}", int(arg)?),
+            'X' => format!("{:X}", int(arg)?),
+            'o' => format!("{:o}", int(arg)?),
+            'c' => {
+                let code = u32::try_from(int(arg)?).map_err(|_| "rangecheck")?;
+                char::from_u32(code).ok_or("rangecheck")?.to_string()
+            }
+            'f' => spec.sign(format!("{:.*}", spec.precision.unwrap_or(6), real(arg)?)),
+            'e' | 'E' => {
+                let text = exponent(real(arg)?, spec.precision.unwrap_or(6));
+                spec.sign(if conversion == 'E' {
+                    text.to_uppercase()
+                } else {
+                    text
+                })
+            }
+            'g' | 'G' => {
+                let text = general(real(arg)?, spec.precision.unwrap_or(6));
+                spec.sign(if conversion == 'G' {
+                    text.to_uppercase()
+                } else {
+                    text
+                })
+            }
+            's' => {
+                let text = String::from_utf8_lossy(&arg.cvs(None)).into_owned();
+                match spec.precision {
+                    Some(n) => text.chars().take(n).collect(),
+                    None => text,
+                }
+            }
+            _ => return Err("rangecheck".into()),
+        };
+        // strings and characters are never zero padded
+        let numeric = !matches!(conversion, 's' | 'c');
+        out.push_str(&spec.pad(text, numeric));
+    }
+    Ok(out)
+}
+
+fn digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Option<usize>, String> {
+    let mut n = None;
+    while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+        let next = n.unwrap_or(0) * 10 + d as usize;
+        if next > MAX_FIELD {
+            return Err("rangecheck".into());
+        }
+        n = Some(next);
+        chars.next();
+    }
+    Ok(n)
+}
+
+fn int(arg: &PSObject) -> Result<i64, String> {
+    match arg {
+        PSObject::Int(n) => Ok(*n),
+        _ => Err("typecheck".into()),
+    }
+}
+
+fn real(arg: &PSObject) -> Result<f64, String> {
+    match arg {
+        PSObject::Int(n) => Ok(*n as f64),
+        PSObject::Real(r) => Ok(*r),
+        _ => Err("typecheck".into()),
+    }
+}
+
+// C's %e: one digit before the point and at least two in the exponent
+fn exponent(x: f64, precision: usize) -> String {
+    let text = format!("{:.*e}", precision, x);
+    let (mantissa, exp) = text.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    format!(
+        "{}e{}{:02}",
+        mantissa,
+        if exp < 0 { '-' } else { '+' },
+        exp.abs()
+    )
+}
+
+// C's %g: %e for very large or small numbers and %f otherwise, without trailing zeros
+fn general(x: f64, precision: usize) -> String {
+    let precision = precision.max(1);
+    if x == 0.0 || !x.is_finite() {
+        return format!("{}", x);
+    }
+    let exp = x.abs().log10().floor() as i32;
+    let trim = |s: String| {
+        if s.contains('.') {
+            s.trim_end_matches('0').trim_end_matches('.').to_string()
+        } else {
+            s
+        }
+    };
+    if exp < -4 || exp >= precision as i32 {
+        let text = exponent(x, precision - 1);
+        let (mantissa, exp) = text.split_once('e').unwrap();
+        format!("{}e{}", trim(mantissa.to_string()), exp)
+    } else {
+        trim(format!("{:.*}", (precision as i32 - 1 - exp) as usize, x))
+    }
+}
+
+impl Spec {
+    fn sign(&self, text: String) -> String {
+        if text.starts_with('-') {
+            text
+        } else if self.plus {
+            format!("+{}", text)
+        } else if self.space {
+            format!(" {}", text)
+        } else {
+            text
+        }
+    }
+
+    fn pad(&self, text: String, numeric: bool) -> String {
+        let len = text.chars().count();
+        if len >= self.width {
+            return text;
+        }
+        let fill = self.width - len;
+        if self.left {
+            format!("{}{}", text, " ".repeat(fill))
+        } else if self.zero && numeric {
+            // zeros go between the sign and the digits
+            let sign = text.starts_with(['-', '+', ' ']) as usize;
+            format!("{}{}{}", &text[..sign], "0".repeat(fill), &text[sign..])
+        } else {
+            format!("{}{}", " ".repeat(fill), text)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn conversions_and_padding() {
+        let args = [
+            PSObject::Int(42),
+            PSObject::Real(1.23456),
+            PSObject::String("abc".into(), false),
+            PSObject::Int(-7),
+            PSObject::Int(255),
+        ];
+        assert_eq!(
+            sprintf("%5d|%.2f|%-5s|%03d|%x%%", &args).unwrap(),
+            "   42|1.23|abc  |-07|ff%"
+        );
+        let reals = [1234.5, 0.0001, 1e20].map(PSObject::Real);
+        assert_eq!(
+            sprintf("%e %g %G", &reals).unwrap(),
+            "1.234500e+03 0.0001 1E+20"
+        );
+        assert_eq!(
+            sprintf("%+d|% d", &[PSObject::Int(3), PSObject::Int(4)]).unwrap(),
+            "+3| 4"
+        );
+        assert_eq!(
+            sprintf(
+                "%s %s",
+                &[PSObject::Name("n".into(), false), PSObject::Bool(true)]
+            )
+            .unwrap(),
+            "n true"
+        );
+    }
+
+    #[test]
+    fn bad_arguments() {
+        assert_eq!(
+            sprintf("%d %d", &[PSObject::Int(1)]).unwrap_err(),
+            "rangecheck"
+        );
+        assert_eq!(
+            sprintf("%d", &[PSObject::Real(1.5)]).unwrap_err(),
+            "typecheck"
+        );
+        assert_eq!(
+            sprintf("%q", &[PSObject::Int(1)]).unwrap_err(),
+            "rangecheck"
+        );
+        assert_eq!(
+            sprintf("%99999999999d", &[PSObject::Int(1)]).unwrap_err(),
+            "rangecheck"
+        );
+        assert_eq!(
+            sprintf("%.65536f", &[PSObject::Real(1.0)]).unwrap_err(),
+            "rangecheck"
+        );
+        assert_eq!(
+            sprintf("%65535d", &[PSObject::Int(1)]).unwrap().len(),
+            MAX_FIELD
+        );
+    }
+}
@@ -0,0 +1,412 @@
+use crate::interpreter::object::{DictMap, PSObject};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// categories every interpreter knows about, even before anything is defined in them
+const CATEGORIES: [&str; 4] = ["Category", "Font", "Encoding", "ProcSet"];
+
+// the standard 14 fonts every printer has. there's no glyph rendering, but programs that
+// look one up and read its dictionary get what they expect. the last two use their own
+// symbol sets rather than StandardEncoding
+const FONTS: [&str; 14] = [
+    "Times-Roman",
+    "Times-Bold",
+    "Times-Italic",
+    "Times-BoldItalic",
+    "Helvetica",
+    "Helvetica-Bold",
+    "Helvetica-Oblique",
+    "Helvetica-BoldOblique",
+    "Courier",
+    "Courier-Bold",
+    "Courier-Oblique",
+    "Courier-BoldOblique",
+    "Symbol",
+    "ZapfDingbats",
+];
+
+// where a resource instance can be found, mirrors the status codes of resourcestatus
+#[derive(Debug, PartialEq)]
+pub enum ResourceStatus {
+    // defined with defineresource (or built in)
+    Defined,
+    // not loaded yet, but there is a file for it in the resource directory
+    OnDisk,
+}
+
+// level 2 resource machinery: named instances grouped by category, with an optional directory
+// laid out as <dir>/<Category>/<key> for resources that get loaded on demand
+pub struct ResourceStore {
+    categories: HashMap<String, HashMap<String, PSObject>>,
+    dir: Option<PathBuf>,
+}
+
+impl Default for ResourceStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResourceStore {
+    pub fn new() -> Self {
+        let mut store = ResourceStore {
+            categories: HashMap::new(),
+            dir: None,
+        };
+        for cat in CATEGORIES {
+            store.add_category(cat);
+        }
+        // built in encodings and fonts
+        let encoding = standard_encoding();
+        for (i, name) in FONTS.into_iter().enumerate() {
+            let encoding = (i < 12).then(|| encoding.clone());
+            store.define("Font", name, font(name, encoding));
+        }
+        store.define("Encoding", "StandardEncoding", encoding);
+        store
+    }
+
+    // categories are resources too, in the Category category: /Font /Category findresource
+    fn add_category(&mut self, category: &str) {
+        if self.categories.contains_key(category) {
+            return;
+        }
+        self.categories.insert(category.to_string(), HashMap::new());
+        let mut instance = DictMap::new();
+        instance.insert(
+            "Category".into(),
+            PSObject::Name(category.to_string(), false),
+        );
+        self.categories
+            .entry("Category".into())
+            .or_default()
+            .insert(category.to_string(), PSObject::Dict(instance));
+    }
+
+    pub fn set_dir(&mut self, dir: Option<PathBuf>) {
+        self.dir = dir;
+    }
+
+    pub fn dir(&self) -> Option<&PathBuf> {
+        self.dir.as_ref()
+    }
+
+    pub fn has_category(&self, category: &str) -> bool {
+        self.categories.contains_key(category)
+    }
+
+    // add (or replace) an instance, unknown categories are created on the fly. defining one in
+    // Category makes a new (empty) category of that name
+    pub fn define(&mut self, category: &str, key: &str, instance: PSObject) {
+        self.add_category(category);
+        if category == "Category" {
+            self.add_category(key);
+        }
+        self.categories
+            .get_mut(category)
+            .unwrap()
+            .insert(key.to_string(), instance);
+    }
+
+    pub fn get(&self, category: &str, key: &str) -> Option<&PSObject> {
+        self.categories.get(category)?.get(key)
+    }
+
+    // the file that would define this resource, if there is one. names that could reach
+    // outside the resource directory never have one
+    pub fn file_for(&self, category: &str, key: &str) -> Option<PathBuf> {
+        if !safe_name(category) || !safe_name(key) {
+            return None;
+        }
+        let path = self.dir.as_ref()?.join(category).join(key);
+        if path.is_file() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    pub fn status(&self, category: &str, key: &str) -> Option<ResourceStatus> {
+        if self.get(category, key).is_some() {
+            Some(ResourceStatus::Defined)
+        } else if self.file_for(category, key).is_some() {
+            Some(ResourceStatus::OnDisk)
+        } else {
+            None
+        }
+    }
+
+    // every key (defined or on disk) in a category matching the template, sorted so
+    // resourceforall runs in a stable order
+    pub fn keys_matching(&self, category: &str, template: &str) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .categories
+            .get(category)
+            .map(|c| c.keys().cloned().collect())
+            .unwrap_or_default();
+        if let Some(dir) = self.dir.as_ref().filter(|_| safe_name(category)) {
+            if let Ok(entries) = std::fs::read_dir(dir.join(category)) {
+                for entry in entries.flatten() {
+                    if let Some(name) = entry.file_name().to_str() {
+                        keys.push(name.to_string());
+                    }
+                }
+            }
+        }
+        keys.sort();
+        keys.dedup();
+        keys.retain(|k| template_matches(template, k));
+        keys
+    }
+}
+
+// a category or key that names one file in one directory: no separators, no parent
+fn safe_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['/', '\\', '\0']) && !name.contains("..")
+}
+
+// resourceforall templates: * matches any run of characters, ? matches exactly one
+pub fn template_matches(template: &str, key: &str) -> bool {
+    let t: Vec<char> = template.chars().collect();
+    let k: Vec<char> = key.chars().collect();
+    // classic two pointer wildcard match with backtracking to the last *
+    let (mut ti, mut ki) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ki < k.len() {
+        if ti < t.len() && (t[ti] == '?' || t[ti] == k[ki]) {
+            ti += 1;
+            ki += 1;
+        } else if ti < t.len() && t[ti] == '*' {
+            star = Some((ti, ki));
+            ti += 1;
+        } else if let Some((st, sk)) = star {
+            ti = st + 1;
+            ki = sk + 1;
+            star = Some((st, sk + 1));
+        } else {
+            return false;
+        }
+    }
+    t[ti..].iter().all(|&c| c == '*')
+}
+
+// the dictionary of a built in font: its name, type and the usual 1000 unit glyph space
+fn font(name: &str, encoding: Option<PSObject>) -> PSObject {
+    let mut dict = DictMap::new();
+    dict.insert("FontName".into(), PSObject::Name(name.to_string(), false));
+    dict.insert("FontType".into(), PSObject::Int(1));
+    let matrix = [0.001, 0.0, 0.0, 0.001, 0.0, 0.0];
+    dict.insert(
+        "FontMatrix".into(),
+        PSObject::Array(matrix.into_iter().map(PSObject::Real).collect()),
+    );
+    if let Some(encoding) = encoding {
+        dict.insert("Encoding".into(), encoding);
+    }
+    PSObject::Dict(dict)
+}
+
+// the standard latin text encoding from the PLRM, codes without a glyph map to .notdef
+fn standard_encoding() -> PSObject {
+    let mut names = vec![".notdef"; 256];
+    let ascii = [
+        "space",
+        "exclam",
+        "quotedbl",
+        "numbersign",
+        "dollar",
+        "percent",
+        "ampersand",
+        "quoteright",
+        "parenleft",
+        "parenright",
+        "asterisk",
+        "plus",
+        "comma",
+        "hyphen",
+        "period",
+        "slash",
+        "zero",
+        "one",
+        "two",
+        "three",
+        "four",
+        "five",
+        "six",
+        "seven",
+        "eight",
+        "nine",
+        "colon",
+        "semicolon",
+        "less",
+        "equal",
+        "greater",
+        "question",
+        "at",
+    ];
+    for (i, n) in ascii.iter().enumerate() {
+        names[32 + i] = n;
+    }
+    let upper: Vec<String> = ('A'..='Z').map(|c| c.to_string()).collect();
+    let lower: Vec<String> = ('a'..='z').map(|c| c.to_string()).collect();
+    for i in 0..26 {
+        names[65 + i] = &upper[i];
+        names[97 + i] = &lower[i];
+    }
+    let rest = [
+        (91, "bracketleft"),
+        (92, "backslash"),
+        (93, "bracketright"),
+        (94, "asciicircum"),
+        (95, "underscore"),
+        (96, "quoteleft"),
+        (123, "braceleft"),
+        (124, "bar"),
+        (125, "braceright"),
+        (126, "asciitilde"),
+        (161, "exclamdown"),
+        (162, "cent"),
+        (163, "sterling"),
+        (164, "fraction"),
+        (165, "yen"),
+        (166, "florin"),
+        (167, "section"),
+        (168, "currency"),
+        (169, "quotesingle"),
+        (170, "quotedblleft"),
+        (171, "guillemotleft"),
+        (172, "guilsinglleft"),
+        (173, "guilsinglright"),
+        (174, "fi"),
+        (175, "fl"),
+        (177, "endash"),
+        (178, "dagger"),
+        (179, "daggerdbl"),
+        (180, "periodcentered"),
+        (182, "paragraph"),
+        (183, "bullet"),
+        (184, "quotesinglbase"),
+        (185, "quotedblbase"),
+        (186, "quotedblright"),
+        (187, "guillemotright"),
+        (188, "ellipsis"),
+        (189, "perthousand"),
+        (191, "questiondown"),
+        (193, "grave"),
+        (194, "acute"),
+        (195, "circumflex"),
+        (196, "tilde"),
+        (197, "macron"),
+        (198, "breve"),
+        (199, "dotaccent"),
+        (200, "dieresis"),
+        (202, "ring"),
+        (203, "cedilla"),
+        (205, "hungarumlaut"),
+        (206, "ogonek"),
+        (207, "caron"),
+        (208, "emdash"),
+        (225, "AE"),
+        (227, "ordfeminine"),
+        (232, "Lslash"),
+        (233, "Oslash"),
+        (234, "OE"),
+        (235, "ordmasculine"),
+        (241, "ae"),
+        (245, "dotlessi"),
+        (248, "lslash"),
+        (249, "oslash"),
+        (250, "oe"),
+        (251, "germandbls"),
+    ];
+    for (code, n) in rest {
+        names[code] = n;
+    }
+    PSObject::Array(
+        names
+            .into_iter()
+            .map(|n| PSObject::Name(n.to_string(), false))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn template_wildcards() {
+        assert!(template_matches("*", "Times-Roman"));
+        assert!(template_matches("Times-*", "Times-Roman"));
+        assert!(template_matches("T?mes*", "Times-Bold"));
+        assert!(!template_matches("Times-*", "Helvetica"));
+        assert!(!template_matches("?", "ab"));
+    }
+
+    #[test]
+    fn define_and_list() {
+        let mut r = ResourceStore::new();
+        r.define("ProcSet", "util", PSObject::Int(1));
+        r.define("ProcSet", "other", PSObject::Int(2));
+        assert_eq!(r.get("ProcSet", "util"), Some(&PSObject::Int(1)));
+        assert_eq!(r.status("ProcSet", "util"), Some(ResourceStatus::Defined));
+        assert_eq!(r.status("ProcSet", "missing"), None);
+        assert_eq!(r.keys_matching("ProcSet", "*"), vec!["other", "util"]);
+    }
+
+    #[test]
+    fn standard_encoding_is_built_in() {
+        let r = ResourceStore::new();
+        match r.get("Encoding", "StandardEncoding") {
+            Some(PSObject::Array(a)) => {
+                assert_eq!(a.len(), 256);
+                assert_eq!(a[65], PSObject::Name("A".into(), false));
+                assert_eq!(a[0], PSObject::Name(".notdef".into(), false));
+            }
+            _ => panic!("Expected StandardEncoding array"),
+        }
+    }
+
+    #[test]
+    fn standard_fonts_and_categories_are_built_in() {
+        let mut r = ResourceStore::new();
+        match r.get("Font", "Helvetica") {
+            Some(PSObject::Dict(d)) => {
+                assert_eq!(d["FontName"], PSObject::Name("Helvetica".into(), false));
+                assert!(d.contains_key("Encoding"));
+            }
+            _ => panic!("Expected a Helvetica font dictionary"),
+        }
+        assert_eq!(r.keys_matching("Font", "*").len(), 14);
+        assert_eq!(
+            r.keys_matching("Category", "*"),
+            vec!["Category", "Encoding", "Font", "ProcSet"]
+        );
+        r.define("Category", "Form", PSObject::Dict(DictMap::new()));
+        assert!(r.has_category("Form"));
+        r.define("Pattern", "dots", PSObject::Int(1));
+        assert_eq!(
+            r.status("Category", "Pattern"),
+            Some(ResourceStatus::Defined)
+        );
+    }
+
+    #[test]
+    fn keys_never_leave_the_resource_directory() {
+        let dir = std::env::temp_dir().join(format!("ps-resources-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("ProcSet")).unwrap();
+        std::fs::write(dir.join("ProcSet").join("util"), "").unwrap();
+        std::fs::write(dir.join("secret"), "").unwrap();
+        let mut r = ResourceStore::new();
+        r.set_dir(Some(dir.clone()));
+        assert_eq!(r.status("ProcSet", "util"), Some(ResourceStatus::OnDisk));
+        assert_eq!(r.file_for("ProcSet", "../secret"), None);
+        assert_eq!(r.file_for("ProcSet", ".."), None);
+        assert_eq!(r.file_for("..", "secret"), None);
+        assert_eq!(
+            r.file_for("ProcSet", &format!("{}", dir.join("secret").display())),
+            None
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
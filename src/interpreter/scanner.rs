@@ -0,0 +1,689 @@
+use crate::interpreter::binary;
+use crate::interpreter::config::IntWidth;
+use crate::interpreter::object::PSObject;
+use std::collections::VecDeque;
+
+// how deeply procedures and arrays may nest before limitcheck, unless the scanner is told
+// otherwise. deeper than this and just dropping the object would risk the rust stack
+pub const MAX_NESTING: usize = 1000;
+
+// incremental scanner: pulls one raw token at a time out of the source, so callers like the
+// `token` operator can stop after the first token and keep the rest of the input around
+pub struct Scanner<'a> {
+    src: &'a [u8],
+    // byte offset of the next unread character
+    pos: usize,
+    // recognize binary tokens (bytes 128-159) where a token starts
+    binary: bool,
+    // integer literals wider than this are reals
+    ints: IntWidth,
+    // objects of a binary object sequence that haven't been handed out yet
+    pending: VecDeque<PSObject>,
+    // the input ran out in the middle of the last raw token (a string, procedure or array)
+    unterminated: bool,
+    // the last raw token ended at a } or ] with nothing open of its kind to close
+    unmatched: bool,
+    // procedures and arrays nested deeper than this are a limitcheck
+    max_depth: usize,
+    // hand out braces and brackets as tokens of their own instead of whole composites, for
+    // parsing the inside of one
+    flat: bool,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(src: &'a str) -> Self {
+        Self::from_bytes(src.as_bytes())
+    }
+
+    // byte input may mix text with binary tokens
+    pub fn from_bytes(src: &'a [u8]) -> Self {
+        Scanner {
+            src,
+            pos: 0,
+            binary: true,
+            ints: IntWidth::default(),
+            pending: VecDeque::new(),
+            unterminated: false,
+            unmatched: false,
+            max_depth: MAX_NESTING,
+            flat: false,
+        }
+    }
+
+    pub fn binary_tokens(mut self, on: bool) -> Self {
+        self.binary = on;
+        self
+    }
+
+    pub fn int_width(mut self, width: IntWidth) -> Self {
+        self.ints = width;
+        self
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    pub(crate) fn flat(mut self, on: bool) -> Self {
+        self.flat = on;
+        self
+    }
+
+    // byte offset of the next unread character
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    // like next_object, along with the offset the token starts at
+    pub fn next_with_position(&mut self) -> Option<(usize, Result<PSObject, String>)> {
+        if self.pending.is_empty() {
+            self.skip_blanks();
+        }
+        let at = self.pos;
+        self.next_object().map(|r| (at, r))
+    }
+
+    // like next_raw, along with the offset the token starts at
+    pub fn next_raw_with_position(&mut self) -> Option<(usize, String)> {
+        self.skip_blanks();
+        let at = self.pos;
+        self.next_raw().map(|raw| (at, raw))
+    }
+
+    // whether the input ran out inside the last raw token
+    pub fn unterminated(&self) -> bool {
+        self.unterminated
+    }
+
+    // whatever has not been scanned yet
+    pub fn remainder(&self) -> &'a [u8] {
+        &self.src[self.pos..]
+    }
+
+    // move past whitespace and comments up to where the next token starts
+    fn skip_blanks(&mut self) {
+        while let Some(&b) = self.src.get(self.pos) {
+            if b == b'%' {
+                while self.pos < self.src.len() && !matches!(self.src[self.pos], b'\n' | b'\r') {
+                    self.pos += 1;
+                }
+            } else if b.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    // scan the next raw token text, none once the input is exhausted
+    pub fn next_raw(&mut self) -> Option<String> {
+        let mut buf = String::new();
+        self.unmatched = false;
+
+        let mut in_str = false; // inside ( ... )
+        let mut str_depth = 0; // balanced parentheses inside the string, the string's own included
+        let mut escaped = false; // just after a backslash in a string
+        let mut in_comment = false; // from % to the end of the line
+        let mut brace_depth = 0; // inside { ... }
+        let mut bracket_depth = 0; // inside [ ... ]
+
+        while self.pos < self.src.len() {
+            // a binary token ends the text token in front of it
+            if self.binary
+                && binary::is_binary_token(self.src[self.pos])
+                && !in_str
+                && !in_comment
+                && brace_depth == 0
+                && bracket_depth == 0
+                && !buf.is_empty()
+            {
+                return Some(buf);
+            }
+            let (c, len) = decode_char(&self.src[self.pos..]);
+            self.pos += len;
+            if in_comment {
+                if c == '\n' || c == '\r' {
+                    in_comment = false;
+                }
+            } else if in_str {
+                buf.push(c);
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == '(' {
+                    str_depth += 1;
+                } else if c == ')' {
+                    str_depth -= 1;
+                    if str_depth == 0 {
+                        in_str = false;
+                        if brace_depth == 0 && bracket_depth == 0 {
+                            return Some(buf);
+                        }
+                    }
+                }
+            } else if brace_depth > 0 || bracket_depth > 0 {
+                match c {
+                    // comments inside a procedure or array still separate the tokens around them
+                    '%' => {
+                        in_comment = true;
+                        buf.push(' ');
+                        continue;
+                    }
+                    '(' => {
+                        in_str = true;
+                        str_depth = 1;
+                    }
+                    '{' => brace_depth += 1,
+                    '[' => bracket_depth += 1,
+                    '}' | ']' => {
+                        let depth = if c == '}' {
+                            &mut brace_depth
+                        } else {
+                            &mut bracket_depth
+                        };
+                        // a closer with nothing of its kind open ends the token as an error, so
+                        // what follows it still scans on its own
+                        if *depth == 0 {
+                            buf.push(c);
+                            self.unmatched = true;
+                            return Some(buf);
+                        }
+                        *depth -= 1;
+                    }
+                    _ => {}
+                }
+                buf.push(c);
+                if brace_depth == 0 && bracket_depth == 0 {
+                    return Some(buf);
+                }
+            } else {
+                match c {
+                    '{' | '}' | '[' | ']' if self.flat => {
+                        if !buf.is_empty() {
+                            self.pos -= len;
+                            return Some(buf);
+                        }
+                        return Some(c.to_string());
+                    }
+                    '%' => {
+                        // finish the current token first, the comment is skipped on the next call
+                        if !buf.is_empty() {
+                            self.pos -= 1;
+                            return Some(buf);
+                        }
+                        in_comment = true;
+                    }
+                    '(' => {
+                        in_str = true;
+                        str_depth = 1;
+                        buf.push(c);
+                    }
+                    '{' => {
+                        brace_depth = 1;
+                        buf.push(c);
+                    }
+                    '[' => {
+                        bracket_depth = 1;
+                        buf.push(c);
+                    }
+                    // whitespace ends a token and is consumed along with it
+                    ch if ch.is_whitespace() => {
+                        if !buf.is_empty() {
+                            return Some(buf);
+                        }
+                    }
+                    _ => buf.push(c),
+                }
+            }
+        }
+        if buf.is_empty() {
+            None
+        } else {
+            self.unterminated = in_str || brace_depth > 0 || bracket_depth > 0;
+            Some(buf)
+        }
+    }
+
+    // scan the next token and turn it into an object
+    pub fn next_object(&mut self) -> Option<Result<PSObject, String>> {
+        if let Some(obj) = self.pending.pop_front() {
+            return Some(Ok(obj));
+        }
+        if self.binary {
+            self.skip_blanks();
+            if self
+                .src
+                .get(self.pos)
+                .is_some_and(|&b| binary::is_binary_token(b))
+            {
+                match binary::decode(&self.src[self.pos..]) {
+                    Ok((objs, used)) => {
+                        self.pos += used;
+                        self.pending.extend(objs);
+                        // an empty object sequence stands for nothing at all
+                        return self.next_object();
+                    }
+                    Err(e) => {
+                        // there is no telling where the next token starts, so stop here
+                        self.pos = self.src.len();
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
+        let raw = self.next_raw()?;
+        // a string or procedure still open at the end of the input, or one closed by the
+        // wrong kind of bracket
+        if self.unterminated || self.unmatched {
+            return Some(Err("syntaxerror".into()));
+        }
+        Some(parse_nested(&raw, self.ints, self.max_depth))
+    }
+}
+
+impl Iterator for Scanner<'_> {
+    type Item = Result<PSObject, String>;
+
+    fn next(&mut self) -> Option<Result<PSObject, String>> {
+        self.next_object()
+    }
+}
+
+// next character of the input, bytes that aren't valid utf-8 come out as U+FFFD
+fn decode_char(bytes: &[u8]) -> (char, usize) {
+    let len = match bytes[0] {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => 1,
+    };
+    match bytes.get(..len).and_then(|b| std::str::from_utf8(b).ok()) {
+        Some(s) => (s.chars().next().unwrap(), len),
+        None => (char::REPLACEMENT_CHARACTER, 1),
+    }
+}
+
+// turn into ps object after tokenizing
+pub fn parse_token(tok: &str) -> PSObject {
+    parse_token_as(tok, IntWidth::default())
+}
+
+// the same with integers of the given width. procedures and arrays may nest as deep as
+// they like here, the scanner is what enforces a limit. that leaves a real too big for a
+// double as the only thing that fails, which comes out as a name so tools see it isn't a
+// number
+pub fn parse_token_as(tok: &str, ints: IntWidth) -> PSObject {
+    parse_nested(tok, ints, usize::MAX).unwrap_or_else(|_| name(tok))
+}
+
+// a raw token, procedures and arrays included. those are taken apart with a stack of the
+// ones still open rather than by recursion, so nesting depth costs no rust stack
+fn parse_nested(tok: &str, ints: IntWidth, max_depth: usize) -> Result<PSObject, String> {
+    if !tok.starts_with(['{', '[']) {
+        return parse_simple(tok, ints);
+    }
+    let mut scanner = Scanner::new(tok)
+        .binary_tokens(false)
+        .int_width(ints)
+        .flat(true);
+    // the opening bracket of each composite still open, and what's in it so far
+    let mut open: Vec<(char, Vec<PSObject>)> = Vec::new();
+    while let Some(raw) = scanner.next_raw() {
+        let closer = match raw.as_str() {
+            "{" | "[" => {
+                if open.len() >= max_depth {
+                    return Err("limitcheck".into());
+                }
+                open.push((raw.chars().next().unwrap(), Vec::new()));
+                continue;
+            }
+            "}" => Some('{'),
+            "]" => Some('['),
+            _ => None,
+        };
+        let obj = match (closer, open.last()) {
+            (Some(opener), Some(&(top, _))) if opener == top => {
+                let (_, items) = open.pop().unwrap();
+                if opener == '{' {
+                    PSObject::Procedure {
+                        code: items,
+                        env: None,
+                    }
+                } else {
+                    PSObject::Array(items)
+                }
+            }
+            // a stray closing bracket is just a name
+            _ => parse_simple(&raw, ints)?,
+        };
+        match open.last_mut() {
+            Some((_, items)) => items.push(obj),
+            None => return Ok(obj),
+        }
+    }
+    // the raw token always closes what it opens, so this is only reached for odd input
+    // like "{ ]": close whatever is left
+    let mut obj = None;
+    while let Some((opener, mut items)) = open.pop() {
+        items.extend(obj.take());
+        obj = Some(if opener == '{' {
+            PSObject::Procedure {
+                code: items,
+                env: None,
+            }
+        } else {
+            PSObject::Array(items)
+        });
+    }
+    Ok(obj.unwrap())
+}
+
+// any token but a procedure or array
+fn parse_simple(tok: &str, ints: IntWidth) -> Result<PSObject, String> {
+    // string literal
+    Ok(if tok.starts_with('(') && tok.ends_with(')') {
+        PSObject::String(unescape(&tok[1..tok.len() - 1]), false)
+
+    // integer
+    } else if let Some(n) = tok.parse::<i64>().ok().filter(|&n| ints.fits(n)) {
+        PSObject::Int(n)
+    // real, only when it looks like a number so names like "inf" stay names. one too big
+    // for a double would parse as infinity, which is a limitcheck instead
+    } else if is_real(tok) {
+        let r: f64 = tok.parse().unwrap();
+        if !r.is_finite() {
+            return Err("limitcheck".into());
+        }
+        PSObject::Real(r)
+    // boolean
+    } else if tok == "true" {
+        PSObject::Bool(true)
+    } else if tok == "false" {
+        PSObject::Bool(false)
+
+    // literal (/name) and executable names
+    } else {
+        name(tok)
+    })
+}
+
+fn name(tok: &str) -> PSObject {
+    match tok.strip_prefix('/') {
+        Some(n) => PSObject::Name(n.into(), false),
+        None => PSObject::Name(tok.into(), true),
+    }
+}
+
+// digits with a decimal point and/or exponent, e.g. 3.5 -.2 1e10
+fn is_real(tok: &str) -> bool {
+    tok.chars().any(|c| c.is_ascii_digit())
+        && tok
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E'))
+        && tok.parse::<f64>().is_ok()
+}
+
+// the text of a string literal: \n \r \t \b \f, \\ \( \), up to three octal digits for a
+// byte, and a backslash before a newline continues the string without one. a backslash
+// before anything else is dropped. everything else is kept as the UTF-8 it was written in
+fn unescape(body: &str) -> Vec<u8> {
+    let mut text = Vec::with_capacity(body.len());
+    let mut chars = body.chars().peekable();
+    let mut utf8 = [0; 4];
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => text.push(b'\n'),
+            Some('r') => text.push(b'\r'),
+            Some('t') => text.push(b'\t'),
+            Some('b') => text.push(8),
+            Some('f') => text.push(12),
+            Some('\r') => {
+                chars.next_if_eq(&'\n');
+            }
+            Some('\n') | None => {}
+            Some(d @ '0'..='7') => {
+                let mut code = d.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.next_if(|c| c.is_digit(8)) {
+                        Some(d) => code = code * 8 + d.to_digit(8).unwrap(),
+                        None => break,
+                    }
+                }
+                text.push((code & 0xff) as u8);
+            }
+            Some(other) => text.extend_from_slice(other.encode_utf8(&mut utf8).as_bytes()),
+        }
+    }
+    text
+}
+
+// what a string literal for some bytes looks like: the parentheses and backslashes in it
+// escaped, and control characters and bytes that aren't UTF-8 written as escapes, so it
+// scans back to the same bytes
+pub fn escape(bytes: &[u8]) -> String {
+    let mut literal = String::with_capacity(bytes.len() + 2);
+    literal.push('(');
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '(' | ')' | '\\' => {
+                    literal.push('\\');
+                    literal.push(c);
+                }
+                '\n' => literal.push_str("\\n"),
+                '\r' => literal.push_str("\\r"),
+                '\t' => literal.push_str("\\t"),
+                c if c.is_ascii_control() => literal.push_str(&format!("\\{:03o}", c as u32)),
+                c => literal.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            literal.push_str(&format!("\\{:03o}", b));
+        }
+    }
+    literal.push(')');
+    literal
+}
+
+// 1-based line and column (in characters) of a byte offset, for pointing users at a token
+pub fn line_col(src: &[u8], at: usize) -> (usize, usize) {
+    let before = &src[..at];
+    let line_start = before
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    (
+        before.iter().filter(|&&b| b == b'\n').count() + 1,
+        String::from_utf8_lossy(&before[line_start..])
+            .chars()
+            .count()
+            + 1,
+    )
+}
+
+// whether src stops in the middle of a string, procedure or array, so the repl knows to
+// read another line before running it
+pub fn needs_more_input(src: &str) -> bool {
+    let mut scanner = Scanner::new(src).binary_tokens(false);
+    while scanner.next_raw().is_some() {}
+    scanner.unterminated
+}
+
+// run the scanner over a whole program for convienence
+pub fn tokenize(program: &str) -> Result<Vec<PSObject>, String> {
+    Scanner::new(program).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scans_one_token_at_a_time() {
+        let mut s = Scanner::new("3 {1 2 add} (hi there) /x");
+        assert_eq!(s.next_raw(), Some("3".into()));
+        assert_eq!(s.remainder(), b"{1 2 add} (hi there) /x");
+        assert_eq!(s.next_raw(), Some("{1 2 add}".into()));
+        assert_eq!(s.next_raw(), Some("(hi there)".into()));
+        assert_eq!(s.next_raw(), Some("/x".into()));
+        assert_eq!(s.next_raw(), None);
+        assert_eq!(s.remainder(), b"");
+    }
+
+    #[test]
+    fn skips_comments() {
+        let objs = tokenize("1 % one\n2%two\n{ 3 % } ignored\n (%) }").unwrap();
+        assert_eq!(objs.len(), 3);
+        match &objs[2] {
+            PSObject::Procedure { code, .. } => {
+                assert_eq!(
+                    code,
+                    &vec![PSObject::Int(3), PSObject::String("%".into(), false)]
+                )
+            }
+            _ => panic!("Expected Procedure"),
+        }
+    }
+
+    #[test]
+    fn strings_with_escapes() {
+        let objs = tokenize(
+            r"(a (b) c) (\(\)\\\n\t\101\7x) (one\
+two) {(})}",
+        )
+        .unwrap();
+        assert_eq!(
+            objs[..3],
+            [
+                PSObject::String("a (b) c".into(), false),
+                PSObject::String("()\\\n\tA\u{7}x".into(), false),
+                PSObject::String("onetwo".into(), false),
+            ]
+        );
+        assert!(matches!(&objs[3], PSObject::Procedure { code, .. } if code.len() == 1));
+        let texts: [&[u8]; 7] = [
+            b"a)b",
+            b"(",
+            b"back\\slash",
+            b"\x01\r\n\x7f",
+            "é".as_bytes(),
+            b"\x80\xff",
+            b"\xc3",
+        ];
+        for text in texts {
+            assert_eq!(
+                tokenize(&escape(text)),
+                Ok(vec![PSObject::String(text.to_vec(), false)])
+            );
+        }
+        // an octal escape is one byte, whichever byte it is
+        assert_eq!(
+            tokenize("(\\200\\377\\101)"),
+            Ok(vec![PSObject::String(vec![0x80, 0xff, b'A'], false)])
+        );
+    }
+
+    #[test]
+    fn whitespace_only_has_no_tokens() {
+        let mut s = Scanner::new("  \n\t ");
+        assert!(s.next_object().is_none());
+    }
+
+    #[test]
+    fn parses_numbers_and_names() {
+        assert_eq!(parse_token("42"), PSObject::Int(42));
+        assert_eq!(parse_token("-1.5"), PSObject::Real(-1.5));
+        assert_eq!(parse_token("inf"), PSObject::Name("inf".into(), true));
+        assert_eq!(parse_token("/add"), PSObject::Name("add".into(), false));
+        // reals too big for a double don't scan as infinity
+        assert_eq!(tokenize("1e400"), Err("limitcheck".to_string()));
+        assert_eq!(tokenize("{ 1 -1e400 }"), Err("limitcheck".to_string()));
+        assert_eq!(parse_token("1e400"), PSObject::Name("1e400".into(), true));
+        assert_eq!(parse_token("1e308"), PSObject::Real(1e308));
+    }
+
+    #[test]
+    fn nested_procedures() {
+        let objs = tokenize("{ {1} [2 {3}] }").unwrap();
+        assert_eq!(objs.len(), 1);
+        match &objs[0] {
+            PSObject::Procedure { code, .. } => assert_eq!(code.len(), 2),
+            _ => panic!("Expected Procedure"),
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_a_limitcheck() {
+        let deep = |n: usize| format!("{}1{}", "{".repeat(n), "}".repeat(n));
+        assert_eq!(tokenize(&deep(100_000)), Err("limitcheck".to_string()));
+        assert_eq!(tokenize(&deep(MAX_NESTING)).unwrap().len(), 1);
+        let arrays = format!(
+            "{}{}",
+            "[".repeat(MAX_NESTING + 1),
+            "]".repeat(MAX_NESTING + 1)
+        );
+        assert_eq!(tokenize(&arrays), Err("limitcheck".to_string()));
+
+        // with the limit raised it parses without touching the rust stack
+        let src = deep(100_000);
+        let mut obj = Scanner::new(&src)
+            .max_depth(100_000)
+            .next_object()
+            .unwrap()
+            .unwrap();
+        let mut depth = 0;
+        // dropping recurses, so take it apart by hand
+        while let PSObject::Procedure { mut code, .. } = obj {
+            depth += 1;
+            obj = code.pop().unwrap();
+        }
+        assert_eq!((depth, obj), (100_000, PSObject::Int(1)));
+    }
+
+    #[test]
+    fn binary_tokens_between_text() {
+        // 3, then a binary int8 4, then add
+        let src = [b'3', b' ', 136, 4, b'a', b'd', b'd'];
+        let objs: Result<Vec<_>, _> = Scanner::from_bytes(&src).collect();
+        assert_eq!(
+            objs.unwrap(),
+            vec![
+                PSObject::Int(3),
+                PSObject::Int(4),
+                PSObject::Name("add".into(), true)
+            ]
+        );
+        // text-only mode leaves the byte as part of a name
+        let objs: Result<Vec<_>, _> = Scanner::from_bytes(&src).binary_tokens(false).collect();
+        assert_eq!(objs.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn unfinished_input_needs_more() {
+        assert!(needs_more_input("/fact {"));
+        assert!(needs_more_input("/fact { 1 [ 2"));
+        assert!(needs_more_input("(hello"));
+        assert!(needs_more_input("{ % a comment }\n"));
+        assert!(!needs_more_input("/fact { dup mul } def"));
+        assert!(!needs_more_input("1 2 add % (open"));
+        assert!(!needs_more_input(""));
+        assert_eq!(tokenize("1 { 2"), Err("syntaxerror".to_string()));
+    }
+
+    #[test]
+    fn stray_closers_are_a_syntaxerror() {
+        let objs: Vec<_> = Scanner::new("{ ] } 1 [ 2 } ] /x").collect();
+        assert_eq!(objs[0], Err("syntaxerror".to_string()));
+        assert_eq!(objs[3], Err("syntaxerror".to_string()));
+        // the tokens after each stray closer are scanned as usual
+        assert_eq!(objs[2], Ok(PSObject::Int(1)));
+        assert_eq!(objs.last(), Some(&Ok(parse_token("/x"))));
+        assert!(!needs_more_input("{ ] } 1 2"));
+    }
+}
@@ -1,40 +1,85 @@
 // import from PSObject so our functions can modify it
 use crate::interpreter::object::PSObject;
-use std::slice::Iter;
+use std::rc::Rc;
 
 // our stack will contain it's own stack of vector objects
-// allow for clones of this stack to happen (necessary for some types)
+// allow for clones of this stack to happen (necessary for some types). clones are copy on
+// write: they share the vector until one of them changes, and even then share the objects,
+// so taking a snapshot for $error or :undo costs nothing however big the operands are
 #[derive(Clone)]
 pub struct Stack {
     // holds ur actual stack
     // this post says linked list is always worse, so i use vec: https://www.reddit.com/r/rust/comments/qpmue5/question_should_i_use_a_vec_or_a_linkedlist/
-    stack: Vec<PSObject>,
+    // an object only gets copied when it's popped off a stack that shares it with a clone
+    stack: Rc<Vec<Rc<PSObject>>>,
+    // the stack as it was at the last mark: nothing below low has changed since, and above
+    // keeps what was over it then, top first. only what an operator changes or takes off
+    // is kept, so marking before each one costs next to nothing
+    low: usize,
+    above: Vec<Rc<PSObject>>,
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Stack {
     // constructor
     pub fn new() -> Self {
-        Stack { stack: Vec::new() }
+        Stack {
+            stack: Rc::new(Vec::new()),
+            low: 0,
+            above: Vec::new(),
+        }
+    }
+    // remember the stack as it is now, see marked
+    pub fn mark(&mut self) {
+        self.low = self.stack.len();
+        self.above.clear();
+    }
+    // the stack as it was at the last mark, whatever's been popped or pushed since
+    pub fn marked(&self) -> Stack {
+        let objects = self.stack[..self.low]
+            .iter()
+            .chain(self.above.iter().rev())
+            .cloned();
+        Stack {
+            stack: Rc::new(objects.collect()),
+            low: 0,
+            above: Vec::new(),
+        }
+    }
+    // the objects from i up are about to change, keep the ones the mark still needs
+    fn touch(&mut self, i: usize) {
+        if i < self.low {
+            self.above
+                .extend(self.stack[i..self.low].iter().rev().cloned());
+            self.low = i;
+        }
     }
 
     // allows iteration for the stack
-    pub fn iter(&self) -> Iter<'_, PSObject> {
-        return self.stack.iter();
+    pub fn iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = &PSObject> + ExactSizeIterator + Clone + '_ {
+        self.stack.iter().map(|o| &**o)
     }
     // helper function push to add objects into stack
     pub fn push(&mut self, val: PSObject) {
-        self.stack.push(val);
+        Rc::make_mut(&mut self.stack).push(Rc::new(val));
     }
     // helper function to see the top of stack
     // we have to borrow the object and not take ownership, so we reference it with &
     pub fn peek(&self) -> Option<&PSObject> {
         // since last returns an option, we don't have to worry about stack being empty it will
         // return none or some
-        return self.stack.last();
+        self.stack.last().map(|o| &**o)
     }
     // helper function to print all of stack
     pub fn print_all(&self) {
-        for n in &self.stack {
+        for n in self.iter() {
             println!("{:?}", n);
         }
     }
@@ -43,7 +88,11 @@ impl Stack {
     pub fn pop(&mut self) -> Option<PSObject> {
         // since pop will return an option (either Some<t> or none), we don't need the logic to
         // handle empty stacks
-        return self.stack.pop();
+        // one still shared, with a clone or the mark, is copied without recursing
+        self.touch(self.stack.len().saturating_sub(1));
+        Rc::make_mut(&mut self.stack)
+            .pop()
+            .map(|o| Rc::try_unwrap(o).unwrap_or_else(|o| o.deep_copy()))
     }
     // exch will be a function type that swaps the first two elements within the stack and returns
     // a bool (success indicator)
@@ -53,68 +102,107 @@ impl Stack {
             return false;
         }
         let i = self.stack.len();
+        self.touch(i - 2);
         // swap the first two elements in stack
-        self.stack.swap(i - 1, i - 2);
-        return true;
+        Rc::make_mut(&mut self.stack).swap(i - 1, i - 2);
+        true
     }
     // dup will duplicate the top of the stack and returns a bool (success indicator)
     pub fn dup(&mut self) -> bool {
-        // duplicate top value of stack
-        let v = self.peek().cloned();
-        // check if v is none
-        if v.is_none() {
+        // duplicate top value of stack, sharing it until one of the two is popped
+        let Some(v) = self.stack.last().cloned() else {
             return false;
-        }
-        // push in duplicated value
-        self.push(v.unwrap());
-        return true;
+        };
+        Rc::make_mut(&mut self.stack).push(v);
+        true
+    }
+    // index pushes the object n down, shared like dup's, [1,2,3] 2 index => [1,2,3,1]
+    pub fn index(&mut self, n: usize) -> bool {
+        let Some(v) = self
+            .stack
+            .len()
+            .checked_sub(n.saturating_add(1))
+            .map(|i| self.stack[i].clone())
+        else {
+            return false;
+        };
+        Rc::make_mut(&mut self.stack).push(v);
+        true
+    }
+    // over pushes the object under the top, [1,2] => [1,2,1]
+    pub fn over(&mut self) -> bool {
+        self.index(1)
+    }
+    // nip drops the object under the top, [1,2] => [2]
+    pub fn nip(&mut self) -> bool {
+        let Some(i) = self.stack.len().checked_sub(2) else {
+            return false;
+        };
+        self.touch(i);
+        Rc::make_mut(&mut self.stack).remove(i);
+        true
+    }
+    // tuck puts a copy of the top under the object below it, [1,2] => [2,1,2]
+    pub fn tuck(&mut self) -> bool {
+        let Some(i) = self.stack.len().checked_sub(2) else {
+            return false;
+        };
+        let top = self.stack[i + 1].clone();
+        self.touch(i);
+        Rc::make_mut(&mut self.stack).insert(i, top);
+        true
+    }
+    // ndup pushes n more of the top, shared like dup's, [1] 2 ndup => [1,1,1]
+    pub fn ndup(&mut self, n: i64) -> bool {
+        let Some(v) = self.stack.last().cloned().filter(|_| n >= 0) else {
+            return false;
+        };
+        Rc::make_mut(&mut self.stack).extend(std::iter::repeat_n(v, n as usize));
+        true
+    }
+    // room for n more objects, false when there's no memory for it
+    pub fn reserve(&mut self, n: usize) -> bool {
+        Rc::make_mut(&mut self.stack).try_reserve(n).is_ok()
     }
     // copy will take n integer operands as a parameter and create and set n elements in the
     // operand stack
     // they are pushed in the same order they originall appear,
     // i.e [1,2,3,4], 3 copy => [1,2,3,4,2,3,4]
-    pub fn copy(&mut self, mut n: i32) -> bool {
-        // make a copy of the stack
-        let mut cp_stack = self.clone();
+    pub fn copy(&mut self, n: i64) -> bool {
         // check if there are even n values in stack
-        if (self.count() < n) {
+        if n < 0 || self.count() < n {
             return false;
         }
         // get n copies
         // doesnt work since we would be borrowing the same object twice: let n_copies = &self.stack[self.count() as usize - n as usize..];
         // so first, get n count
         let n_index = self.count() as usize - n as usize;
-        let n_copies = &self.stack[n_index..].to_vec();
+        let n_copies: Vec<Rc<PSObject>> = self.stack[n_index..]
+            .iter()
+            .map(|o| Rc::new(o.deep_copy()))
+            .collect();
         // apend the copies to our stack
-        self.stack.extend_from_slice(&n_copies);
-        return true;
+        Rc::make_mut(&mut self.stack).extend(n_copies);
+        true
     }
     // clear will discard all elements of the stack
     pub fn clear(&mut self) {
-        while self.peek().is_some() {
-            self.pop();
-        }
+        // a fresh vector, rather than copying a shared one just to empty it
+        self.touch(0);
+        self.stack = Rc::new(Vec::new());
     }
     // count will count the elements of the stack and pushed as a new element (returning)
-    pub fn count(&mut self) -> i32 {
-        // clone a copy and iterate through that stack
-        // clone the entire stack, not just the vector object i.e not self.stack.clone, but
-        // self.clone
-        let mut cp_stack = self.clone();
-        let mut count = 0;
-        while cp_stack.peek().is_some() {
-            count += 1;
-            cp_stack.pop();
-        }
-        return count;
+    pub fn count(&self) -> i64 {
+        self.stack.len() as i64
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod test {
     use super::*;
-    use crate::object::PSObject;
-    use std::collections::HashMap;
+    use crate::interpreter::object::DictMap;
+    use proptest::prelude::*;
 
     #[test]
     fn test_push() {
@@ -146,12 +234,12 @@ mod test {
         println!("Actual value {:?}", s.peek());
 
         let temp = String::from("cool");
-        s.push(PSObject::String(temp));
+        s.push(PSObject::String(temp.into_bytes(), false));
 
         let val3 = s.peek().expect("Stack is empty, push failed!");
         /* check string */
         match val3 {
-            PSObject::String(n) => assert_eq!(*n, String::from("cool")),
+            PSObject::String(n, _) => assert_eq!(*n, b"cool"),
             _ => panic!("Top of stack was not of string value value!"),
         }
         /* check dict */
@@ -255,6 +343,35 @@ mod test {
         assert_eq!(s.count(), 1);
     }
 
+    #[test]
+    fn forth_words() {
+        let ints = |s: &Stack| -> Vec<PSObject> { s.iter().cloned().collect() };
+        let mut s = Stack::new();
+        s.push(PSObject::Int(1));
+        assert_eq!(s.over(), false);
+        assert_eq!(s.nip(), false);
+        assert_eq!(s.tuck(), false);
+        s.push(PSObject::Int(2));
+        assert!(s.over());
+        assert_eq!(ints(&s), [1, 2, 1].map(PSObject::Int));
+        assert!(s.nip());
+        assert_eq!(ints(&s), [1, 1].map(PSObject::Int));
+        s.push(PSObject::Int(3));
+        assert!(s.tuck());
+        assert_eq!(ints(&s), [1, 3, 1, 3].map(PSObject::Int));
+        assert!(s.index(3));
+        assert_eq!(ints(&s), [1, 3, 1, 3, 1].map(PSObject::Int));
+        assert_eq!(s.index(5), false);
+        s.pop();
+        assert!(s.ndup(2));
+        assert_eq!(ints(&s), [1, 3, 1, 3, 3, 3].map(PSObject::Int));
+        assert!(s.ndup(0));
+        assert_eq!(s.count(), 6);
+        assert_eq!(s.ndup(-1), false);
+        s.clear();
+        assert_eq!(s.ndup(1), false);
+    }
+
     #[test]
     fn pop_empty_does_not_panic() {
         let mut s = Stack::new();
@@ -288,7 +405,7 @@ mod test {
     fn mixed_type_count_clear() {
         let mut s = Stack::new();
         s.push(PSObject::Bool(true));
-        s.push(PSObject::String("hi".into()));
+        s.push(PSObject::String("hi".into(), false));
         s.push(PSObject::Array(vec![
             PSObject::Int(1),
             PSObject::Bool(false),
@@ -322,7 +439,7 @@ mod test {
     #[test]
     fn dict_and_array_peek() {
         let mut s = Stack::new();
-        let mut d = HashMap::new();
+        let mut d = DictMap::new();
         d.insert("x".into(), PSObject::Int(99));
         s.push(PSObject::Dict(d.clone()));
         s.push(PSObject::Array(vec![PSObject::Bool(true)]));
@@ -338,4 +455,108 @@ mod test {
             _ => panic!("Expected Dict"),
         }
     }
+
+    #[test]
+    fn clones_share_until_changed() {
+        let mut s = Stack::new();
+        s.push(PSObject::Array(vec![PSObject::Int(1); 1000]));
+        s.push(PSObject::Int(2));
+        let snapshot = s.clone();
+        assert!(Rc::ptr_eq(&s.stack, &snapshot.stack));
+        s.pop();
+        s.push(PSObject::Int(3));
+        // the vector was copied, the big array wasn't
+        assert!(!Rc::ptr_eq(&s.stack, &snapshot.stack));
+        assert!(Rc::ptr_eq(&s.stack[0], &snapshot.stack[0]));
+        assert_eq!(snapshot.peek(), Some(&PSObject::Int(2)));
+        s.clear();
+        assert_eq!(snapshot.count(), 2);
+    }
+
+    #[test]
+    fn marked_is_the_stack_at_the_mark() {
+        let ints = |s: &Stack| -> Vec<PSObject> { s.iter().cloned().collect() };
+        let mut s = Stack::new();
+        for i in 1..=4 {
+            s.push(PSObject::Int(i));
+        }
+        s.mark();
+        s.pop();
+        s.exch();
+        s.push(PSObject::Int(5));
+        assert!(s.nip());
+        assert_eq!(ints(&s), [1, 3, 5].map(PSObject::Int));
+        assert_eq!(ints(&s.marked()), [1, 2, 3, 4].map(PSObject::Int));
+        s.clear();
+        assert_eq!(ints(&s.marked()), [1, 2, 3, 4].map(PSObject::Int));
+        s.mark();
+        assert_eq!(s.marked().count(), 0);
+    }
+
+    // small stacks of mixed objects, arrays included
+    fn objects() -> impl Strategy<Value = Vec<PSObject>> {
+        let leaf = prop_oneof![
+            any::<i32>().prop_map(|n| PSObject::Int(n as i64)),
+            any::<bool>().prop_map(PSObject::Bool),
+            "[a-z]{0,4}".prop_map(|s: String| PSObject::String(s.into_bytes(), false)),
+        ];
+        let obj = leaf.prop_recursive(2, 8, 3, |inner| {
+            prop::collection::vec(inner, 0..3).prop_map(PSObject::Array)
+        });
+        prop::collection::vec(obj, 0..8)
+    }
+
+    fn stack_of(objs: &[PSObject]) -> Stack {
+        let mut s = Stack::new();
+        for o in objs {
+            s.push(o.clone());
+        }
+        s
+    }
+
+    proptest! {
+        #[test]
+        fn exch_twice_changes_nothing(objs in objects()) {
+            let mut s = stack_of(&objs);
+            let swapped = s.exch() && s.exch();
+            prop_assert_eq!(swapped, objs.len() >= 2);
+            prop_assert_eq!(s.iter().cloned().collect::<Vec<_>>(), objs);
+        }
+
+        #[test]
+        fn copy_adds_n_equal_objects(objs in objects(), n in 0i64..10) {
+            let mut s = stack_of(&objs);
+            let before = s.count();
+            if s.copy(n) {
+                prop_assert_eq!(s.count(), before + n);
+                let all: Vec<PSObject> = s.iter().cloned().collect();
+                let (bottom, copies) = all.split_at(before as usize);
+                prop_assert_eq!(&bottom[bottom.len() - n as usize..], copies);
+            } else {
+                prop_assert!(n > before);
+                prop_assert_eq!(s.count(), before);
+            }
+        }
+
+        #[test]
+        fn tuck_is_exch_over(objs in objects()) {
+            let mut tucked = stack_of(&objs);
+            let mut swapped = stack_of(&objs);
+            prop_assert_eq!(tucked.tuck(), swapped.exch() && swapped.over());
+            prop_assert_eq!(
+                tucked.iter().cloned().collect::<Vec<_>>(),
+                swapped.iter().cloned().collect::<Vec<_>>()
+            );
+        }
+
+        #[test]
+        fn dup_then_pop_changes_nothing(objs in objects()) {
+            let mut s = stack_of(&objs);
+            if s.dup() {
+                prop_assert_eq!(s.peek(), objs.last());
+                s.pop();
+            }
+            prop_assert_eq!(s.iter().cloned().collect::<Vec<_>>(), objs);
+        }
+    }
 }
@@ -0,0 +1,7 @@
+// the interpreter as a library, so other programs can use the dsc model, resources, etc.
+pub mod interpreter;
+
+// the C API, built as a library with
+// `cargo rustc --release --lib --features capi --crate-type cdylib`
+#[cfg(feature = "capi")]
+pub mod capi;
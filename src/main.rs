@@ -1,36 +1,369 @@
-mod interpreter;
+mod cli;
+mod dump;
+mod repl;
+mod style;
 
-use interpreter::interpreter::Interpreter;
-use interpreter::object::PSObject;
-use interpreter::stack::Stack;
+use cli::{Highlight, Mode, Options, Source};
+use ps_interpreter::interpreter::check::{self, Diagnostic};
+use ps_interpreter::interpreter::config::InterpreterConfig;
+use ps_interpreter::interpreter::dsc::DscDocument;
+use ps_interpreter::interpreter::eps::EpsInfo;
+use ps_interpreter::interpreter::error::PSError;
+use ps_interpreter::interpreter::format;
+use ps_interpreter::interpreter::highlight;
+use ps_interpreter::interpreter::interpreter::Interpreter;
+use ps_interpreter::interpreter::lint;
+use ps_interpreter::interpreter::pages;
+use ps_interpreter::interpreter::scanner::line_col;
+use style::Style;
 
 // bring in rust's io
-use std::io::{self, Write};
-
-fn main() {
-    // start our interpreter
-    let mut my_interpreter = Interpreter::new();
-    // basic loop to act as a repl
-    loop {
-        print!("ps> ");
-        io::stdout().flush().unwrap();
-        let mut buf = String::new();
-        if io::stdin().read_line(&mut buf).unwrap() == 0 {
-            break;
+use std::io::{self, IsTerminal, Read};
+use std::process::ExitCode;
+
+// what to call a source in messages
+fn source_name(source: &Source) -> &str {
+    match source {
+        Source::File(path) => path,
+        Source::Expr(_) => "-e",
+        Source::Stdin => "-",
+    }
+}
+
+// the error is the bare message, for style.message or the json error format
+fn read_source(source: &Source) -> Result<Vec<u8>, String> {
+    match source {
+        Source::Expr(code) => Ok(code.as_bytes().to_vec()),
+        Source::Stdin => {
+            let mut src = Vec::new();
+            io::stdin()
+                .read_to_end(&mut src)
+                .map_err(|e| format!("stdin: {}", e))?;
+            Ok(src)
         }
-        let line = buf.trim();
+        // read raw bytes, files may contain binary tokens
+        Source::File(path) => std::fs::read(path).map_err(|e| format!("{}: {}", path, e)),
+    }
+}
+
+// a source that couldn't be read, as --error-format asks for it
+fn unreadable(source: &Source, message: String, opts: &Options, style: Style) -> String {
+    if !opts.json_errors {
+        return style.message(&message);
+    }
+    let name = match source {
+        Source::File(_) => "undefinedfilename",
+        _ => "ioerror",
+    };
+    style::json_error(name, &message, source_name(source), None, None)
+}
 
-        if line.is_empty() {
-            continue;
+// what every interpreter made here gets before running anything
+fn prepare(interp: &mut Interpreter, opts: &Options) {
+    // optional directory findresource can load resources from
+    if let Ok(dir) = std::env::var("PS_RESOURCE_DIR") {
+        if let Err(e) = interp.set_resource_dir(&dir) {
+            eprintln!("Error: {}: {}", dir, e);
         }
+    }
+    if !opts.arguments.is_empty() {
+        interp.set_arguments(&opts.arguments);
+    }
+}
 
-        if line == "quit" {
-            break;
+// run everything given on the command line in order, stopping at the first error. the error
+// comes back empty when the program's own handleerror has reported it
+fn run_sources(
+    interp: &mut Interpreter,
+    opts: &Options,
+    style: Style,
+    config: &InterpreterConfig,
+) -> Result<(), String> {
+    let report = |e: PSError, source: &Source, src: Option<&str>| {
+        if e.handled {
+            String::new()
+        } else if opts.json_errors {
+            let at = src
+                .zip(e.position)
+                .filter(|(src, at)| *at <= src.len())
+                .map(|(src, at)| line_col(src.as_bytes(), at));
+            let depth = Some(e.stack.len());
+            style::json_error(&e.name, &e.summary(), source_name(source), at, depth)
+        } else {
+            style.error(&e, src)
+        }
+    };
+    // a range past the last page runs nothing from it, which is worth saying
+    let check_pages = |doc: &DscDocument, source: &Source| {
+        if opts
+            .pages
+            .as_ref()
+            .is_some_and(|p| p.beyond(doc.pages.len()))
+        {
+            eprintln!(
+                "{}: warning: --pages selects pages past the end ({} in all)",
+                source_name(source),
+                doc.pages.len()
+            );
+        }
+    };
+    for source in &opts.sources {
+        let src = read_source(source).map_err(|e| unreadable(source, e, opts, style))?;
+        // --jobs: the pages of a structured document on threads of their own. they don't
+        // share state with the interpreter here or with each other
+        if let (true, Source::File(_)) = (opts.jobs > 1, source) {
+            let text = String::from_utf8_lossy(&src);
+            let doc = DscDocument::parse(&text);
+            if doc.is_conforming() && !doc.pages.is_empty() {
+                check_pages(&doc, source);
+                let setup = |interp: &mut Interpreter| prepare(interp, opts);
+                let runs = pages::run_pages(
+                    &doc,
+                    opts.pages.as_ref(),
+                    config,
+                    opts.jobs,
+                    opts.skip_prolog_errors,
+                    &setup,
+                );
+                for run in runs {
+                    print!("{}", run.output);
+                    eprint!("{}", run.errors);
+                    run.result.map_err(|e| report(e, source, None))?;
+                }
+                continue;
+            }
         }
+        let result = match (&opts.pages, source) {
+            // keep only the selected pages (plus prolog and trailer), running the prolog on
+            // its own so --skip-prolog-errors can carry on past what fails in it
+            (pages, Source::File(_)) if pages.is_some() || opts.skip_prolog_errors => {
+                let text = String::from_utf8_lossy(&src);
+                let doc = DscDocument::parse(&text);
+                check_pages(&doc, source);
+                // without pages it's all prolog, and nothing should be skipped
+                let skip = opts.skip_prolog_errors && !doc.pages.is_empty();
+                pages::run_prolog(interp, doc.prolog_source(), skip)
+                    .and_then(|skipped| {
+                        for e in skipped {
+                            eprintln!("{}: prolog: {} (skipped)", source_name(source), e);
+                        }
+                        interp.run(&doc.body(pages.as_ref()))
+                    })
+                    // positions are in the prolog or the pages, not the file
+                    .map_err(|e| report(e, source, None))
+            }
+            _ => interp
+                .run_bytes(&src)
+                .map_err(|e| report(e, source, std::str::from_utf8(&src).ok())),
+        };
+        result?;
+    }
+    Ok(())
+}
+
+// --check and lint: report what `find` says about every source as file:line:col, without
+// running any of them. with --error-format json each problem is a line of JSON called name
+fn report_sources(
+    opts: &Options,
+    style: Style,
+    name: &str,
+    find: impl Fn(&str) -> Vec<Diagnostic>,
+) -> ExitCode {
+    let mut clean = true;
+    for source in &opts.sources {
+        let src = match read_source(source) {
+            Ok(src) => src,
+            Err(e) => {
+                eprintln!("{}", unreadable(source, e, opts, style));
+                clean = false;
+                continue;
+            }
+        };
+        for problem in find(&String::from_utf8_lossy(&src)) {
+            if opts.json_errors {
+                let at = Some((problem.line, problem.column));
+                let file = source_name(source);
+                println!(
+                    "{}",
+                    style::json_error(name, &problem.message, file, at, None)
+                );
+            } else {
+                println!("{}:{}", source_name(source), problem);
+            }
+            clean = false;
+        }
+    }
+    if clean {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
 
-        // run the code
-        my_interpreter.run(line);
+// --dump-tokens: the scanner's view of every source, nothing gets run
+fn dump_sources(opts: &Options, style: Style) -> ExitCode {
+    for source in &opts.sources {
+        let src = match read_source(source) {
+            Ok(src) => src,
+            Err(e) => {
+                eprintln!("{}", style.message(&e));
+                return ExitCode::FAILURE;
+            }
+        };
+        if opts.sources.len() > 1 {
+            println!("# {}", source_name(source));
+        }
+        for line in dump::dump_tokens(&src) {
+            println!("{}", line);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+// --highlight: print every source colored for a terminal or marked up for a web page
+fn highlight_sources(opts: &Options, style: Style, how: Highlight) -> ExitCode {
+    for source in &opts.sources {
+        let src = match read_source(source) {
+            Ok(src) => src,
+            Err(e) => {
+                eprintln!("{}", style.message(&e));
+                return ExitCode::FAILURE;
+            }
+        };
+        let src = String::from_utf8_lossy(&src);
+        match how {
+            Highlight::Ansi => print!("{}", highlight::ansi(&src)),
+            Highlight::Html => print!("{}", highlight::html(&src)),
+        }
     }
+    ExitCode::SUCCESS
+}
+
+// ps-interpreter fmt: print every source reformatted, or what stops it from being formatted
+fn format_sources(opts: &Options, style: Style) -> ExitCode {
+    let mut ok = true;
+    for source in &opts.sources {
+        let src = match read_source(source) {
+            Ok(src) => src,
+            Err(e) => {
+                eprintln!("{}", style.message(&e));
+                ok = false;
+                continue;
+            }
+        };
+        match format::format(&String::from_utf8_lossy(&src)) {
+            Ok(text) => print!("{}", text),
+            Err(problems) => {
+                for problem in problems {
+                    eprintln!("{}:{}", source_name(source), problem);
+                }
+                ok = false;
+            }
+        }
+    }
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+// ps-interpreter --eps-info file.eps prints the placement metadata of an EPS file
+fn print_eps_info(path: &str) -> Result<(), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let info = EpsInfo::from_bytes(&bytes)?;
+    let b = info.bounding_box;
+    println!("BoundingBox: {} {} {} {}", b.llx, b.lly, b.urx, b.ury);
+    if let Some(h) = info.hires_bounding_box {
+        println!("HiResBoundingBox: {} {} {} {}", h.llx, h.lly, h.urx, h.ury);
+    }
+    println!("Size: {} x {}", b.width(), b.height());
+    for (key, value) in [
+        ("Title", &info.title),
+        ("Creator", &info.creator),
+        ("CreationDate", &info.creation_date),
+    ] {
+        if let Some(v) = value {
+            println!("{}: {}", key, v);
+        }
+    }
+    if let Some(level) = info.language_level {
+        println!("LanguageLevel: {}", level);
+    }
+    println!("Preview: {}", if info.has_preview { "yes" } else { "no" });
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut opts = match Options::parse(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+    if let Some(path) = &opts.eps_info {
+        return match print_eps_info(path) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let style = Style::new(opts.color);
+    if opts.mode != Mode::Run {
+        if opts.sources.is_empty() {
+            opts.sources.push(Source::Stdin);
+        }
+        return match opts.mode {
+            Mode::Check => report_sources(&opts, style, "syntaxerror", check::check),
+            Mode::Lint => report_sources(&opts, style, "warning", lint::lint),
+            Mode::DumpTokens => dump_sources(&opts, style),
+            Mode::Fmt => format_sources(&opts, style),
+            Mode::Highlight(how) => highlight_sources(&opts, style, how),
+            Mode::Run => unreachable!(),
+        };
+    }
+    // start our interpreter, with our own operators since scripts run from here are ours too
+    let config = InterpreterConfig {
+        extensions: true,
+        deterministic: opts.deterministic,
+        real_digits: opts.real_digits,
+        ..InterpreterConfig::default()
+    };
+    let mut my_interpreter = Interpreter::with_config(config.clone());
+    prepare(&mut my_interpreter, &opts);
+
+    // `cat job.ps | ps-interpreter` runs the piped program rather than a repl on it
+    if opts.sources.is_empty() && !opts.interactive && !io::stdin().is_terminal() {
+        opts.sources.push(Source::Stdin);
+    }
+    if opts.explain {
+        my_interpreter.set_explainer(Some(Box::new(|step: &str| eprintln!("  {}", step))));
+    }
+    // executive in a program opens the same repl, then carries on with the program
+    let undo_limit = opts.undo_limit.unwrap_or(repl::UNDO_LIMIT);
+    my_interpreter.set_executive(move |interp| {
+        repl::run(interp, style, undo_limit).map_err(|e| e.to_string())
+    });
+    if let Err(e) = run_sources(&mut my_interpreter, &opts, style, &config) {
+        // errors go to stderr in either format, apart from what the program prints
+        if !e.is_empty() {
+            eprintln!("{}", e);
+        }
+        return ExitCode::FAILURE;
+    }
+    if opts.sources.is_empty() || opts.interactive {
+        if let Err(e) = repl::run(&mut my_interpreter, style, undo_limit) {
+            eprintln!("{}", style.message(&e.to_string()));
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+
     /* debug stack: create our stack
     let mut my_stack = Stack::new();
     */
@@ -0,0 +1,341 @@
+use crate::style::Style;
+use ps_interpreter::interpreter::interpreter::{Interpreter, Snapshot};
+use ps_interpreter::interpreter::operators;
+use ps_interpreter::interpreter::scanner::needs_more_input;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::collections::VecDeque;
+use std::io::{self, IsTerminal};
+
+// characters that end a name, so completion only looks at the name under the cursor
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "()<>[]{}/%".contains(c)
+}
+
+// where the name being typed starts and every known name it could be. a leading / is left
+// alone so /myp completes to /myproc
+fn complete(line: &str, pos: usize, names: &[String]) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| is_delimiter(c))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let prefix = &line[start..pos];
+    if prefix.is_empty() {
+        return (pos, Vec::new());
+    }
+    let matches = names
+        .iter()
+        .filter(|n| n.starts_with(prefix))
+        .cloned()
+        .collect();
+    (start, matches)
+}
+
+// how many lines :undo can take back unless --undo says otherwise
+pub const UNDO_LIMIT: usize = 100;
+
+const META_HELP: &str = "\
+:stack               show the operand stack, top first
+:dicts               show the dictionary stack, top first
+:clear               empty the operand stack
+:autostack [on|off]  show the stack after every line (on at start)
+:load FILE           run a postscript file
+:save FILE           write this session's input to FILE, :load it to get back here
+:dump FILE           write the stacks and dictionaries to FILE as JSON, for diffing
+:undo [N]            take back the last N lines (1 if not given)
+:help [OP]           this list, or what operator OP does";
+
+// colon commands that talk to the repl instead of the interpreter
+#[derive(Debug, PartialEq)]
+enum Meta<'a> {
+    Stack,
+    Dicts,
+    Clear,
+    // None flips it
+    AutoStack(Option<bool>),
+    Load(&'a str),
+    Save(&'a str),
+    Dump(&'a str),
+    Undo(usize),
+    Help(Option<&'a str>),
+}
+
+impl<'a> Meta<'a> {
+    // None for lines that are postscript rather than a meta-command
+    fn parse(line: &'a str) -> Option<Result<Meta<'a>, String>> {
+        let rest = line.strip_prefix(':')?;
+        let (cmd, arg) = match rest.split_once(char::is_whitespace) {
+            Some((cmd, arg)) => (cmd, Some(arg.trim()).filter(|a| !a.is_empty())),
+            None => (rest, None),
+        };
+        let needs_file = || arg.ok_or(format!(":{} needs a file name", cmd));
+        Some(match cmd {
+            "stack" => Ok(Meta::Stack),
+            "dicts" => Ok(Meta::Dicts),
+            "clear" => Ok(Meta::Clear),
+            "autostack" => match arg {
+                None => Ok(Meta::AutoStack(None)),
+                Some("on") => Ok(Meta::AutoStack(Some(true))),
+                Some("off") => Ok(Meta::AutoStack(Some(false))),
+                Some(other) => Err(format!(":autostack takes on or off, not {}", other)),
+            },
+            "load" => needs_file().map(Meta::Load),
+            "save" => needs_file().map(Meta::Save),
+            "dump" => needs_file().map(Meta::Dump),
+            "undo" => match arg {
+                None => Ok(Meta::Undo(1)),
+                Some(n) => n
+                    .parse()
+                    .map(Meta::Undo)
+                    .map_err(|_| format!(":undo takes a number of lines, not {}", n)),
+            },
+            "help" => Ok(Meta::Help(arg)),
+            _ => Err(format!("unknown command :{}, try :help", cmd)),
+        })
+    }
+
+    fn run(
+        &self,
+        interp: &mut Interpreter,
+        session: &mut Session,
+        style: Style,
+    ) -> Result<(), String> {
+        match self {
+            Meta::Stack => {
+                let operands: Vec<_> = interp.operands().collect();
+                for obj in operands.into_iter().rev() {
+                    println!("{}", style.object(obj));
+                }
+            }
+            Meta::Dicts => {
+                for (i, dict) in interp.dictionaries().iter().enumerate().rev() {
+                    println!("-- dict {} ({} entries)", i, dict.len());
+                    for (k, v) in dict {
+                        println!("  /{} {}", k, v);
+                    }
+                }
+            }
+            Meta::Clear => interp.run("clear").map_err(|e| e.to_string())?,
+            Meta::AutoStack(on) => session.auto_stack = on.unwrap_or(!session.auto_stack),
+            Meta::Load(path) => {
+                let src = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+                interp.run_bytes(&src).map_err(|e| e.to_string())?;
+                session
+                    .transcript
+                    .push(String::from_utf8_lossy(&src).into_owned());
+            }
+            Meta::Save(path) => {
+                let mut text = session.transcript.join("\n");
+                text.push('\n');
+                std::fs::write(path, text).map_err(|e| format!("{}: {}", path, e))?;
+            }
+            Meta::Dump(path) => {
+                std::fs::write(path, interp.dump_state()).map_err(|e| format!("{}: {}", path, e))?
+            }
+            Meta::Undo(n) => {
+                if *n > session.undo.len() {
+                    return Err(format!("can only undo {} lines", session.undo.len()));
+                }
+                let start = session.undo.len() - n;
+                // the oldest of the ones dropped is the state before all n lines
+                if let Some((snapshot, transcript)) = session.undo.drain(start..).next() {
+                    interp.restore(snapshot);
+                    session.transcript.truncate(transcript);
+                }
+            }
+            Meta::Help(None) => println!("{}", META_HELP),
+            Meta::Help(Some(name)) => {
+                let op = operators::info(name).ok_or(format!("no help for {}", name))?;
+                println!("{}\n  {}", op.usage, op.description);
+            }
+        }
+        Ok(())
+    }
+}
+
+// repl settings and history that outlive a single line
+struct Session {
+    // every piece of postscript run so far, for :save
+    transcript: Vec<String>,
+    // print the stack after each line
+    auto_stack: bool,
+    // the state before each of the last lines, with how long the transcript was then, for
+    // :undo. at most undo_limit of them are kept
+    undo: VecDeque<(Snapshot, usize)>,
+    undo_limit: usize,
+}
+
+impl Session {
+    fn remember(&mut self, interp: &Interpreter) {
+        if self.undo_limit == 0 {
+            return;
+        }
+        if self.undo.len() == self.undo_limit {
+            self.undo.pop_front();
+        }
+        self.undo
+            .push_back((interp.snapshot(), self.transcript.len()));
+    }
+}
+
+// tab completion over the names the interpreter knew at the last prompt
+#[derive(Default)]
+struct NameCompleter {
+    names: Vec<String>,
+}
+
+impl Completer for NameCompleter {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(line, pos, &self.names))
+    }
+}
+
+impl Hinter for NameCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for NameCompleter {}
+
+impl Validator for NameCompleter {}
+
+impl Helper for NameCompleter {}
+
+// basic loop to act as a repl. undo_limit is how many lines :undo can take back
+pub fn run(
+    my_interpreter: &mut Interpreter,
+    style: Style,
+    undo_limit: usize,
+) -> rustyline::Result<()> {
+    let mut editor: Editor<NameCompleter, _> = Editor::new()?;
+    editor.set_helper(Some(NameCompleter::default()));
+    // lines of a construct that isn't closed yet
+    let mut pending = String::new();
+    let mut session = Session {
+        transcript: Vec::new(),
+        auto_stack: true,
+        undo: VecDeque::new(),
+        undo_limit,
+    };
+    loop {
+        // refresh every prompt so names defined by the last line complete too
+        if let Some(helper) = editor.helper_mut() {
+            helper.names = my_interpreter
+                .known_names()
+                .into_iter()
+                .map(|n| n.name)
+                .collect();
+        }
+        // keep reading while a string, procedure or array is still open
+        // the prompt comes from the interpreter's prompt procedure, which programs can redefine
+        let prompt = if pending.is_empty() {
+            my_interpreter.prompt()
+        } else {
+            "...> ".to_string()
+        };
+        let buf = match editor.readline(&prompt) {
+            Ok(buf) => buf,
+            // ctrl-c drops the line (and anything unfinished), ctrl-d leaves
+            Err(ReadlineError::Interrupted) => {
+                pending.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        };
+        let line = buf.trim();
+        // `echo` copies input to stdout when it isn't already visible on a terminal
+        if my_interpreter.echo() && !io::stdin().is_terminal() {
+            println!("{}", line);
+        }
+
+        if !pending.is_empty() {
+            pending.push('\n');
+        } else if line.is_empty() {
+            continue;
+        } else if line == "quit" {
+            break;
+        } else if let Some(meta) = Meta::parse(line) {
+            // :load runs postscript too
+            if let Ok(Meta::Load(_)) = meta {
+                session.remember(my_interpreter);
+            }
+            if let Err(e) = meta.and_then(|m| m.run(my_interpreter, &mut session, style)) {
+                println!("{}", style.message(&e));
+            }
+            editor.add_history_entry(line)?;
+            continue;
+        }
+        pending.push_str(line);
+        if needs_more_input(&pending) {
+            continue;
+        }
+        let line = std::mem::take(&mut pending);
+        editor.add_history_entry(&line)?;
+
+        // run the code
+        session.remember(my_interpreter);
+        match my_interpreter.run(&line) {
+            Ok(()) => session.transcript.push(line),
+            // the program's handleerror has already shown it
+            Err(e) if e.handled => {}
+            Err(e) => println!("{}", style.error(&e, Some(&line))),
+        }
+        let operands: Vec<_> = my_interpreter.operands().collect();
+        if session.auto_stack && !operands.is_empty() {
+            println!("{}", style.stack(&operands));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn completes_the_name_under_the_cursor() {
+        let names: Vec<String> = ["add", "abs", "myproc", "dup"]
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        assert_eq!(
+            complete("1 2 a", 5, &names),
+            (4, vec!["add".into(), "abs".into()])
+        );
+        assert_eq!(complete("{ /myp", 6, &names), (3, vec!["myproc".into()]));
+        assert_eq!(complete("[1 du", 5, &names), (3, vec!["dup".into()]));
+        assert_eq!(complete("1 ", 2, &names), (2, vec![]));
+    }
+
+    #[test]
+    fn meta_commands() {
+        assert_eq!(Meta::parse("1 2 add"), None);
+        assert_eq!(Meta::parse(":stack"), Some(Ok(Meta::Stack)));
+        assert_eq!(Meta::parse(":load  a.ps "), Some(Ok(Meta::Load("a.ps"))));
+        assert_eq!(Meta::parse(":help"), Some(Ok(Meta::Help(None))));
+        assert_eq!(Meta::parse(":help add"), Some(Ok(Meta::Help(Some("add")))));
+        assert!(matches!(Meta::parse(":save"), Some(Err(_))));
+        assert_eq!(Meta::parse(":dump s.json"), Some(Ok(Meta::Dump("s.json"))));
+        assert_eq!(
+            Meta::parse(":autostack off"),
+            Some(Ok(Meta::AutoStack(Some(false))))
+        );
+        assert!(matches!(Meta::parse(":autostack maybe"), Some(Err(_))));
+        assert!(matches!(Meta::parse(":frob"), Some(Err(_))));
+        assert_eq!(Meta::parse(":undo"), Some(Ok(Meta::Undo(1))));
+        assert_eq!(Meta::parse(":undo 3"), Some(Ok(Meta::Undo(3))));
+        assert!(matches!(Meta::parse(":undo all"), Some(Err(_))));
+    }
+}
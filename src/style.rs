@@ -0,0 +1,255 @@
+use ps_interpreter::interpreter::error::PSError;
+use ps_interpreter::interpreter::json::quote;
+use ps_interpreter::interpreter::object::{PSObject, MAX_PRINT_DEPTH};
+use ps_interpreter::interpreter::scanner::Scanner;
+use std::io::IsTerminal;
+
+// composites wider than this get one element per line
+const WIDTH: usize = 60;
+
+// --color auto|always|never
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn parse(s: &str) -> Result<ColorChoice, String> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("--color takes auto, always or never, not {}", s)),
+        }
+    }
+}
+
+// how the cli and repl show objects and errors
+#[derive(Debug, Clone, Copy)]
+pub struct Style {
+    pub color: bool,
+}
+
+impl Style {
+    // auto means terminals for both output and errors and no NO_COLOR (https://no-color.org),
+    // an explicit flag wins
+    pub fn new(choice: ColorChoice) -> Style {
+        let color = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::io::stdout().is_terminal()
+                    && std::io::stderr().is_terminal()
+            }
+        };
+        Style { color }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    // an object in == syntax, colored by type and spread over lines when it's too wide
+    pub fn object(&self, obj: &PSObject) -> String {
+        self.nested(obj, MAX_PRINT_DEPTH)
+    }
+
+    // with at most `depth` more levels of composites, like the plain printer
+    fn nested(&self, obj: &PSObject, depth: usize) -> String {
+        match obj {
+            PSObject::Array(_)
+            | PSObject::PackedArray(_)
+            | PSObject::Dict(_)
+            | PSObject::Procedure { .. }
+                if depth == 0 =>
+            {
+                "...".to_string()
+            }
+            PSObject::Int(_) | PSObject::Real(_) => self.paint("33", &obj.to_string()),
+            PSObject::String(..) => self.paint("32", &obj.to_string()),
+            PSObject::Bool(_) => self.paint("34", &obj.to_string()),
+            PSObject::Name(_, false) => self.paint("35", &obj.to_string()),
+            PSObject::Array(items) | PSObject::PackedArray(items) => {
+                self.composite("[", "]", items.iter().map(|o| self.nested(o, depth - 1)))
+            }
+            PSObject::Procedure { code, .. } => {
+                self.composite("{", "}", code.iter().map(|o| self.nested(o, depth - 1)))
+            }
+            PSObject::Dict(d) => {
+                // in definition order, same as ==
+                let entries = d.iter().map(|(k, v)| {
+                    format!(
+                        "{} {}",
+                        self.paint("35", &format!("/{}", k)),
+                        self.nested(v, depth - 1)
+                    )
+                });
+                self.composite("<<", ">>", entries)
+            }
+            _ => obj.to_string(),
+        }
+    }
+
+    fn composite(&self, open: &str, close: &str, items: impl Iterator<Item = String>) -> String {
+        let items: Vec<String> = items.collect();
+        let flat = format!("{}{}{}", open, items.join(" "), close);
+        if visible_len(&flat) <= WIDTH && !flat.contains('\n') {
+            return flat;
+        }
+        let mut out = open.to_string();
+        for item in &items {
+            for line in item.lines() {
+                out.push_str("\n  ");
+                out.push_str(line);
+            }
+        }
+        out.push('\n');
+        out.push_str(close);
+        out
+    }
+
+    // the stack line the repl prints after each input, like `<3> 1 2 (abc)`
+    pub fn stack(&self, operands: &[&PSObject]) -> String {
+        let items: Vec<String> = operands.iter().map(|o| self.object(o)).collect();
+        format!("<{}> {}", operands.len(), items.join(" "))
+    }
+
+    pub fn message(&self, msg: &str) -> String {
+        self.paint("1;31", &format!("Error: {}", msg))
+    }
+
+    // the error trailer with its first line in red, and the source line with the failing
+    // token underlined after it when we know where in src it happened
+    pub fn error(&self, err: &PSError, src: Option<&str>) -> String {
+        let mut out = self.message(&err.summary());
+        out.push_str(&self.excerpt(err, src));
+        out.push('\n');
+        out.push_str(&err.stacks());
+        out
+    }
+
+    // the source line and an underline, starting with a newline, or nothing
+    fn excerpt(&self, err: &PSError, src: Option<&str>) -> String {
+        let mut out = String::new();
+        let (Some(src), Some(pos)) = (src, err.position) else {
+            return out;
+        };
+        if !src.is_char_boundary(pos) {
+            return out;
+        }
+        let start = src[..pos].rfind('\n').map_or(0, |i| i + 1);
+        let end = src[pos..].find('\n').map_or(src.len(), |i| pos + i);
+        let token = Scanner::new(&src[pos..end]).next_raw().unwrap_or_default();
+        let col = src[start..pos].chars().count();
+        out.push('\n');
+        out.push_str(&src[start..end]);
+        out.push('\n');
+        out.push_str(&" ".repeat(col));
+        out.push_str(&self.paint("31", &"^".repeat(token.chars().count().max(1))));
+        out
+    }
+}
+
+// one error as a line of JSON, for --error-format json: its name, the message a person
+// would get, the file, the 1-based line and column when they're known and how many operands
+// were on the stack
+pub fn json_error(
+    name: &str,
+    message: &str,
+    file: &str,
+    at: Option<(usize, usize)>,
+    depth: Option<usize>,
+) -> String {
+    let number = |n: Option<usize>| n.map_or("null".to_string(), |n| n.to_string());
+    format!(
+        "{{\"name\":{},\"message\":{},\"file\":{},\"line\":{},\"column\":{},\"depth\":{}}}",
+        quote(name),
+        quote(message),
+        quote(file),
+        number(at.map(|(line, _)| line)),
+        number(at.map(|(_, column)| column)),
+        number(depth)
+    )
+}
+
+// length on screen, leaving out color escapes
+fn visible_len(s: &str) -> usize {
+    let mut len = 0;
+    let mut in_escape = false;
+    for c in s.chars() {
+        match c {
+            '\x1b' => in_escape = true,
+            'm' if in_escape => in_escape = false,
+            _ if in_escape => {}
+            _ => len += 1,
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PLAIN: Style = Style { color: false };
+
+    #[test]
+    fn wide_composites_break_into_lines() {
+        let short = PSObject::Array(vec![PSObject::Int(1), PSObject::Name("a".into(), false)]);
+        assert_eq!(PLAIN.object(&short), "[1 /a]");
+        let long = PSObject::Array(vec![PSObject::String(b"x".repeat(40), false); 2]);
+        let text = PLAIN.object(&long);
+        assert_eq!(text.lines().count(), 4);
+        assert!(text.lines().nth(1).unwrap().starts_with("  ("));
+        let colored = Style { color: true }.object(&short);
+        assert_eq!(visible_len(&colored), 6);
+    }
+
+    #[test]
+    fn errors_underline_the_token() {
+        let err = PSError {
+            name: "typecheck".into(),
+            command: Some("add".into()),
+            position: Some(10),
+            stack: vec!["3".into()],
+            calls: vec![],
+            dicts: vec![],
+            handled: false,
+        };
+        assert_eq!(
+            PLAIN.error(&err, Some("1 2 add\n1 (x) add")),
+            "Error: /typecheck in --add--\n1 (x) add\n  ^^^\nOperand stack:\n   3\nExecution stack:\n\nDictionary stack:\n"
+        );
+        assert!(PLAIN
+            .error(&err, None)
+            .starts_with("Error: /typecheck in --add--\nOperand stack:"));
+        assert_eq!(
+            json_error("typecheck", &err.summary(), "a.ps", Some((2, 3)), Some(1)),
+            r#"{"name":"typecheck","message":"/typecheck in --add--","file":"a.ps","line":2,"column":3,"depth":1}"#
+        );
+        assert!(json_error("ioerror", "stdin: gone", "-", None, None)
+            .ends_with(r#""line":null,"column":null,"depth":null}"#));
+    }
+
+    #[test]
+    fn deep_nesting_is_cut_short() {
+        let mut nested = PSObject::Int(1);
+        for _ in 0..1000 {
+            nested = PSObject::Procedure {
+                code: vec![nested],
+                env: None,
+            };
+        }
+        let text = PLAIN.object(&nested);
+        assert!(text.contains("..."));
+        assert!(!text.contains('1'));
+    }
+}